@@ -1,4 +1,39 @@
 extern crate lalrpop;
+
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
 fn main() {
     lalrpop::process_root().unwrap();
+    build_runtime();
+}
+
+/*
+ * Compile src/runtime.c into a static library in OUT_DIR, which the compiler
+ * embeds and links every Cat program against. No rerun directives are printed
+ * since they would stop cargo rerunning this script for the grammar.
+ */
+fn build_runtime() {
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let object = Path::new(&out_dir).join("runtime.o");
+    let library = Path::new(&out_dir).join("libcatrt.a");
+
+    let compiler = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = Command::new(&compiler)
+        .args(["-c", "-O2", "-fPIC", "src/runtime.c", "-o"])
+        .arg(&object)
+        .status()
+        .unwrap_or_else(|error| panic!("could not run `{}`: {}", compiler, error));
+    assert!(status.success(), "compiling the runtime failed");
+
+    let archiver = env::var("AR").unwrap_or_else(|_| "ar".to_string());
+    let _ = std::fs::remove_file(&library);
+    let status = Command::new(&archiver)
+        .arg("crs")
+        .arg(&library)
+        .arg(&object)
+        .status()
+        .unwrap_or_else(|error| panic!("could not run `{}`: {}", archiver, error));
+    assert!(status.success(), "archiving the runtime failed");
 }
@@ -12,9 +12,17 @@ use crate::x64::{
     X64opCode,
 };
 
+use crate::target::Target;
+
 use crate::x64s::{SOperand, SOperands, X64SAssembly, X64SFunction, X64SInstruction, X64SProgram};
 
-use std::collections::{HashMap, HashSet};
+use crate::control_flow_graph::{
+    construct_control_flow_graph_x64s, difference, liveness, union, union_all, Node,
+};
+
+use petgraph::graph::NodeIndex;
+
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::convert::TryInto;
 
 #[derive(Debug)]
@@ -22,10 +30,32 @@ pub struct GlobalInfo {
     pub label_gen: LabelGenerator,
     pub symbol_gen: SymbolGenerator,
     pub string_literals: HashMap<Label, String>,
+    pub options: BackendOptions,
+}
+
+/*
+ * Options that change the code the backend generates
+ *
+ * trap_overflow: follow every add, sub, imulq and neg with a jo to the
+ * runtime's integer_overflow, which exits with an error, rather than letting
+ * the result wrap around.
+ *
+ * target: the platform the assembly is written for.
+ */
+#[derive(Debug, Copy, Clone, Default)]
+pub struct BackendOptions {
+    pub trap_overflow: bool,
+    pub target: Target,
 }
 
 const QUADWORD_SIZE: i64 = 8;
 
+/*
+ * The words of a shadow stack frame record: the record of the frame below
+ * and the number of pointer slots above this one.
+ */
+const SHADOW_FRAME_RECORD_SIZE: i64 = 2;
+
 /*
  * Compile
  *
@@ -38,6 +68,8 @@ const QUADWORD_SIZE: i64 = 8;
  *          label generator that generates new, valid labels for the program
  *      symbol_gen:
  *          symbol generator that generates new, valid symbols for the program
+ *      options:
+ *          options that change the generated code
  * Output:
  *      the compiled program
  */
@@ -45,16 +77,19 @@ pub fn compile(
     lir_program: LIRProgram,
     label_gen: LabelGenerator,
     symbol_gen: SymbolGenerator,
+    options: BackendOptions,
 ) -> X64Program {
     let mut state = GlobalInfo {
         label_gen,
         symbol_gen,
         string_literals: HashMap::new(),
+        options,
     };
 
     let selected_program = select(lir_program, &mut state);
     let single_memory_op = fix_up(selected_program);
-    let assigned_to_stack = assign_homes(single_memory_op);
+    let mut assigned_to_stack = assign_homes(single_memory_op);
+    assigned_to_stack.target = options.target;
 
     assigned_to_stack
 }
@@ -101,81 +136,45 @@ fn select_fn(
     string_literals: &mut HashMap<Label, String>,
     state: &mut GlobalInfo,
 ) -> X64SFunction {
-    let mut selected_function = X64SFunction { body: Vec::new() };
+    let mut selected_function = X64SFunction {
+        body: Vec::new(),
+        pointers: function.pointers.clone(),
+    };
 
     // Move registers into parameters
-    for (pos, arg) in function.arguments.iter().enumerate() {
-        match pos {
-            0 => {
-                selected_function
-                    .body
-                    .push(X64SAssembly::Instruction(X64SInstruction {
-                        op_code: X64opCode::Movq,
-                        args: SOperands::Two(
-                            SOperand::Register(X64Register::Rdi),
-                            SOperand::Symbol(*arg),
-                        ),
-                    }));
-            }
-            1 => {
-                selected_function
-                    .body
-                    .push(X64SAssembly::Instruction(X64SInstruction {
-                        op_code: X64opCode::Movq,
-                        args: SOperands::Two(
-                            SOperand::Register(X64Register::Rsi),
-                            SOperand::Symbol(*arg),
-                        ),
-                    }));
-            }
-            2 => {
-                selected_function
-                    .body
-                    .push(X64SAssembly::Instruction(X64SInstruction {
-                        op_code: X64opCode::Movq,
-                        args: SOperands::Two(
-                            SOperand::Register(X64Register::Rdx),
-                            SOperand::Symbol(*arg),
-                        ),
-                    }));
-            }
-            3 => {
-                selected_function
-                    .body
-                    .push(X64SAssembly::Instruction(X64SInstruction {
-                        op_code: X64opCode::Movq,
-                        args: SOperands::Two(
-                            SOperand::Register(X64Register::Rcx),
-                            SOperand::Symbol(*arg),
-                        ),
-                    }));
-            }
-            4 => {
-                selected_function
-                    .body
-                    .push(X64SAssembly::Instruction(X64SInstruction {
-                        op_code: X64opCode::Movq,
-                        args: SOperands::Two(
-                            SOperand::Register(X64Register::R8),
-                            SOperand::Symbol(*arg),
-                        ),
-                    }));
-            }
-            5 => {
-                selected_function
-                    .body
-                    .push(X64SAssembly::Instruction(X64SInstruction {
-                        op_code: X64opCode::Movq,
-                        args: SOperands::Two(
-                            SOperand::Register(X64Register::R9),
-                            SOperand::Symbol(*arg),
-                        ),
-                    }));
-            }
-            _ => {
-                panic!("Unexpected number of arguments");
-            }
-        };
+    for (arg, register) in function.arguments.iter().zip(ARGUMENT_REGISTERS.iter()) {
+        selected_function
+            .body
+            .push(X64SAssembly::Instruction(X64SInstruction {
+                op_code: X64opCode::Movq,
+                args: SOperands::Two(SOperand::Register(*register), SOperand::Symbol(*arg)),
+            }));
+    }
+
+    // Move the remaining parameters off the stack, the seventh argument is
+    // just above the return address and saved %rbp at 16(%rbp).
+    for (pos, arg) in function
+        .arguments
+        .iter()
+        .skip(ARGUMENT_REGISTERS.len())
+        .enumerate()
+    {
+        let offset = 2 * QUADWORD_SIZE + QUADWORD_SIZE * pos as i64;
+        selected_function
+            .body
+            .push(X64SAssembly::Instruction(X64SInstruction {
+                op_code: X64opCode::Movq,
+                args: SOperands::Two(
+                    SOperand::MemoryOffset(X64Value::Absolute(offset), X64Register::Rbp),
+                    SOperand::Register(X64Register::Rax),
+                ),
+            }));
+        selected_function
+            .body
+            .push(X64SAssembly::Instruction(X64SInstruction {
+                op_code: X64opCode::Movq,
+                args: SOperands::Two(SOperand::Register(X64Register::Rax), SOperand::Symbol(*arg)),
+            }));
     }
 
     // Convert LIRAssembly into X64SAssembly
@@ -392,6 +391,7 @@ fn select_fn(
                                 op_code: X64opCode::Neg,
                                 args: SOperands::One(SOperand::Symbol(*assign_to)),
                             }));
+                        select_overflow_check(&mut selected_function, state);
                     }
                     LIRInstruction::BinaryOp {
                         assign_to,
@@ -428,6 +428,7 @@ fn select_fn(
                                         args: SOperands::One(SOperand::Symbol(*right)),
                                     },
                                 ));
+                                select_overflow_check(&mut selected_function, state);
 
                                 selected_function.body.push(X64SAssembly::Instruction(
                                     X64SInstruction {
@@ -439,7 +440,7 @@ fn select_fn(
                                     },
                                 ));
                             }
-                            InfixOp::Divide => {
+                            InfixOp::Divide | InfixOp::Modulo => {
                                 selected_function.body.push(X64SAssembly::Instruction(
                                     X64SInstruction {
                                         op_code: X64opCode::Movq,
@@ -450,6 +451,14 @@ fn select_fn(
                                     },
                                 ));
 
+                                // idivq divides %rdx:%rax, so sign extend the dividend
+                                selected_function.body.push(X64SAssembly::Instruction(
+                                    X64SInstruction {
+                                        op_code: X64opCode::Cqto,
+                                        args: SOperands::Zero,
+                                    },
+                                ));
+
                                 selected_function.body.push(X64SAssembly::Instruction(
                                     X64SInstruction {
                                         op_code: X64opCode::IDivq,
@@ -457,11 +466,17 @@ fn select_fn(
                                     },
                                 ));
 
+                                // The quotient is left in %rax and the remainder in %rdx
+                                let result = match op {
+                                    InfixOp::Divide => X64Register::Rax,
+                                    _ => X64Register::Rdx,
+                                };
+
                                 selected_function.body.push(X64SAssembly::Instruction(
                                     X64SInstruction {
                                         op_code: X64opCode::Movq,
                                         args: SOperands::Two(
-                                            SOperand::Register(X64Register::Rax),
+                                            SOperand::Register(result),
                                             SOperand::Symbol(*assign_to),
                                         ),
                                     },
@@ -487,6 +502,7 @@ fn select_fn(
                                         ),
                                     },
                                 ));
+                                select_overflow_check(&mut selected_function, state);
                             }
                             InfixOp::Subtract => {
                                 selected_function.body.push(X64SAssembly::Instruction(
@@ -508,6 +524,7 @@ fn select_fn(
                                         ),
                                     },
                                 ));
+                                select_overflow_check(&mut selected_function, state);
                             }
                             InfixOp::And => {
                                 selected_function.body.push(X64SAssembly::Instruction(
@@ -558,78 +575,50 @@ fn select_fn(
                         function_name,
                         args,
                     } => {
-                        for (pos, arg) in args.iter().enumerate() {
-                            match pos {
-                                0 => {
-                                    selected_function.body.push(X64SAssembly::Instruction(
-                                        X64SInstruction {
-                                            op_code: X64opCode::Movq,
-                                            args: SOperands::Two(
-                                                SOperand::Symbol(*arg),
-                                                SOperand::Register(X64Register::Rdi),
-                                            ),
-                                        },
-                                    ));
-                                }
-                                1 => {
-                                    selected_function.body.push(X64SAssembly::Instruction(
-                                        X64SInstruction {
-                                            op_code: X64opCode::Movq,
-                                            args: SOperands::Two(
-                                                SOperand::Symbol(*arg),
-                                                SOperand::Register(X64Register::Rsi),
-                                            ),
-                                        },
-                                    ));
-                                }
-                                2 => {
-                                    selected_function.body.push(X64SAssembly::Instruction(
-                                        X64SInstruction {
-                                            op_code: X64opCode::Movq,
-                                            args: SOperands::Two(
-                                                SOperand::Symbol(*arg),
-                                                SOperand::Register(X64Register::Rdx),
-                                            ),
-                                        },
-                                    ));
-                                }
-                                3 => {
-                                    selected_function.body.push(X64SAssembly::Instruction(
-                                        X64SInstruction {
-                                            op_code: X64opCode::Movq,
-                                            args: SOperands::Two(
-                                                SOperand::Symbol(*arg),
-                                                SOperand::Register(X64Register::Rcx),
-                                            ),
-                                        },
-                                    ));
-                                }
-                                4 => {
-                                    selected_function.body.push(X64SAssembly::Instruction(
-                                        X64SInstruction {
-                                            op_code: X64opCode::Movq,
-                                            args: SOperands::Two(
-                                                SOperand::Symbol(*arg),
-                                                SOperand::Register(X64Register::R8),
-                                            ),
-                                        },
-                                    ));
-                                }
-                                5 => {
-                                    selected_function.body.push(X64SAssembly::Instruction(
-                                        X64SInstruction {
-                                            op_code: X64opCode::Movq,
-                                            args: SOperands::Two(
-                                                SOperand::Symbol(*arg),
-                                                SOperand::Register(X64Register::R9),
-                                            ),
-                                        },
-                                    ));
-                                }
-                                _ => {
-                                    panic!("Unexpected number of args");
-                                }
-                            };
+                        // Arguments past the sixth are pushed right to left, with
+                        // padding first if needed so %rsp is 16 byte aligned at
+                        // the call.
+                        let stack_args: Vec<&Symbol> =
+                            args.iter().skip(ARGUMENT_REGISTERS.len()).collect();
+                        let padding = if stack_args.len().is_multiple_of(2) {
+                            0
+                        } else {
+                            QUADWORD_SIZE
+                        };
+                        let stack_args_size = padding + QUADWORD_SIZE * stack_args.len() as i64;
+
+                        if padding != 0 {
+                            selected_function.body.push(X64SAssembly::Instruction(
+                                X64SInstruction {
+                                    op_code: X64opCode::Sub,
+                                    args: SOperands::Two(
+                                        SOperand::Immediate(X64Value::Absolute(padding)),
+                                        SOperand::Register(X64Register::Rsp),
+                                    ),
+                                },
+                            ));
+                        }
+                        for arg in stack_args.iter().rev() {
+                            selected_function.body.push(X64SAssembly::Instruction(
+                                X64SInstruction {
+                                    op_code: X64opCode::Push,
+                                    args: SOperands::One(SOperand::Symbol(**arg)),
+                                },
+                            ));
+                        }
+
+                        // The first six arguments go in registers, moved last so
+                        // nothing is computed between them and the call.
+                        for (arg, register) in args.iter().zip(ARGUMENT_REGISTERS.iter()) {
+                            selected_function.body.push(X64SAssembly::Instruction(
+                                X64SInstruction {
+                                    op_code: X64opCode::Movq,
+                                    args: SOperands::Two(
+                                        SOperand::Symbol(*arg),
+                                        SOperand::Register(*register),
+                                    ),
+                                },
+                            ));
                         }
 
                         selected_function
@@ -641,6 +630,18 @@ fn select_fn(
                                 ))),
                             }));
 
+                        if stack_args_size != 0 {
+                            selected_function.body.push(X64SAssembly::Instruction(
+                                X64SInstruction {
+                                    op_code: X64opCode::Add,
+                                    args: SOperands::Two(
+                                        SOperand::Immediate(X64Value::Absolute(stack_args_size)),
+                                        SOperand::Register(X64Register::Rsp),
+                                    ),
+                                },
+                            ));
+                        }
+
                         selected_function
                             .body
                             .push(X64SAssembly::Instruction(X64SInstruction {
@@ -754,6 +755,27 @@ fn select_fn(
     return selected_function;
 }
 
+/*
+ * Overflow Check
+ *
+ * With overflow trapping on, jump to the runtime's integer_overflow when the
+ * arithmetic instruction just selected overflowed. fix_up only adds movq's
+ * after it, which leave the flags alone. integer_overflow never returns, so
+ * a jump is enough and the function's control flow is unchanged.
+ */
+fn select_overflow_check(selected_function: &mut X64SFunction, state: &GlobalInfo) {
+    if state.options.trap_overflow {
+        selected_function
+            .body
+            .push(X64SAssembly::Instruction(X64SInstruction {
+                op_code: X64opCode::Jo,
+                args: SOperands::One(SOperand::MemoryImm(X64Value::LabelRef(
+                    Label::IntegerOverflow,
+                ))),
+            }));
+    }
+}
+
 /*
  * Fix Up
  *
@@ -762,7 +784,7 @@ fn select_fn(
  *      instruction.
  * Output:
  *      X64SProgram with at most one potential memory op (symbol) per
- *      instruction.
+ *      instruction, except for movq's between two symbols.
  */
 pub fn fix_up(program: X64SProgram) -> X64SProgram {
     let mut fixed_program = X64SProgram {
@@ -788,15 +810,25 @@ pub fn fix_up(program: X64SProgram) -> X64SProgram {
  *      instruction.
  * Output:
  *      X64SFunction with at most one potential memory op (symbol) per
- *      instruction.
+ *      instruction, except for movq's between two symbols.
  */
 fn fix_up_fn(function: &X64SFunction) -> X64SFunction {
-    let mut fixed_function = X64SFunction { body: Vec::new() };
+    let mut fixed_function = X64SFunction {
+        body: Vec::new(),
+        pointers: function.pointers.clone(),
+    };
 
     for assembly in &function.body {
         match assembly {
             X64SAssembly::Instruction(instruction) => {
                 match instruction.args {
+                    // movq 'x', 'y' is left for assign_homes_fn, since the
+                    // register allocator may give 'x' and 'y' the same home
+                    SOperands::Two(SOperand::Symbol(_), SOperand::Symbol(_))
+                        if matches!(instruction.op_code, X64opCode::Movq) =>
+                    {
+                        fixed_function.body.push(assembly.clone())
+                    }
                     // op 'x', 'y'
                     SOperands::Two(SOperand::Symbol(x), SOperand::Symbol(y)) => {
                         // mov 'x' into %rax
@@ -1005,42 +1037,64 @@ fn fix_up_fn(function: &X64SFunction) -> X64SFunction {
  *
  * Input:
  *      X64SProgram with at most one potential memory op (symbol) per
- *      instruction.
+ *      instruction, except for movq's between two symbols.
  * Output:
  *      X64Program ready to run.
  */
 pub fn assign_homes(program: X64SProgram) -> X64Program {
     let main_allocation = register_alloc(&program.main_function);
-    let main_assignment = register_assignment(main_allocation);
+    let main_assignment = register_assignment(main_allocation, &program.main_function.pointers);
 
     let mut compiled_program = X64Program {
         main_function: assign_homes_fn(program.main_function, main_assignment),
         other_functions: HashMap::new(),
         string_literals: program.string_literals,
+        target: Target::default(),
     };
 
     for (label, function) in program.other_functions.iter() {
         let function_allocation = register_alloc(function);
-        let function_assignment = register_assignment(function_allocation);
-        let compiled_function = assign_homes_fn(function.clone(), function_assignment);
+        let function_assignment = register_assignment(function_allocation, &function.pointers);
+        let mut compiled_function = assign_homes_fn(function.clone(), function_assignment);
+        remove_self_moves(&mut compiled_function);
 
         compiled_program
             .other_functions
             .insert(*label, compiled_function);
     }
+    remove_self_moves(&mut compiled_program.main_function);
 
     compiled_program
 }
 
+/*
+ * Remove Self Moves
+ *
+ * Copies between symbols that don't interfere can be given the same
+ * register, which leaves moves like movq %rax, %rax that do nothing.
+ */
+fn remove_self_moves(function: &mut X64Function) {
+    function.instruction_listing.retain(|assembly| {
+        !matches!(
+            assembly,
+            X64Assembly::Instruction(X64Instruction {
+                op_code: X64opCode::Movq,
+                args: Operands::Two(Operand::Register(source), Operand::Register(destination)),
+            }) if source == destination
+        )
+    });
+}
+
 /*
  * Assign Homes Function
  *
  * Input:
  *      X64SFunction with at most one potential memory op (symbol) per
- *      instruction.
+ *      instruction, except for movq's between two symbols.
  * Output:
  *      X64Function with prologue and epilogue, symbols replaced with
- *      stack offsets.
+ *      stack offsets. A movq between symbols with the same home is left
+ *      out, and one between two stack homes goes through %rax.
  */
 fn assign_homes_fn(function: X64SFunction, homes: HashMap<Symbol, StackOrReg>) -> X64Function {
     let mut compiled_function = X64Function {
@@ -1064,9 +1118,31 @@ fn assign_homes_fn(function: X64SFunction, homes: HashMap<Symbol, StackOrReg>) -
             ),
         }));
 
-    // Ensure allocation is multiple of 16
-    let homes_len: i64 = homes.len().try_into().unwrap();
-    let reservations: i64 = if homes.len() % 2 == 0 {
+    // Callee saved registers this function writes to, which must be given
+    // back to the caller unchanged.
+    let clobbered = clobbered_registers(&homes);
+
+    // Pointers on the stack, which register_assignment put in the slots
+    // directly above the shadow stack frame record.
+    let stack_pointers = homes
+        .iter()
+        .filter(|(symbol, home)| {
+            function.pointers.contains(symbol) && matches!(home, StackOrReg::Stack(_))
+        })
+        .count() as i64;
+    let frame_record = -(stack_pointers + SHADOW_FRAME_RECORD_SIZE) * QUADWORD_SIZE;
+
+    // Ensure allocation is multiple of 16, counting the callee saved
+    // registers pushed below the stack homes.
+    let mut stack_homes = homes
+        .values()
+        .filter(|home| matches!(home, StackOrReg::Stack(_)))
+        .count();
+    if stack_pointers > 0 {
+        stack_homes += SHADOW_FRAME_RECORD_SIZE as usize;
+    }
+    let homes_len: i64 = stack_homes.try_into().unwrap();
+    let reservations: i64 = if (stack_homes + clobbered.len()).is_multiple_of(2) {
         homes_len
     } else {
         homes_len + 1
@@ -1082,6 +1158,83 @@ fn assign_homes_fn(function: X64SFunction, homes: HashMap<Symbol, StackOrReg>) -
             ),
         }));
 
+    // Save callee saved registers
+    for register in clobbered.iter() {
+        compiled_function
+            .instruction_listing
+            .push(X64Assembly::Instruction(X64Instruction {
+                op_code: X64opCode::Push,
+                args: Operands::One(Operand::Register(*register)),
+            }));
+    }
+
+    // Link the frame record onto the collector's shadow stack, with the
+    // pointer slots cleared so the collector never follows garbage.
+    if stack_pointers > 0 {
+        for slot in 1..=stack_pointers {
+            compiled_function
+                .instruction_listing
+                .push(X64Assembly::Instruction(X64Instruction {
+                    op_code: X64opCode::Movq,
+                    args: Operands::Two(
+                        Operand::Immediate(X64Value::Absolute(0)),
+                        Operand::MemoryOffset(
+                            X64Value::Absolute(-slot * QUADWORD_SIZE),
+                            X64Register::Rbp,
+                        ),
+                    ),
+                }));
+        }
+        compiled_function
+            .instruction_listing
+            .push(X64Assembly::Instruction(X64Instruction {
+                op_code: X64opCode::Movq,
+                args: Operands::Two(
+                    Operand::Immediate(X64Value::Absolute(stack_pointers)),
+                    Operand::MemoryOffset(
+                        X64Value::Absolute(frame_record + QUADWORD_SIZE),
+                        X64Register::Rbp,
+                    ),
+                ),
+            }));
+        compiled_function
+            .instruction_listing
+            .push(X64Assembly::Instruction(X64Instruction {
+                op_code: X64opCode::Movq,
+                args: Operands::Two(
+                    Operand::MemoryOffset(X64Value::LabelRef(Label::GcFrames), X64Register::Rip),
+                    Operand::Register(X64Register::Rax),
+                ),
+            }));
+        compiled_function
+            .instruction_listing
+            .push(X64Assembly::Instruction(X64Instruction {
+                op_code: X64opCode::Movq,
+                args: Operands::Two(
+                    Operand::Register(X64Register::Rax),
+                    Operand::MemoryOffset(X64Value::Absolute(frame_record), X64Register::Rbp),
+                ),
+            }));
+        compiled_function
+            .instruction_listing
+            .push(X64Assembly::Instruction(X64Instruction {
+                op_code: X64opCode::Lea,
+                args: Operands::Two(
+                    Operand::MemoryOffset(X64Value::Absolute(frame_record), X64Register::Rbp),
+                    Operand::Register(X64Register::Rax),
+                ),
+            }));
+        compiled_function
+            .instruction_listing
+            .push(X64Assembly::Instruction(X64Instruction {
+                op_code: X64opCode::Movq,
+                args: Operands::Two(
+                    Operand::Register(X64Register::Rax),
+                    Operand::MemoryOffset(X64Value::LabelRef(Label::GcFrames), X64Register::Rip),
+                ),
+            }));
+    }
+
     for assembly in function.body {
         match assembly {
             X64SAssembly::Label(label) => {
@@ -1091,6 +1244,29 @@ fn assign_homes_fn(function: X64SFunction, homes: HashMap<Symbol, StackOrReg>) -
             }
             X64SAssembly::Instruction(instruction) => {
                 match instruction.args {
+                    SOperands::Two(SOperand::Symbol(source), SOperand::Symbol(destination)) => {
+                        let source = homes[&source];
+                        let destination = homes[&destination];
+                        let moves = match (source, destination) {
+                            _ if source == destination => vec![],
+                            (StackOrReg::Stack(_), StackOrReg::Stack(_)) => vec![
+                                (home_operand(source), Operand::Register(X64Register::Rax)),
+                                (
+                                    Operand::Register(X64Register::Rax),
+                                    home_operand(destination),
+                                ),
+                            ],
+                            _ => vec![(home_operand(source), home_operand(destination))],
+                        };
+                        for (from, to) in moves {
+                            compiled_function
+                                .instruction_listing
+                                .push(X64Assembly::Instruction(X64Instruction {
+                                    op_code: instruction.op_code,
+                                    args: Operands::Two(from, to),
+                                }));
+                        }
+                    }
                     SOperands::One(SOperand::Symbol(symbol)) => {
                         let stack_or_reg = homes.get(&symbol).unwrap();
                         let operand = match stack_or_reg {
@@ -1220,6 +1396,35 @@ fn assign_homes_fn(function: X64SFunction, homes: HashMap<Symbol, StackOrReg>) -
     }
 
     // Epilogue
+    // Unlink the frame record, leaving the return value in %rax alone
+    if stack_pointers > 0 {
+        compiled_function
+            .instruction_listing
+            .push(X64Assembly::Instruction(X64Instruction {
+                op_code: X64opCode::Movq,
+                args: Operands::Two(
+                    Operand::MemoryOffset(X64Value::Absolute(frame_record), X64Register::Rbp),
+                    Operand::Register(X64Register::Rdx),
+                ),
+            }));
+        compiled_function
+            .instruction_listing
+            .push(X64Assembly::Instruction(X64Instruction {
+                op_code: X64opCode::Movq,
+                args: Operands::Two(
+                    Operand::Register(X64Register::Rdx),
+                    Operand::MemoryOffset(X64Value::LabelRef(Label::GcFrames), X64Register::Rip),
+                ),
+            }));
+    }
+    for register in clobbered.iter().rev() {
+        compiled_function
+            .instruction_listing
+            .push(X64Assembly::Instruction(X64Instruction {
+                op_code: X64opCode::Pop,
+                args: Operands::One(Operand::Register(*register)),
+            }));
+    }
     compiled_function
         .instruction_listing
         .push(X64Assembly::Instruction(X64Instruction {
@@ -1245,10 +1450,132 @@ fn assign_homes_fn(function: X64SFunction, homes: HashMap<Symbol, StackOrReg>) -
     compiled_function
 }
 
-static AVALIBLE_REGISTERS: u64 = 16 - 2;
+/*
+ * The registers handed out by the register allocator, indexed by color.
+ * %rax and %rdx are left out because select and fix_up use them as scratch
+ * registers, %rsp and %rbp hold the frame.
+ *
+ * Caller saved registers come first so that the allocator only reaches for a
+ * callee saved register (which costs a push and a pop) when a symbol is live
+ * across a call and so can't be kept in a caller saved one.
+ */
+const COLOR_REGISTERS: [X64Register; 12] = [
+    X64Register::Rcx,
+    X64Register::Rsi,
+    X64Register::Rdi,
+    X64Register::R8,
+    X64Register::R9,
+    X64Register::R10,
+    X64Register::R11,
+    X64Register::Rbx,
+    X64Register::R12,
+    X64Register::R13,
+    X64Register::R14,
+    X64Register::R15,
+];
+
+/*
+ * Registers a call may overwrite (not saved across calls).
+ */
+const CALLER_SAVED_REGISTERS: [X64Register; 9] = [
+    X64Register::Rax,
+    X64Register::Rcx,
+    X64Register::Rdx,
+    X64Register::Rsi,
+    X64Register::Rdi,
+    X64Register::R8,
+    X64Register::R9,
+    X64Register::R10,
+    X64Register::R11,
+];
+
+/*
+ * Registers used to pass the first six arguments, in order.
+ */
+const ARGUMENT_REGISTERS: [X64Register; 6] = [
+    X64Register::Rdi,
+    X64Register::Rsi,
+    X64Register::Rdx,
+    X64Register::Rcx,
+    X64Register::R8,
+    X64Register::R9,
+];
+
+/*
+ * Registers a function must restore before returning (saved across calls).
+ */
+const CALLEE_SAVED_REGISTERS: [X64Register; 5] = [
+    X64Register::Rbx,
+    X64Register::R12,
+    X64Register::R13,
+    X64Register::R14,
+    X64Register::R15,
+];
+
+static AVALIBLE_REGISTERS: u64 = COLOR_REGISTERS.len() as u64;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Color(u64);
 
+/*
+ * InterferenceGraph
+ *
+ * edges:
+ *  for each symbol the symbols which are live at the same time and so can't
+ *  share a color
+ * forbidden:
+ *  for each symbol the registers it can't be colored with, either because
+ *  the register is written while the symbol is live or the symbol is written
+ *  while the register is live (e.g. arguments waiting for a call)
+ */
 #[derive(Debug)]
-struct Color(u64);
+pub struct InterferenceGraph {
+    pub edges: HashMap<Symbol, HashSet<Symbol>>,
+    pub forbidden: HashMap<Symbol, HashSet<X64Register>>,
+}
+
+impl InterferenceGraph {
+    fn add_node(&mut self, symbol: Symbol) {
+        self.edges.entry(symbol).or_default();
+        self.forbidden.entry(symbol).or_default();
+    }
+
+    fn add_edge(&mut self, a: Symbol, b: Symbol) {
+        if a != b {
+            self.edges.entry(a).or_default().insert(b);
+            self.edges.entry(b).or_default().insert(a);
+        }
+    }
+
+    fn forbid(&mut self, symbol: Symbol, register: X64Register) {
+        self.forbidden.entry(symbol).or_default().insert(register);
+    }
+
+    /*
+     * Merge a symbol into another, which takes on its neighbors and the
+     * registers it may not use.
+     */
+    fn merge(&mut self, symbol: Symbol, into: Symbol) {
+        for neighbor in self.edges.remove(&symbol).unwrap() {
+            self.edges.get_mut(&neighbor).unwrap().remove(&symbol);
+            self.add_edge(into, neighbor);
+        }
+        let forbidden = self.forbidden.remove(&symbol).unwrap();
+        self.forbidden.get_mut(&into).unwrap().extend(forbidden);
+    }
+
+    /*
+     * The number of constraints on a symbol: its neighbors plus the
+     * registers it may not use.
+     */
+    fn degree(&self, symbol: Symbol) -> usize {
+        self.edges[&symbol].len()
+            + self.forbidden[&symbol]
+                .iter()
+                .filter(|r| COLOR_REGISTERS.contains(r))
+                .count()
+    }
+}
 
 /*
  * Register Allocation
@@ -1260,72 +1587,427 @@ struct Color(u64);
  *      A mapping from Symbols to Option<Color>, None indicates that the
  *      register should be placed on the stack. Some(Color) indicates the
  *      color of the symbol. The color can be in [0,.AVALIBLE_REGISTERS).
+ *      Symbols coalesced into one node share its color.
  */
-fn register_alloc(function: &X64SFunction) -> HashMap<Symbol, Option<Color>> {
-    // A default implementation for homework 2 can return a HashMap mapping
-    // all the symbols in the function to None.
-    let mut map = HashMap::new();
+pub fn register_alloc(function: &X64SFunction) -> HashMap<Symbol, Option<Color>> {
+    let mut graph = build_interference_graph(function);
+    let merged_into = coalesce(&mut graph, function);
+    let mut colors = color_interference_graph(&graph);
+    for (symbol, into) in merged_into {
+        colors.insert(symbol, colors[&into]);
+    }
+    colors
+}
 
-    for assembly in &function.body {
-        match assembly {
-            X64SAssembly::Instruction(instruction) => {
-                match instruction.args {
-                    SOperands::One(SOperand::Symbol(symbol)) => {
-                        map.insert(symbol, None);
-                    }
-                    SOperands::One(SOperand::MemorySym(symbol)) => {
-                        map.insert(symbol, None);
-                    }
-                    SOperands::Two(SOperand::Symbol(symbol), _) => {
-                        map.insert(symbol, None);
-                    }
-                    SOperands::Two(SOperand::MemorySym(symbol), _) => {
-                        map.insert(symbol, None);
-                    }
-                    SOperands::Two(_, SOperand::Symbol(symbol)) => {
-                        map.insert(symbol, None);
-                    }
-                    SOperands::Two(_, SOperand::MemorySym(symbol)) => {
-                        map.insert(symbol, None);
-                    }
-                    _ => (),
-                };
+/*
+ * Build Interference Graph
+ *
+ * Runs liveness over the function and makes every symbol which is written by
+ * an instruction interfere with the symbols live out of that instruction.
+ * A call writes every caller saved register, so symbols live across a call
+ * are kept out of them and survive the call in a callee saved register or on
+ * the stack. Pointers live across a call always survive it on the stack,
+ * where the garbage collector can find them.
+ *
+ * The destination of a movq between symbols doesn't interfere with its
+ * source, as both hold the same value afterwards, so the two can be
+ * coalesced.
+ */
+pub fn build_interference_graph(function: &X64SFunction) -> InterferenceGraph {
+    let cfg = liveness(construct_control_flow_graph_x64s(function));
+    let live_registers = live_registers_out(function);
+
+    let mut graph = InterferenceGraph {
+        edges: HashMap::new(),
+        forbidden: HashMap::new(),
+    };
+
+    // liveness stores live in and live out together; recover live in so the
+    // live out of an instruction is exactly the union over its successors.
+    let live_in: HashMap<NodeIndex, HashSet<Symbol>> = cfg
+        .node_indices()
+        .into_iter()
+        .map(|n| {
+            let live = cfg.gen_node(n);
+            let through = difference(cfg.node_data(n), cfg.kill_node(n));
+            (n, union(live, through))
+        })
+        .collect();
+
+    for n in cfg.node_indices() {
+        let index = match cfg.node_weight(n) {
+            Node::Index(index) => index,
+            _ => continue,
+        };
+        let instruction = match &cfg.instructions[index] {
+            X64SAssembly::Instruction(instruction) => instruction,
+            X64SAssembly::Label(_) => continue,
+        };
+
+        let live_out = union_all(cfg.succ(n).iter().map(|s| live_in[s].clone()).collect());
+        let defs = cfg.kill_node(n);
+        let source = move_between_symbols(instruction).map(|(source, _)| source);
+
+        for symbol in cfg.gen_node(n).iter().chain(defs.iter()) {
+            graph.add_node(*symbol);
+        }
+
+        for def in defs.iter() {
+            for live in live_out.iter().filter(|live| Some(**live) != source) {
+                graph.add_edge(*def, *live);
+            }
+            for register in live_registers[index].iter() {
+                graph.forbid(*def, *register);
+            }
+        }
+
+        for register in register_defs(instruction) {
+            for live in live_out.iter() {
+                graph.forbid(*live, register);
+            }
+        }
+
+        // The collector finds pointers in stack frames, so they can't be kept
+        // in registers across a call
+        if let X64opCode::Call = instruction.op_code {
+            for live in live_out.iter().filter(|s| function.pointers.contains(s)) {
+                for register in COLOR_REGISTERS.iter() {
+                    graph.forbid(*live, *register);
+                }
+            }
+        }
+    }
+
+    graph
+}
+
+/*
+ * The source and destination of a movq between symbols
+ */
+fn move_between_symbols(instruction: &X64SInstruction) -> Option<(Symbol, Symbol)> {
+    match instruction {
+        X64SInstruction {
+            op_code: X64opCode::Movq,
+            args: SOperands::Two(SOperand::Symbol(source), SOperand::Symbol(destination)),
+        } => Some((*source, *destination)),
+        _ => None,
+    }
+}
+
+/*
+ * Coalesce
+ *
+ * Briggs style conservative coalescing: the source and destination of a movq
+ * which don't interfere are merged into one node as long as fewer than
+ * AVALIBLE_REGISTERS of the merged node's neighbors and forbidden registers
+ * are significant (neighbors with at least AVALIBLE_REGISTERS constraints, and
+ * every forbidden register), so the merged node can still be colored. This
+ * repeats until no move can be coalesced, and the movq's between symbols
+ * sharing a node are dropped by assign_homes_fn.
+ *
+ * Pointers get stack slots of their own, so they are only coalesced with
+ * other pointers.
+ *
+ * Output:
+ *      The node each merged symbol ended up in.
+ */
+fn coalesce(graph: &mut InterferenceGraph, function: &X64SFunction) -> HashMap<Symbol, Symbol> {
+    let k = AVALIBLE_REGISTERS as usize;
+    let moves: Vec<(Symbol, Symbol)> = function
+        .body
+        .iter()
+        .filter_map(|assembly| match assembly {
+            X64SAssembly::Instruction(instruction) => move_between_symbols(instruction),
+            X64SAssembly::Label(_) => None,
+        })
+        .collect();
+
+    let mut merged_into: HashMap<Symbol, Symbol> = HashMap::new();
+    let find = |merged_into: &HashMap<Symbol, Symbol>, mut symbol| {
+        while let Some(into) = merged_into.get(&symbol) {
+            symbol = *into;
+        }
+        symbol
+    };
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (source, destination) in moves.iter() {
+            let a = find(&merged_into, *source);
+            let b = find(&merged_into, *destination);
+            if a == b
+                || graph.edges[&a].contains(&b)
+                || function.pointers.contains(&a) != function.pointers.contains(&b)
+            {
+                continue;
+            }
+
+            let significant_neighbors = graph.edges[&a]
+                .union(&graph.edges[&b])
+                .filter(|neighbor| graph.degree(**neighbor) >= k)
+                .count();
+            let forbidden = graph.forbidden[&a]
+                .union(&graph.forbidden[&b])
+                .filter(|r| COLOR_REGISTERS.contains(r))
+                .count();
+            if significant_neighbors + forbidden < k {
+                graph.merge(b, a);
+                merged_into.insert(b, a);
+                changed = true;
+            }
+        }
+    }
+
+    merged_into
+        .keys()
+        .map(|symbol| (*symbol, find(&merged_into, *symbol)))
+        .collect()
+}
+
+/*
+ * Color Interference Graph
+ *
+ * Chaitin-Briggs style coloring: repeatedly remove a symbol with fewer than
+ * AVALIBLE_REGISTERS constraints (or the most constrained symbol when there is
+ * none), then color the symbols in reverse order of removal. Symbols which
+ * find no free color are spilled to the stack.
+ */
+fn color_interference_graph(graph: &InterferenceGraph) -> HashMap<Symbol, Option<Color>> {
+    let k = AVALIBLE_REGISTERS as usize;
+
+    let mut degrees: HashMap<Symbol, usize> =
+        graph.edges.keys().map(|s| (*s, graph.degree(*s))).collect();
+    let mut remaining: BTreeSet<Symbol> = graph.edges.keys().copied().collect();
+    let mut removed = Vec::new();
+
+    while !remaining.is_empty() {
+        let next = remaining
+            .iter()
+            .find(|s| degrees[s] < k)
+            .or_else(|| remaining.iter().max_by_key(|s| degrees[s]))
+            .copied()
+            .unwrap();
+
+        remaining.remove(&next);
+        for neighbor in graph.edges[&next].iter() {
+            if remaining.contains(neighbor) {
+                *degrees.get_mut(neighbor).unwrap() -= 1;
+            }
+        }
+        removed.push(next);
+    }
+
+    let mut colors: HashMap<Symbol, Option<Color>> = HashMap::new();
+
+    while let Some(symbol) = removed.pop() {
+        let taken: HashSet<u64> = graph.edges[&symbol]
+            .iter()
+            .filter_map(|neighbor| match colors.get(neighbor) {
+                Some(Some(Color(c))) => Some(*c),
+                _ => None,
+            })
+            .collect();
+        let forbidden = &graph.forbidden[&symbol];
+
+        let color = (0..AVALIBLE_REGISTERS)
+            .find(|c| !taken.contains(c) && !forbidden.contains(&COLOR_REGISTERS[*c as usize]))
+            .map(Color);
+        colors.insert(symbol, color);
+    }
+
+    colors
+}
+
+/*
+ * Live Registers Out
+ *
+ * For each line of the function the set of registers which hold a value that
+ * a later instruction reads. Registers are only ever live inside a straight
+ * line of code (arguments on their way into a call, results on their way
+ * out) so a backward scan which forgets everything at labels and jumps is
+ * enough.
+ */
+fn live_registers_out(function: &X64SFunction) -> Vec<HashSet<X64Register>> {
+    let mut live_out = vec![HashSet::new(); function.body.len()];
+    let mut live: HashSet<X64Register> = HashSet::new();
+
+    for (index, assembly) in function.body.iter().enumerate().rev() {
+        let instruction = match assembly {
+            X64SAssembly::Instruction(instruction) => instruction,
+            X64SAssembly::Label(_) => {
+                live = HashSet::new();
+                continue;
             }
-            _ => (),
         };
+        if let X64opCode::Jmp = instruction.op_code {
+            live = HashSet::new();
+        }
+
+        live_out[index] = live.clone();
+
+        for register in register_defs(instruction) {
+            live.remove(&register);
+        }
+        for register in register_uses(instruction) {
+            live.insert(register);
+        }
+        if let X64opCode::Call = instruction.op_code {
+            live.extend(call_arguments(&function.body[..index]));
+        }
     }
 
-    map
+    live_out
 }
 
-#[derive(Debug)]
+/*
+ * The argument registers filled in by the moves directly before a call.
+ */
+fn call_arguments(before_call: &[X64SAssembly]) -> Vec<X64Register> {
+    before_call
+        .iter()
+        .rev()
+        .map_while(|assembly| match assembly {
+            X64SAssembly::Instruction(X64SInstruction {
+                op_code: X64opCode::Movq,
+                args: SOperands::Two(_, SOperand::Register(register)),
+            }) if ARGUMENT_REGISTERS.contains(register) => Some(*register),
+            _ => None,
+        })
+        .collect()
+}
+
+/*
+ * Registers written by an instruction.
+ */
+fn register_defs(instruction: &X64SInstruction) -> Vec<X64Register> {
+    match (instruction.op_code, instruction.args) {
+        (X64opCode::Call, _) => CALLER_SAVED_REGISTERS.to_vec(),
+        (X64opCode::IMulq, _) | (X64opCode::IDivq, _) => {
+            vec![X64Register::Rax, X64Register::Rdx]
+        }
+        (X64opCode::Cqto, _) => vec![X64Register::Rdx],
+        (X64opCode::Cmp, _) => vec![],
+        (_, SOperands::Two(_, SOperand::Register(register)))
+        | (X64opCode::Pop, SOperands::One(SOperand::Register(register)))
+        | (X64opCode::Neg, SOperands::One(SOperand::Register(register))) => vec![register],
+        _ => vec![],
+    }
+}
+
+/*
+ * Registers read by an instruction (call arguments are handled separately).
+ */
+fn register_uses(instruction: &X64SInstruction) -> Vec<X64Register> {
+    let mut uses = match instruction.op_code {
+        X64opCode::IMulq | X64opCode::Cqto => vec![X64Register::Rax],
+        X64opCode::IDivq => vec![X64Register::Rax, X64Register::Rdx],
+        _ => vec![],
+    };
+
+    let operand_registers = |operand: &SOperand| match operand {
+        SOperand::Register(register) | SOperand::MemoryReg(register) => vec![*register],
+        SOperand::MemoryOffset(_, register) => vec![*register],
+        SOperand::MemoryScaledIndexed(_, base, _, index) => vec![*base, *index],
+        _ => vec![],
+    };
+
+    match (instruction.op_code, &instruction.args) {
+        (X64opCode::Movq, SOperands::Two(source, SOperand::Register(_)))
+        | (X64opCode::Lea, SOperands::Two(source, SOperand::Register(_))) => {
+            uses.append(&mut operand_registers(source));
+        }
+        (_, SOperands::Two(source, dest)) => {
+            uses.append(&mut operand_registers(source));
+            uses.append(&mut operand_registers(dest));
+        }
+        (_, SOperands::One(operand)) => {
+            uses.append(&mut operand_registers(operand));
+        }
+        (_, SOperands::Zero) => (),
+    }
+
+    uses
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 enum StackOrReg {
     Stack(i64),
     Reg(X64Register),
 }
 
+fn home_operand(home: StackOrReg) -> Operand {
+    match home {
+        StackOrReg::Stack(offset) => {
+            Operand::MemoryOffset(X64Value::Absolute(offset), X64Register::Rbp)
+        }
+        StackOrReg::Reg(register) => Operand::Register(register),
+    }
+}
+
+/*
+ * Clobbered Registers
+ *
+ * The callee saved registers used as homes in a function, in a fixed order so
+ * the epilogue can pop them in reverse.
+ */
+fn clobbered_registers(homes: &HashMap<Symbol, StackOrReg>) -> Vec<X64Register> {
+    CALLEE_SAVED_REGISTERS
+        .iter()
+        .copied()
+        .filter(|register| {
+            homes.values().any(|home| match home {
+                StackOrReg::Reg(r) => r == register,
+                StackOrReg::Stack(_) => false,
+            })
+        })
+        .collect()
+}
+
 /*
  * Register Assignment
  *
  * Input:
  *      The register allocation: a mapping of symbols to optionally a color.
+ *      The symbols which hold pointers.
  * Output:
  *      The register assignment: a mapping of symbols to the stack offset or
- *      the register to use.
+ *      the register to use. Pointers on the stack come first, directly above
+ *      the shadow stack frame record (see assign_homes_fn), so the collector
+ *      finds them as one run of slots.
  */
-fn register_assignment(allocation: HashMap<Symbol, Option<Color>>) -> HashMap<Symbol, StackOrReg> {
+fn register_assignment(
+    allocation: HashMap<Symbol, Option<Color>>,
+    pointers: &HashSet<Symbol>,
+) -> HashMap<Symbol, StackOrReg> {
     // Map all None's to distinct Stack offsets.
     // Map each Color (which contains a value less than AVALIBLE_REGISTERS)
     // to distinct Registers.
-
-    // A default implementation for homework 2 should map all symbols in
-    // allocation to some stack offset.
     let mut assignment = HashMap::new();
     let mut offset = -QUADWORD_SIZE;
 
-    for (symbol, _) in allocation.iter() {
-        assignment.insert(*symbol, StackOrReg::Stack(offset));
-        offset -= QUADWORD_SIZE;
+    let mut symbols: Vec<_> = allocation.keys().copied().collect();
+    symbols.sort();
+    let (pointer_symbols, other_symbols): (Vec<_>, Vec<_>) = symbols
+        .into_iter()
+        .partition(|symbol| pointers.contains(symbol));
+
+    for (group, symbols) in [pointer_symbols, other_symbols].iter().enumerate() {
+        // Leave room for the frame record below the pointers
+        if group == 1 && offset < -QUADWORD_SIZE {
+            offset -= SHADOW_FRAME_RECORD_SIZE * QUADWORD_SIZE;
+        }
+        for symbol in symbols.iter() {
+            match allocation[symbol] {
+                Some(Color(c)) => {
+                    assignment.insert(*symbol, StackOrReg::Reg(COLOR_REGISTERS[c as usize]));
+                }
+                None => {
+                    assignment.insert(*symbol, StackOrReg::Stack(offset));
+                    offset -= QUADWORD_SIZE;
+                }
+            }
+        }
     }
 
     assignment
@@ -2,13 +2,11 @@ use crate::checked_grammar::{
     CheckedDec, CheckedExp, CheckedLValue, CheckedProgram, CheckedTopLevelDec, FunctionType,
     GenerateTypeId, GlobalTypeInfo, Type, TypeId, B,
 };
-use crate::common::{Label, LabelGenerator, Symbol, SymbolGenerator};
+use crate::common::{InfixSourceOp, Label, LabelGenerator, Symbol, SymbolGenerator};
+use crate::diagnostic::{Diagnostic, Span};
 use crate::source_grammar::{Dec, Exp, LValue, Program, TopLevelDec};
 use std::collections::{HashMap, VecDeque};
 
-#[derive(Debug)]
-pub struct TypeError(pub &'static str);
-
 #[derive(Debug, Clone)]
 struct Context {
     f: HashMap<String, Label>,
@@ -27,6 +25,9 @@ impl StackedContext {
         let int_id = gti.new_type_id(Type::Int);
         let str_id = gti.new_type_id(Type::Str);
         let void_id = gti.new_type_id(Type::Void);
+        gti.type_names.insert(int_id, "int".to_string());
+        gti.type_names.insert(str_id, "string".to_string());
+        gti.type_names.insert(void_id, "void".to_string());
         let s = vec![].into_iter().collect();
         let t = vec![
             ("int".to_string(), int_id),
@@ -36,52 +37,53 @@ impl StackedContext {
         .into_iter()
         .collect();
 
-        let f = vec![
-            ("print_line_int".to_string(), Label::PrintlnInt),
-            ("print_int".to_string(), Label::PrintInt),
-            ("print_line_string".to_string(), Label::PrintlnString),
-            ("print_string".to_string(), Label::PrintString),
-        ]
-        .into_iter()
-        .collect();
-
-        let pli_param = gti.gen_sym.new_symbol();
-        let pi_param = gti.gen_sym.new_symbol();
-        let pls_param = gti.gen_sym.new_symbol();
-        let ps_param = gti.gen_sym.new_symbol();
-
-        gti.insert_f(
-            &Label::PrintlnInt,
-            FunctionType {
-                return_type: void_id,
-                arguments: vec![(pli_param, int_id)],
-            },
-        )
-        .unwrap();
-        gti.insert_f(
-            &Label::PrintInt,
-            FunctionType {
-                return_type: void_id,
-                arguments: vec![(pi_param, int_id)],
-            },
-        )
-        .unwrap();
-        gti.insert_f(
-            &Label::PrintlnString,
-            FunctionType {
-                return_type: void_id,
-                arguments: vec![(pls_param, str_id)],
-            },
-        )
-        .unwrap();
-        gti.insert_f(
-            &Label::PrintString,
-            FunctionType {
-                return_type: void_id,
-                arguments: vec![(ps_param, str_id)],
-            },
-        )
-        .unwrap();
+        // The builtins implemented in runtime.c: name, label, argument types
+        // and return type
+        let builtins = vec![
+            ("print_line_int", Label::PrintlnInt, vec![int_id], void_id),
+            ("print_int", Label::PrintInt, vec![int_id], void_id),
+            (
+                "print_line_string",
+                Label::PrintlnString,
+                vec![str_id],
+                void_id,
+            ),
+            ("print_string", Label::PrintString, vec![str_id], void_id),
+            ("size", Label::Size, vec![str_id], int_id),
+            (
+                "substring",
+                Label::Substring,
+                vec![str_id, int_id, int_id],
+                str_id,
+            ),
+            ("ord", Label::Ord, vec![str_id], int_id),
+            ("chr", Label::Chr, vec![int_id], str_id),
+            ("concat", Label::StringConcat, vec![str_id, str_id], str_id),
+            ("not", Label::Not, vec![int_id], int_id),
+            ("read_int", Label::ReadInt, vec![], int_id),
+            ("read_line", Label::ReadLine, vec![], str_id),
+            ("flush", Label::Flush, vec![], void_id),
+            ("exit", Label::Exit, vec![int_id], void_id),
+            // Takes an array of any type, which check_exp checks for
+            ("length", Label::ArrayLength, vec![], int_id),
+        ];
+
+        let mut f = HashMap::new();
+        for (name, label, argument_types, return_type) in builtins {
+            let arguments = argument_types
+                .into_iter()
+                .map(|type_id| (gti.gen_sym.new_symbol(), type_id))
+                .collect();
+            gti.insert_f(
+                &label,
+                FunctionType {
+                    return_type,
+                    arguments,
+                },
+            )
+            .unwrap();
+            f.insert(name.to_string(), label);
+        }
 
         StackedContext {
             context: Context { f, s, t },
@@ -125,59 +127,103 @@ impl StackedContext {
             .or_else(|| self.rest.as_ref().and_then(|s| s.lookup_f(name)))
     }
 
+    /*
+     * Look up a type by name, reporting it if it isn't in scope and using the
+     * error type in its place.
+     */
+    fn lookup_type(&self, gti: &mut GlobalTypeInfo, name: &String, span: Span) -> TypeId {
+        match self.lookup_t(name) {
+            Some(type_id) => type_id,
+            None => {
+                gti.report(unknown("type", name, span));
+                gti.new_type_id(Type::Error)
+            }
+        }
+    }
+
+    /*
+     * Declare a group of consecutive type declarations. Every name in the
+     * group gets its type id before any declaration is resolved, so records
+     * and arrays in the group can refer to themselves and to each other.
+     * A name defined twice keeps its first definition.
+     */
+    fn declare_type_group(&mut self, gti: &mut GlobalTypeInfo, group: Vec<TopLevelDec>) {
+        let mut names: HashMap<String, TypeId> = HashMap::new();
+        let mut definitions = vec![];
+        for dec in group.into_iter() {
+            let (new_type, span) = match &dec {
+                TopLevelDec::TyDecArray { new_type, span, .. } => (new_type, span),
+                TopLevelDec::TyDecRecord { new_type, span, .. } => (new_type, span),
+                TopLevelDec::FunDec { .. } => continue,
+            };
+            if names.contains_key(new_type) {
+                gti.report(
+                    Diagnostic::new(
+                        format!(
+                            "type `{}` is defined more than once in this group",
+                            new_type
+                        ),
+                        *span,
+                    )
+                    .with_identifier(new_type),
+                );
+                continue;
+            }
+            let type_id = gti.gen_type.new_type_id();
+            gti.type_names.insert(type_id, new_type.clone());
+            names.insert(new_type.clone(), type_id);
+            definitions.push(dec);
+        }
+
+        *self = self
+            .clone()
+            .stack(HashMap::new(), HashMap::new(), names.clone());
+
+        for dec in definitions.into_iter() {
+            match dec {
+                TopLevelDec::TyDecArray {
+                    new_type,
+                    element_type,
+                    span,
+                } => self.declare_type_array(gti, names[&new_type], element_type, span),
+                TopLevelDec::TyDecRecord {
+                    new_type,
+                    field_decs,
+                    span,
+                } => self.declare_type_record(gti, names[&new_type], field_decs, span),
+                TopLevelDec::FunDec { .. } => (),
+            }
+        }
+    }
+
     fn declare_type_array(
         &mut self,
         gti: &mut GlobalTypeInfo,
-        new_type: String,
+        new_type_id: TypeId,
         element_type: String,
-    ) -> Result<(), TypeError> {
-        let element_type_id = match self.lookup_t(&element_type) {
-            None => return Err(TypeError("no such type in array declaration")),
-            Some(type_id) => match gti.lookup_c(type_id) {
-                None => return Err(TypeError("element type not defined")),
-                Some(_tau) => type_id,
-            },
-        };
+        span: Span,
+    ) {
+        let element_type_id = self.lookup_type(gti, &element_type, span);
 
-        let new_type_id = gti.new_type_id(Type::Array(element_type_id));
-
-        *self = self.clone().stack(
-            HashMap::new(),
-            HashMap::new(),
-            vec![(new_type, new_type_id)].into_iter().collect(),
-        );
-        Ok(())
+        gti.types.insert(new_type_id, Type::Array(element_type_id));
     }
 
     fn declare_type_record(
         &mut self,
         gti: &mut GlobalTypeInfo,
-        new_type: String,
+        new_type_id: TypeId,
         field_decs: VecDeque<(String, String)>,
-    ) -> Result<(), TypeError> {
-        pairwise_diffrent(&field_decs)?;
+        span: Span,
+    ) {
+        pairwise_diffrent(gti, &field_decs, "field", span);
 
         let field_decs: Vec<(String, TypeId)> = field_decs
             .iter()
             .cloned()
-            .map(|(id, type_name)| match self.lookup_t(&type_name) {
-                None => Err(TypeError("no such type in record declaration")),
-                Some(type_id) => match gti.lookup_c(type_id) {
-                    None => Err(TypeError("element_type not defined")),
-                    Some(_tau) => Ok((id, type_id)),
-                },
-            })
-            .collect::<Result<_, _>>()?;
+            .map(|(id, type_name)| (id, self.lookup_type(gti, &type_name, span)))
+            .collect();
 
-        let new_type_id = gti.new_type_id(Type::Record(field_decs));
-
-        *self = self.clone().stack(
-            HashMap::new(),
-            HashMap::new(),
-            vec![(new_type, new_type_id)].into_iter().collect(),
-        );
-
-        Ok(())
+        gti.types.insert(new_type_id, Type::Record(field_decs));
     }
 
     fn declare_function(
@@ -187,28 +233,20 @@ impl StackedContext {
         return_type: String,
         args: VecDeque<(String, String)>,
         exp: Exp,
-    ) -> Result<CheckedTopLevelDec, TypeError> {
-        pairwise_diffrent(&args)?;
+        span: Span,
+    ) -> Result<CheckedTopLevelDec, Diagnostic> {
+        pairwise_diffrent(gti, &args, "argument", span);
 
         let arguments: Vec<(String, Symbol, TypeId)> = args
             .iter()
             .cloned()
-            .map(|(id, type_name)| match self.lookup_t(&type_name) {
-                None => Err(TypeError("no such type in function declaration")),
-                Some(type_id) => match gti.lookup_c(type_id) {
-                    None => Err(TypeError("arg type not defined")),
-                    Some(_) => Ok((id, gti.gen_sym.new_symbol(), type_id)),
-                },
+            .map(|(id, type_name)| {
+                let type_id = self.lookup_type(gti, &type_name, span);
+                (id, gti.gen_sym.new_symbol(), type_id)
             })
-            .collect::<Result<_, _>>()?;
+            .collect();
 
-        let return_type: TypeId = match self.lookup_t(&return_type) {
-            None => Err(TypeError("no such type in function declaration")),
-            Some(return_type_id) => match gti.lookup_c(return_type_id) {
-                None => return Err(TypeError("arg type not defined")),
-                Some(_) => Ok(return_type_id),
-            },
-        }?;
+        let return_type: TypeId = self.lookup_type(gti, &return_type, span);
 
         let new_name = if name == "main" {
             Label::Main
@@ -217,7 +255,8 @@ impl StackedContext {
         };
 
         for i in arguments.clone().into_iter() {
-            gti.insert_gamma(i.1, i.2)?;
+            gti.insert_gamma(i.1, i.2)
+                .map_err(|e| Diagnostic::new(e, span))?;
         }
 
         gti.insert_f(
@@ -226,7 +265,10 @@ impl StackedContext {
                 return_type,
                 arguments: arguments.clone().iter().map(|i| (i.1, i.2)).collect(),
             },
-        )?;
+        )
+        .map_err(|e| Diagnostic::new(e, span))?;
+
+        let body_span = exp.span();
 
         let (type_, cexp) = type_check_exp(
             gti,
@@ -239,8 +281,9 @@ impl StackedContext {
             exp,
         )?;
 
-        if gti.types.get(&return_type).unwrap() != &type_ {
-            return Err(TypeError("Function body does not evaluate to proper type"));
+        let expected_type = gti.types.get(&return_type).unwrap();
+        if !types_match(expected_type, &type_) {
+            gti.report(mismatch(gti, expected_type, &type_, body_span));
         }
 
         *self = self.clone().stack(
@@ -262,29 +305,22 @@ impl StackedContext {
         name: String,
         value_type: String,
         value: Exp,
-    ) -> Result<(Self, CheckedDec), TypeError> {
+        span: Span,
+    ) -> Result<(Self, CheckedDec), Diagnostic> {
+        let value_span = value.span();
         let (type_, cexp) = type_check_exp(gti, &self, false, value)?;
 
-        let dec_type: TypeId = match self.lookup_t(&value_type) {
-            None => Err(TypeError("no such type in var declaration")),
-            Some(dec_type_id) => match gti.lookup_c(dec_type_id) {
-                None => {
-                    return Err(TypeError(
-                        "internal error: couldn't find type associated with type id",
-                    ))
-                }
-                Some(_) => Ok(dec_type_id),
-            },
-        }?;
+        let dec_type: TypeId = self.lookup_type(gti, &value_type, span);
 
         let tp = gti.types.get(&dec_type).unwrap();
 
-        if tp != &type_ {
-            return Err(TypeError("declaration doesn't match exp"));
+        if !types_match(tp, &type_) {
+            gti.report(mismatch(gti, tp, &type_, value_span));
         }
 
         let s = gti.gen_sym.new_symbol();
-        gti.insert_gamma(s, dec_type)?;
+        gti.insert_gamma(s, dec_type)
+            .map_err(|e| Diagnostic::new(e, span))?;
 
         Ok((
             self.stack(
@@ -300,11 +336,17 @@ impl StackedContext {
     }
 }
 
-pub fn type_check(program: Program) -> Result<CheckedProgram, TypeError> {
+/*
+ * Type check a whole program. Checking carries on past errors, so on failure
+ * every diagnostic found is returned, in the order they were found.
+ */
+pub fn type_check(program: Program) -> Result<CheckedProgram, Vec<Diagnostic>> {
     let mut type_info = GlobalTypeInfo {
         function_symbols: HashMap::new(),
         symbol_table: HashMap::new(),
         types: HashMap::new(),
+        type_names: HashMap::new(),
+        diagnostics: vec![],
         gen_type: GenerateTypeId::new(),
         gen_sym: SymbolGenerator::new(),
         gen_label: LabelGenerator::new(),
@@ -316,42 +358,55 @@ pub fn type_check(program: Program) -> Result<CheckedProgram, TypeError> {
 
     let mut dec_list = VecDeque::new();
 
+    let mut type_group = vec![];
+
     for dec in program.dec_list.into_iter() {
         match dec {
-            TopLevelDec::TyDecArray {
-                new_type,
-                element_type,
-            } => {
-                sc.declare_type_array(&mut type_info, new_type, element_type)?;
-            }
-            TopLevelDec::TyDecRecord {
-                new_type,
-                field_decs,
-            } => {
-                sc.declare_type_record(&mut type_info, new_type, field_decs)?;
+            TopLevelDec::TyDecArray { .. } | TopLevelDec::TyDecRecord { .. } => {
+                type_group.push(dec);
             }
             TopLevelDec::FunDec {
                 name,
                 return_type,
                 args,
                 body,
+                span,
             } => {
                 if name == "main" {
                     if args.is_empty() && (return_type == "int" || return_type == "void") {
                         found_main = true;
                     } else {
-                        return Err(TypeError(
-                            "Main doesn't return int/void or main takes more than 0 args",
-                        ));
+                        type_info.report(
+                            Diagnostic::new(
+                                "`main` must take no arguments and return int or void",
+                                span,
+                            )
+                            .with_identifier(&name),
+                        );
                     }
                 }
 
-                let func = sc.declare_function(&mut type_info, name, return_type, args, *body)?;
-                dec_list.push_back(func);
+                if !type_group.is_empty() {
+                    sc.declare_type_group(&mut type_info, type_group.split_off(0));
+                }
+
+                match sc.declare_function(&mut type_info, name, return_type, args, *body, span) {
+                    Ok(func) => dec_list.push_back(func),
+                    Err(diagnostic) => type_info.report(diagnostic),
+                }
             }
         }
     }
-    if found_main {
+    if !type_group.is_empty() {
+        sc.declare_type_group(&mut type_info, type_group);
+    }
+    if !found_main {
+        type_info.report(Diagnostic::new(
+            "`main` function not found",
+            Span::default(),
+        ));
+    }
+    if type_info.diagnostics.is_empty() {
         Ok(CheckedProgram {
             dec_list,
             function_symbols: type_info.function_symbols,
@@ -361,31 +416,62 @@ pub fn type_check(program: Program) -> Result<CheckedProgram, TypeError> {
             gen_label: type_info.gen_label,
         })
     } else {
-        Err(TypeError("No Main Found"))
+        Err(type_info.diagnostics)
     }
 }
 
+/*
+ * Type check an expression, recovering from any error in it. The error is
+ * reported and the expression gets the error type, which matches every other
+ * type so the enclosing expressions don't report it again.
+ */
 fn type_check_exp(
     gti: &mut GlobalTypeInfo,
     c: &StackedContext,
     brk: B,
     exp: Exp,
-) -> Result<(Type, CheckedExp), TypeError> {
+) -> Result<(Type, CheckedExp), Diagnostic> {
+    match check_exp(gti, c, brk, exp) {
+        Ok(checked) => Ok(checked),
+        Err(diagnostic) => {
+            gti.report(diagnostic);
+            Ok((Type::Error, placeholder_exp()))
+        }
+    }
+}
+
+/*
+ * Stands in for an expression that failed to type check. Programs with errors
+ * are never lowered, so any expression will do.
+ */
+fn placeholder_exp() -> CheckedExp {
+    CheckedExp::Sequence {
+        sequence: VecDeque::new(),
+    }
+}
+
+fn check_exp(
+    gti: &mut GlobalTypeInfo,
+    c: &StackedContext,
+    brk: B,
+    exp: Exp,
+) -> Result<(Type, CheckedExp), Diagnostic> {
     match exp {
-        Exp::Break => {
+        Exp::Break { span } => {
             if brk {
                 Ok((Type::Void, CheckedExp::Break))
             } else {
-                Err(TypeError("Break appearing in place it shouldn't"))
+                Err(Diagnostic::new("`break` outside of a loop", span))
             }
         }
-        Exp::IntLit { value } => Ok((Type::Int, CheckedExp::IntLit { value })),
-        Exp::StringLit { value } => Ok((Type::Str, CheckedExp::StringLit { value })),
-        Exp::LValue { lvalue } => {
+        Exp::IntLit { value, .. } => Ok((Type::Int, CheckedExp::IntLit { value })),
+        Exp::StringLit { value, .. } => Ok((Type::Str, CheckedExp::StringLit { value })),
+        Exp::Nil { .. } => Ok((Type::Nil, CheckedExp::Nil)),
+        Exp::LValue { lvalue, .. } => {
             let (tp, clvalue) = type_check_lvalue(gti, c, false, lvalue)?;
             Ok((tp, CheckedExp::LValue { lvalue: clvalue }))
         }
-        Exp::Sequence { sequence } => {
+        Exp::Sequence { sequence, .. } => {
             let mut seq = VecDeque::new();
             let mut tp = None;
             for exp in sequence.into_iter() {
@@ -400,96 +486,180 @@ fn type_check_exp(
 
             Ok((final_type, CheckedExp::Sequence { sequence: seq }))
         }
-        Exp::Negate { exp } => {
+        Exp::Negate { exp, .. } => {
+            let exp_span = exp.span();
             let (tp, cexp) = type_check_exp(gti, c, brk, *exp)?;
             match tp {
-                Type::Int => Ok((
+                Type::Int | Type::Error => Ok((
                     Type::Int,
                     CheckedExp::Negate {
                         exp: Box::new(cexp),
                     },
                 )),
-                _ => Err(TypeError("Negate contains non-integer")),
+                _ => Err(mismatch(gti, &Type::Int, &tp, exp_span)),
             }
         }
-        Exp::Infix { left, op, right } => {
+        Exp::Infix {
+            left, op, right, ..
+        } => {
+            let (left_span, right_span) = (left.span(), right.span());
             let (tp_l, lexp) = type_check_exp(gti, c, brk, *left)?;
             let (tp_r, rexp) = type_check_exp(gti, c, brk, *right)?;
+            if op == InfixSourceOp::Concat {
+                // Concatenation allocates a new string in the runtime
+                let checked_concat = CheckedExp::Call {
+                    function_name: Label::StringConcat,
+                    args: vec![lexp, rexp].into_iter().collect(),
+                };
+                return match (tp_l, tp_r) {
+                    (Type::Str, Type::Str) | (Type::Error, _) | (_, Type::Error) => {
+                        Ok((Type::Str, checked_concat))
+                    }
+                    (Type::Str, tp_r) => Err(mismatch(gti, &Type::Str, &tp_r, right_span)),
+                    (tp_l, _) => Err(mismatch(gti, &Type::Str, &tp_l, left_span)),
+                };
+            }
+            let equality = op == InfixSourceOp::Equal || op == InfixSourceOp::NotEqual;
+            let comparison = equality
+                || op == InfixSourceOp::LessThan
+                || op == InfixSourceOp::LessThanEqual
+                || op == InfixSourceOp::GreaterThan
+                || op == InfixSourceOp::GreaterThanEqual;
+            // Strings are compared by contents: the runtime's string_compare
+            // returns a negative, zero or positive int, which is compared
+            // against zero with the same operator.
+            let string_comparison = comparison && matches!((&tp_l, &tp_r), (Type::Str, Type::Str));
+            let (lexp, rexp) = if string_comparison {
+                let compare = CheckedExp::Call {
+                    function_name: Label::StringCompare,
+                    args: vec![lexp, rexp].into_iter().collect(),
+                };
+                (compare, CheckedExp::IntLit { value: 0 })
+            } else {
+                (lexp, rexp)
+            };
+            let checked_infix = CheckedExp::Infix {
+                left: Box::new(lexp),
+                op,
+                right: Box::new(rexp),
+            };
             match (tp_l, tp_r) {
-                (Type::Int, Type::Int) => Ok((
-                    Type::Int,
-                    CheckedExp::Infix {
-                        left: Box::new(lexp),
-                        op,
-                        right: Box::new(rexp),
-                    },
-                )),
-                _ => Err(TypeError("Not Both int on either side of infix op")),
+                (Type::Int, Type::Int) | (Type::Error, _) | (_, Type::Error) => {
+                    Ok((Type::Int, checked_infix))
+                }
+                (Type::Str, Type::Str) if comparison => Ok((Type::Int, checked_infix)),
+                (Type::Str, tp_r) if comparison => {
+                    Err(mismatch(gti, &Type::Str, &tp_r, right_span))
+                }
+                // Records and arrays are compared by reference
+                (tp_l, tp_r) if equality && is_reference(&tp_l) => {
+                    if types_match(&tp_l, &tp_r) || types_match(&tp_r, &tp_l) {
+                        Ok((Type::Int, checked_infix))
+                    } else {
+                        Err(mismatch(gti, &tp_l, &tp_r, right_span))
+                    }
+                }
+                (Type::Int, tp_r) => Err(mismatch(gti, &Type::Int, &tp_r, right_span)),
+                (tp_l, _) => Err(mismatch(gti, &Type::Int, &tp_l, left_span)),
             }
         }
         Exp::ArrayCreate {
             type_id,
             length,
             initial_value,
+            span,
         } => {
+            let (init_span, len_span) = (initial_value.span(), length.span());
             let (tp_init, init_exp) = type_check_exp(gti, c, brk, *initial_value)?;
             let (tp_len, len_exp) = type_check_exp(gti, c, brk, *length)?;
-            if Type::Int != tp_len {
-                return Err(TypeError("Array length not int"));
+            if !types_match(&Type::Int, &tp_len) {
+                return Err(mismatch(gti, &Type::Int, &tp_len, len_span));
             }
-            let tau_id = c.lookup_t(&type_id).ok_or(TypeError("Type not found"))?;
-            let tau = gti.lookup_c(tau_id).ok_or(TypeError("Type not found"))?;
+            let tau_id = c
+                .lookup_t(&type_id)
+                .ok_or_else(|| unknown("type", &type_id, span))?;
+            let tau = gti.lookup_c(tau_id).unwrap();
             let element_type_id = match tau {
                 Type::Array(i) => i,
-                _ => return Err(TypeError("Type not array")),
+                Type::Error => return Ok((Type::Error, placeholder_exp())),
+                _ => {
+                    return Err(Diagnostic::new(
+                        format!("`{}` is not an array type", type_id),
+                        span,
+                    )
+                    .with_identifier(&type_id))
+                }
             };
-            let element_type = gti
-                .lookup_c(element_type_id)
-                .ok_or(TypeError("Type not found"))?;
-            if tp_init != element_type {
-                return Err(TypeError("Array type doesn't match exp"));
+            let element_type = gti.lookup_c(element_type_id).unwrap();
+            if !types_match(&element_type, &tp_init) {
+                return Err(mismatch(gti, &element_type, &tp_init, init_span));
             }
             Ok((
                 Type::Array(element_type_id),
                 CheckedExp::ArrayCreate {
+                    type_id: tau_id,
                     length: Box::new(len_exp),
                     initial_value: Box::new(init_exp),
                 },
             ))
         }
-        Exp::RecordCreate { type_id, fields } => {
-            let tau_id = c.lookup_t(&type_id).ok_or(TypeError("Type not found"))?;
-            let tau = gti.lookup_c(tau_id).ok_or(TypeError("Type not found"))?;
+        Exp::RecordCreate {
+            type_id,
+            fields,
+            span,
+        } => {
+            let tau_id = c
+                .lookup_t(&type_id)
+                .ok_or_else(|| unknown("type", &type_id, span))?;
+            let tau = gti.lookup_c(tau_id).unwrap();
             let field_type_ids = match tau {
                 Type::Record(fields) => fields,
-                _ => return Err(TypeError("Not a record type")),
+                Type::Error => return Ok((Type::Error, placeholder_exp())),
+                _ => {
+                    return Err(Diagnostic::new(
+                        format!("`{}` is not a record type", type_id),
+                        span,
+                    )
+                    .with_identifier(&type_id))
+                }
             };
             let cfields = fields
                 .into_iter()
                 .zip(field_type_ids.clone().into_iter())
                 .map(|((id, exp), (field_name, type_id))| {
-                    let (tp_elem, cexp) = type_check_exp(gti, c, brk, exp)?;
-                    let tp = gti.lookup_c(type_id).ok_or(TypeError("Type not found"))?;
-                    if tp != tp_elem {
-                        return Err(TypeError("Array type doesn't match exp"));
-                    }
+                    let exp_span = exp.span();
                     if id != field_name {
-                        return Err(TypeError("Unknown Field name "));
+                        gti.report(
+                            Diagnostic::new(
+                                format!("expected field `{}`, found `{}`", field_name, id),
+                                exp_span,
+                            )
+                            .with_identifier(&id),
+                        );
+                    }
+                    let (tp_elem, cexp) = type_check_exp(gti, c, brk, exp)?;
+                    let tp = gti.lookup_c(type_id).unwrap();
+                    if !types_match(&tp, &tp_elem) {
+                        gti.report(mismatch(gti, &tp, &tp_elem, exp_span));
                     }
                     Ok((field_name, cexp))
                 })
-                .collect::<Result<VecDeque<(String, CheckedExp)>, TypeError>>()?;
+                .collect::<Result<VecDeque<(String, CheckedExp)>, Diagnostic>>()?;
             Ok((
                 Type::Record(field_type_ids),
-                CheckedExp::RecordCreate { fields: cfields },
+                CheckedExp::RecordCreate {
+                    type_id: tau_id,
+                    fields: cfields,
+                },
             ))
         }
-        Exp::Assign { left, right } => {
+        Exp::Assign { left, right, .. } => {
+            let right_span = right.span();
             let (type_left, cleft) = type_check_lvalue(gti, c, brk, left)?;
             let (type_right, cright) = type_check_exp(gti, c, false, *right)?;
 
-            if type_left != type_right {
-                return Err(TypeError("Types on either side of = don't match"));
+            if !types_match(&type_left, &type_right) {
+                return Err(mismatch(gti, &type_left, &type_right, right_span));
             }
 
             Ok((
@@ -504,19 +674,28 @@ fn type_check_exp(
             if_exp,
             then_exp,
             else_exp,
+            ..
         } => {
+            let (if_span, else_span) = (if_exp.span(), else_exp.span());
             let (tp_if, if_cexp) = type_check_exp(gti, c, false, *if_exp)?;
             let (tp_then, then_cexp) = type_check_exp(gti, c, brk, *then_exp)?;
             let (tp_else, else_cexp) = type_check_exp(gti, c, brk, *else_exp)?;
-            match tp_if {
-                Type::Int => (),
-                _ => return Err(TypeError("Cond not int if then else")),
+            if !types_match(&Type::Int, &tp_if) {
+                return Err(mismatch(gti, &Type::Int, &tp_if, if_span));
             }
-            if tp_then != tp_else {
-                return Err(TypeError("then and else branch don't match"));
+            if !types_match(&tp_then, &tp_else) && !types_match(&tp_else, &tp_then) {
+                return Err(
+                    Diagnostic::new("`if` and `else` have incompatible types", else_span)
+                        .with_types(gti.type_name(&tp_then), gti.type_name(&tp_else)),
+                );
             }
+            let tp = if tp_then == Type::Nil {
+                tp_else
+            } else {
+                tp_then
+            };
             Ok((
-                tp_then,
+                tp,
                 CheckedExp::IfThenElse {
                     if_exp: Box::new(if_cexp),
                     then_exp: Box::new(then_cexp),
@@ -524,12 +703,17 @@ fn type_check_exp(
                 },
             ))
         }
-        Exp::IfThen { if_exp, then_exp } => {
+        Exp::IfThen {
+            if_exp, then_exp, ..
+        } => {
+            let (if_span, then_span) = (if_exp.span(), then_exp.span());
             let (tp_if, if_cexp) = type_check_exp(gti, c, false, *if_exp)?;
             let (tp_then, then_cexp) = type_check_exp(gti, c, brk, *then_exp)?;
-            match (tp_if, tp_then) {
-                (Type::Int, Type::Void) => (),
-                _ => return Err(TypeError("Cond not int if_then")),
+            if !types_match(&Type::Int, &tp_if) {
+                return Err(mismatch(gti, &Type::Int, &tp_if, if_span));
+            }
+            if !types_match(&Type::Void, &tp_then) {
+                return Err(mismatch(gti, &Type::Void, &tp_then, then_span));
             }
 
             Ok((
@@ -540,12 +724,17 @@ fn type_check_exp(
                 },
             ))
         }
-        Exp::While { while_exp, do_exp } => {
+        Exp::While {
+            while_exp, do_exp, ..
+        } => {
+            let (while_span, do_span) = (while_exp.span(), do_exp.span());
             let (tp_while, while_cexp) = type_check_exp(gti, c, false, *while_exp)?;
             let (tp_do, do_cexp) = type_check_exp(gti, c, true, *do_exp)?;
-            match (tp_while, tp_do) {
-                (Type::Int, Type::Void) => (),
-                _ => return Err(TypeError("Cond not int while")),
+            if !types_match(&Type::Int, &tp_while) {
+                return Err(mismatch(gti, &Type::Int, &tp_while, while_span));
+            }
+            if !types_match(&Type::Void, &tp_do) {
+                return Err(mismatch(gti, &Type::Void, &tp_do, do_span));
             }
 
             Ok((
@@ -561,13 +750,16 @@ fn type_check_exp(
             for_exp,
             to_exp,
             do_exp,
+            span,
         } => {
+            let (for_span, to_span, do_span) = (for_exp.span(), to_exp.span(), do_exp.span());
             let (tp_for, for_cexp) = type_check_exp(gti, c, false, *for_exp)?;
             let (tp_to, to_cexp) = type_check_exp(gti, c, false, *to_exp)?;
 
             let i = gti.gen_sym.new_symbol();
             let int = gti.new_type_id(Type::Int);
-            gti.insert_gamma(i, int)?;
+            gti.insert_gamma(i, int)
+                .map_err(|e| Diagnostic::new(e, span))?;
             let scope = c.clone().stack(
                 HashMap::new(),
                 vec![(id, i)].into_iter().collect(),
@@ -576,9 +768,14 @@ fn type_check_exp(
 
             let (tp_do, do_cexp) = type_check_exp(gti, &scope, true, *do_exp)?;
 
-            match (tp_do, tp_for, tp_to) {
-                (Type::Void, Type::Int, Type::Int) => (),
-                _ => return Err(TypeError("For loop incorrect")),
+            if !types_match(&Type::Int, &tp_for) {
+                return Err(mismatch(gti, &Type::Int, &tp_for, for_span));
+            }
+            if !types_match(&Type::Int, &tp_to) {
+                return Err(mismatch(gti, &Type::Int, &tp_to, to_span));
+            }
+            if !types_match(&Type::Void, &tp_do) {
+                return Err(mismatch(gti, &Type::Void, &tp_do, do_span));
             }
 
             Ok((
@@ -591,7 +788,9 @@ fn type_check_exp(
                 },
             ))
         }
-        Exp::Let { let_exp, in_exp } => {
+        Exp::Let {
+            let_exp, in_exp, ..
+        } => {
             let mut sc = c.clone();
             let mut decs = VecDeque::new();
             for dec in let_exp.into_iter() {
@@ -600,8 +799,9 @@ fn type_check_exp(
                         name,
                         value_type,
                         value,
+                        span,
                     } => {
-                        let (sc_, dec) = sc.declare_variable(gti, name, value_type, value)?;
+                        let (sc_, dec) = sc.declare_variable(gti, name, value_type, value, span)?;
                         sc = sc_;
                         decs.push_back(dec);
                     }
@@ -621,35 +821,32 @@ fn type_check_exp(
         Exp::Call {
             function_name,
             args,
+            span,
         } => {
             let function_sym = match c.lookup_f(&function_name) {
                 Some(function_sym) => function_sym,
-                None => return Err(TypeError("Unknown Function being called")),
+                None => return Err(unknown("function", &function_name, span)),
             };
 
             let FunctionType {
                 return_type,
                 arguments,
-            } = match gti.lookup_f(&function_sym) {
-                Some(function_type) => function_type,
-                None => return Err(TypeError("internal Error Unknown function")),
-            };
+            } = gti.lookup_f(&function_sym).unwrap();
 
-            let return_type_full = match gti.lookup_c(return_type) {
-                Some(return_type_full) => return_type_full,
-                None => return Err(TypeError("Unknown return type")),
-            };
+            let return_type_full = gti.lookup_c(return_type).unwrap();
+
+            if function_sym == Label::ArrayLength {
+                return check_array_length(gti, c, args, span);
+            }
 
             let mut cargs = VecDeque::new();
 
             for ((_, type_id), exp) in arguments.into_iter().zip(args.into_iter()) {
-                let expected_type = match gti.lookup_c(type_id) {
-                    Some(s) => (s),
-                    None => return Err(TypeError("Unknown arg type")),
-                };
+                let expected_type = gti.lookup_c(type_id).unwrap();
+                let exp_span = exp.span();
                 let (actual_type, cexp) = type_check_exp(gti, c, false, exp)?;
-                if expected_type != actual_type {
-                    return Err(TypeError("argument doesn't have expected type"));
+                if !types_match(&expected_type, &actual_type) {
+                    gti.report(mismatch(gti, &expected_type, &actual_type, exp_span));
                 }
                 cargs.push_back(cexp);
             }
@@ -665,63 +862,126 @@ fn type_check_exp(
     }
 }
 
+/*
+ * `length` is the one builtin that takes arguments of more than one type:
+ * any array.
+ */
+fn check_array_length(
+    gti: &mut GlobalTypeInfo,
+    c: &StackedContext,
+    args: VecDeque<Exp>,
+    span: Span,
+) -> Result<(Type, CheckedExp), Diagnostic> {
+    if args.len() != 1 {
+        return Err(Diagnostic::new(
+            format!(
+                "`length` takes 1 argument but {} arguments were supplied",
+                args.len()
+            ),
+            span,
+        )
+        .with_identifier("length"));
+    }
+    let array = args.into_iter().next().unwrap();
+    let array_span = array.span();
+    let (array_type, array_exp) = type_check_exp(gti, c, false, array)?;
+    match array_type {
+        Type::Array(_) | Type::Error => Ok((
+            Type::Int,
+            CheckedExp::Call {
+                function_name: Label::ArrayLength,
+                args: vec![array_exp].into_iter().collect(),
+            },
+        )),
+        _ => Err(Diagnostic::new("mismatched types", array_span)
+            .with_types("array".to_string(), gti.type_name(&array_type))),
+    }
+}
+
 fn type_check_lvalue(
     gti: &mut GlobalTypeInfo,
     c: &StackedContext,
     brk: B,
     lvalue: LValue,
-) -> Result<(Type, CheckedLValue), TypeError> {
+) -> Result<(Type, CheckedLValue), Diagnostic> {
     match lvalue {
-        LValue::Id { name } => {
+        LValue::Id { name, span } => {
             let name_s = c
                 .lookup_s(&name)
-                .ok_or(TypeError("No Such name in LValue"))?;
-            let type_id = gti
-                .lookup_gamma(name_s)
-                .ok_or(TypeError("No Such type id"))?;
-            let tau = gti.lookup_c(type_id).ok_or(TypeError("No Such type"))?;
+                .ok_or_else(|| unknown("variable", &name, span))?;
+            let type_id = gti.lookup_gamma(name_s).unwrap();
+            let tau = gti.lookup_c(type_id).unwrap();
             Ok((tau, CheckedLValue::Id { name: name_s }))
         }
-        LValue::Subscript { array, index } => {
+        LValue::Subscript { array, index, .. } => {
+            let (array_span, index_span) = (array.span(), index.span());
             let (tau_array, carray) = type_check_lvalue(gti, c, brk, *array)?;
             let (tau_index, cindex) = type_check_exp(gti, c, false, *index)?;
 
-            let element_type_id = match (tau_array, tau_index) {
-                (Type::Array(element_type), Type::Int) => element_type,
-                _ => return Err(TypeError("Array index error")),
+            let checked_subscript = CheckedLValue::Subscript {
+                array: Box::new(carray),
+                index: Box::new(cindex),
             };
-
-            let element_type = match gti.lookup_c(element_type_id) {
-                Some(element_type) => element_type,
-                None => return Err(TypeError("Unknown element Type")),
+            let element_type_id = match tau_array {
+                Type::Array(element_type) => element_type,
+                Type::Error => return Ok((Type::Error, checked_subscript)),
+                _ => {
+                    return Err(Diagnostic::new(
+                        format!(
+                            "cannot index into a value of type `{}`",
+                            gti.type_name(&tau_array)
+                        ),
+                        array_span,
+                    ))
+                }
             };
+            if !types_match(&Type::Int, &tau_index) {
+                return Err(mismatch(gti, &Type::Int, &tau_index, index_span));
+            }
 
-            Ok((
-                element_type,
-                CheckedLValue::Subscript {
-                    array: Box::new(carray),
-                    index: Box::new(cindex),
-                },
-            ))
+            let element_type = gti.lookup_c(element_type_id).unwrap();
+
+            Ok((element_type, checked_subscript))
         }
-        LValue::FieldExp { record, field } => {
+        LValue::FieldExp {
+            record,
+            field,
+            span,
+        } => {
             let (tau_rec, crec) = type_check_lvalue(gti, c, brk, *record)?;
-            let fields = match tau_rec {
+            let fields = match &tau_rec {
                 Type::Record(fields) => fields,
-                _ => return Err(TypeError(".field is into non-record")),
+                Type::Error => {
+                    let checked_field = CheckedLValue::FieldExp {
+                        record: Box::new(crec),
+                        field,
+                    };
+                    return Ok((Type::Error, checked_field));
+                }
+                _ => {
+                    return Err(Diagnostic::new(
+                        format!("no field `{}` on type `{}`", field, gti.type_name(&tau_rec)),
+                        span,
+                    )
+                    .with_identifier(&field))
+                }
             };
 
             let mut type_id = None;
             for (f, fty) in fields {
-                if f == field {
-                    type_id = Some(fty);
+                if *f == field {
+                    type_id = Some(*fty);
                     break;
                 }
             }
-            let type_id = type_id.ok_or(TypeError("No such field in .field"))?;
-            let exp_type = gti
-                .lookup_c(type_id)
-                .ok_or(TypeError("Field Type Not found"))?;
+            let type_id = type_id.ok_or_else(|| {
+                Diagnostic::new(
+                    format!("no field `{}` on type `{}`", field, gti.type_name(&tau_rec)),
+                    span,
+                )
+                .with_identifier(&field)
+            })?;
+            let exp_type = gti.lookup_c(type_id).unwrap();
 
             Ok((
                 exp_type,
@@ -734,23 +994,69 @@ fn type_check_lvalue(
     }
 }
 
-fn pairwise_diffrent(field_decs: &VecDeque<(String, String)>) -> Result<(), TypeError> {
+fn pairwise_diffrent(
+    gti: &mut GlobalTypeInfo,
+    field_decs: &VecDeque<(String, String)>,
+    kind: &str,
+    span: Span,
+) {
     let mut i = 0;
 
-    let mut pairwise_diffrent = true;
-
     while i < field_decs.len() {
         let mut j = i + 1;
         while j < field_decs.len() {
-            pairwise_diffrent = pairwise_diffrent && (field_decs[i].0 != field_decs[j].0);
+            if field_decs[i].0 == field_decs[j].0 {
+                gti.report(
+                    Diagnostic::new(
+                        format!("{} `{}` is declared more than once", kind, field_decs[i].0),
+                        span,
+                    )
+                    .with_identifier(&field_decs[i].0),
+                );
+                break;
+            }
             j += 1;
         }
         i += 1;
     }
+}
 
-    if !pairwise_diffrent {
-        return Err(TypeError("Record Field Names in Types Not distinct"));
+/*
+ * A diagnostic for a name that isn't in scope.
+ */
+fn unknown(kind: &str, name: &str, span: Span) -> Diagnostic {
+    Diagnostic::new(
+        format!("cannot find {} `{}` in this scope", kind, name),
+        span,
+    )
+    .with_identifier(name)
+}
+
+/*
+ * A diagnostic for an expression at span whose type is found rather than
+ * expected.
+ */
+fn mismatch(gti: &GlobalTypeInfo, expected: &Type, found: &Type, span: Span) -> Diagnostic {
+    Diagnostic::new("mismatched types", span)
+        .with_types(gti.type_name(expected), gti.type_name(found))
+}
+
+/*
+ * Whether a value of type found can be used where expected is required. This
+ * is equality except that nil can be used as any record, and the error type
+ * matches everything.
+ */
+fn types_match(expected: &Type, found: &Type) -> bool {
+    match (expected, found) {
+        (Type::Error, _) | (_, Type::Error) => true,
+        (Type::Record(_), Type::Nil) => true,
+        _ => expected == found,
     }
+}
 
-    Ok(())
+/*
+ * Types whose values are pointers to the heap (or nil).
+ */
+fn is_reference(tp: &Type) -> bool {
+    matches!(tp, Type::Record(_) | Type::Array(_) | Type::Nil)
 }
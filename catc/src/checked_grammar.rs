@@ -1,5 +1,5 @@
-use crate::check_type::TypeError;
 use crate::common::{InfixSourceOp, Label, LabelGenerator, Symbol, SymbolGenerator};
+use crate::diagnostic::Diagnostic;
 
 use serde::{Deserialize, Serialize};
 
@@ -28,8 +28,9 @@ pub enum CheckedTopLevelDec {
 #[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
 pub enum CheckedExp {
     Break,
+    Nil,
     IntLit {
-        value: i32,
+        value: i64,
     },
     StringLit {
         value: String,
@@ -49,10 +50,12 @@ pub enum CheckedExp {
         right: Box<CheckedExp>,
     },
     ArrayCreate {
+        type_id: TypeId,
         length: Box<CheckedExp>,
         initial_value: Box<CheckedExp>,
     },
     RecordCreate {
+        type_id: TypeId,
         fields: VecDeque<(String, CheckedExp)>,
     },
     Assign {
@@ -114,6 +117,9 @@ pub struct TypeId(u64);
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub enum Type {
     Void,
+    Nil,
+    // The type of an expression that failed to type check
+    Error,
     Str,
     Int,
     Record(Vec<(String, TypeId)>),
@@ -134,6 +140,8 @@ pub struct GlobalTypeInfo {
     pub function_symbols: HashMap<Label, FunctionType>,
     pub symbol_table: HashMap<Symbol, TypeId>,
     pub types: HashMap<TypeId, Type>,
+    pub type_names: HashMap<TypeId, String>,
+    pub diagnostics: Vec<Diagnostic>,
     pub gen_type: GenerateTypeId,
     pub gen_sym: SymbolGenerator,
     pub gen_label: LabelGenerator,
@@ -147,9 +155,13 @@ impl GlobalTypeInfo {
         new_type_id
     }
 
-    pub fn insert_gamma(&mut self, id: Symbol, type_id: TypeId) -> Result<(), TypeError> {
+    pub fn report(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    pub fn insert_gamma(&mut self, id: Symbol, type_id: TypeId) -> Result<(), &'static str> {
         if self.symbol_table.contains_key(&id) {
-            Err(TypeError("Duplicate Symbol"))
+            Err("internal error: duplicate symbol")
         } else {
             self.symbol_table.insert(id, type_id);
             Ok(())
@@ -168,14 +180,54 @@ impl GlobalTypeInfo {
         self.function_symbols.get(name).cloned()
     }
 
-    pub fn insert_f(&mut self, id: &Label, fn_type: FunctionType) -> Result<(), TypeError> {
+    pub fn insert_f(&mut self, id: &Label, fn_type: FunctionType) -> Result<(), &'static str> {
         if self.function_symbols.contains_key(id) {
-            Err(TypeError("Duplicate Label"))
+            Err("internal error: duplicate label")
         } else {
             self.function_symbols.insert(*id, fn_type);
             Ok(())
         }
     }
+
+    /*
+     * The name a type is written as in Cat source, used in error messages.
+     * Records and arrays are compared structurally, so this is the name of
+     * the first declared type with the same shape.
+     */
+    pub fn type_name(&self, type_: &Type) -> String {
+        match type_ {
+            Type::Void => "void".to_string(),
+            Type::Nil => "nil".to_string(),
+            Type::Error => "{error}".to_string(),
+            Type::Str => "string".to_string(),
+            Type::Int => "int".to_string(),
+            Type::Record(fields) => self.declared_name(type_).unwrap_or_else(|| {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(name, type_id)| format!("{}: {}", name, self.type_id_name(*type_id)))
+                    .collect();
+                format!("{{{}}}", fields.join(", "))
+            }),
+            Type::Array(element_type) => self
+                .declared_name(type_)
+                .unwrap_or_else(|| format!("array of {}", self.type_id_name(*element_type))),
+        }
+    }
+
+    fn declared_name(&self, type_: &Type) -> Option<String> {
+        self.types
+            .iter()
+            .filter(|(type_id, tp)| *tp == type_ && self.type_names.contains_key(type_id))
+            .min_by_key(|(type_id, _)| type_id.0)
+            .map(|(type_id, _)| self.type_names[type_id].clone())
+    }
+
+    fn type_id_name(&self, type_id: TypeId) -> String {
+        match self.type_names.get(&type_id) {
+            Some(name) => name.clone(),
+            None => "?".to_string(),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
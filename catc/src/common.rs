@@ -1,8 +1,9 @@
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use std::convert::TryInto;
 use std::iter::Iterator;
+use std::str::FromStr;
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, PartialOrd, Ord)]
 pub enum Label {
     Uid(u64),
     Allocate,
@@ -11,9 +12,57 @@ pub enum Label {
     PrintlnString,
     PrintInt,
     PrintString,
+    IndexOutOfBounds,
+    StringCompare,
+    StringConcat,
+    Size,
+    Substring,
+    Ord,
+    Chr,
+    Not,
+    ReadInt,
+    ReadLine,
+    Flush,
+    Exit,
+    IntegerOverflow,
+    DivisionByZero,
+    // The runtime's shadow stack of frames which hold pointers
+    GcFrames,
+    // Lowered to a load of the array's header rather than a call
+    ArrayLength,
     Main,
 }
 
+/*
+ * Every label but the generated ones, for reading labels back from their
+ * names. A new variant has to be added here as well as to Display.
+ */
+const NAMED_LABELS: [Label; 23] = [
+    Label::Allocate,
+    Label::AllocateAndMemset,
+    Label::PrintlnInt,
+    Label::PrintlnString,
+    Label::PrintInt,
+    Label::PrintString,
+    Label::IndexOutOfBounds,
+    Label::StringCompare,
+    Label::StringConcat,
+    Label::Size,
+    Label::Substring,
+    Label::Ord,
+    Label::Chr,
+    Label::Not,
+    Label::ReadInt,
+    Label::ReadLine,
+    Label::Flush,
+    Label::Exit,
+    Label::IntegerOverflow,
+    Label::DivisionByZero,
+    Label::GcFrames,
+    Label::ArrayLength,
+    Label::Main,
+];
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct LabelGenerator {
     next_uid: u64,
@@ -33,7 +82,7 @@ impl LabelGenerator {
             next_uid: vec
                 .iter()
                 .filter_map(|l| match l {
-                    Label::Uid(uid) => Some(*uid),
+                    Label::Uid(uid) => Some(*uid + 1),
                     _ => None,
                 })
                 .max_by(|l1, l2| l1.cmp(l2))
@@ -59,17 +108,19 @@ pub enum ComparisonType {
     LessThanEqual,
 }
 
-#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, Serialize, Deserialize)]
 pub enum InfixOp {
     Multiply,
     Divide,
+    // The remainder of truncating division, with the sign of the dividend
+    Modulo,
     Add,
     Subtract,
     And,
     Or,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, PartialOrd, Ord)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Symbol {
     uid: u64,
 }
@@ -92,7 +143,7 @@ impl SymbolGenerator {
         SymbolGenerator {
             next_uid: vec
                 .iter()
-                .map(|sym| sym.uid)
+                .map(|sym| sym.uid + 1)
                 .max_by(|l1, l2| l1.cmp(&l2))
                 .unwrap_or(0),
         }
@@ -105,13 +156,29 @@ impl fmt::Display for Label {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Label::Uid(u) => write!(f, "L{}", u),
-            Label::Allocate => write!(f, "_allocate"),
-            Label::AllocateAndMemset => write!(f, "_allocate_and_memset"),
-            Label::PrintlnInt => write!(f, "_print_line_int"),
-            Label::PrintlnString => write!(f, "_print_line_string"),
-            Label::PrintInt => write!(f, "_print_int"),
-            Label::PrintString => write!(f, "_print_string"),
-            Label::Main => write!(f, "_main"),
+            Label::Allocate => write!(f, "allocate"),
+            Label::AllocateAndMemset => write!(f, "allocate_and_memset"),
+            Label::PrintlnInt => write!(f, "print_line_int"),
+            Label::PrintlnString => write!(f, "print_line_string"),
+            Label::PrintInt => write!(f, "print_int"),
+            Label::PrintString => write!(f, "print_string"),
+            Label::IndexOutOfBounds => write!(f, "index_out_of_bounds"),
+            Label::StringCompare => write!(f, "string_compare"),
+            Label::StringConcat => write!(f, "string_concat"),
+            Label::Size => write!(f, "size"),
+            Label::Substring => write!(f, "substring"),
+            Label::Ord => write!(f, "ord"),
+            Label::Chr => write!(f, "chr"),
+            Label::Not => write!(f, "not"),
+            Label::ReadInt => write!(f, "read_int"),
+            Label::ReadLine => write!(f, "read_line"),
+            Label::Flush => write!(f, "flush"),
+            Label::Exit => write!(f, "exit_program"),
+            Label::IntegerOverflow => write!(f, "integer_overflow"),
+            Label::DivisionByZero => write!(f, "division_by_zero"),
+            Label::GcFrames => write!(f, "gc_frames"),
+            Label::ArrayLength => write!(f, "array_length"),
+            Label::Main => write!(f, "main"),
         }
     }
 }
@@ -122,10 +189,71 @@ impl fmt::Display for Symbol {
     }
 }
 
+impl FromStr for Label {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let uid = name.strip_prefix('L').and_then(|uid| uid.parse().ok());
+        match uid {
+            Some(uid) => Ok(Label::Uid(uid)),
+            None => NAMED_LABELS
+                .iter()
+                .find(|label| label.to_string() == name)
+                .copied()
+                .ok_or_else(|| format!("unknown label `{}`", name)),
+        }
+    }
+}
+
+impl FromStr for Symbol {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.strip_prefix('S').and_then(|uid| uid.parse().ok()) {
+            Some(uid) => Ok(Symbol { uid }),
+            None => Err(format!("unknown symbol `{}`", name)),
+        }
+    }
+}
+
+/*
+ * Labels and symbols are serialized by name, as they are displayed, so that
+ * they can be map keys in any format and stay readable in a stage written
+ * out to be edited.
+ */
+impl Serialize for Label {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Label {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for Symbol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Symbol {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Copy, Clone, Deserialize, Serialize)]
 pub enum InfixSourceOp {
     Multiply,
     Divide,
+    Modulo,
     Add,
     Subtract,
     Equal,
@@ -136,6 +264,7 @@ pub enum InfixSourceOp {
     LessThanEqual,
     And,
     Or,
+    Concat,
 }
 
 impl TryInto<InfixOp> for InfixSourceOp {
@@ -145,6 +274,7 @@ impl TryInto<InfixOp> for InfixSourceOp {
         match self {
             Self::Multiply => Ok(InfixOp::Multiply),
             Self::Divide => Ok(InfixOp::Divide),
+            Self::Modulo => Ok(InfixOp::Modulo),
             Self::Add => Ok(InfixOp::Add),
             Self::Subtract => Ok(InfixOp::Subtract),
             Self::Equal => Err(()),
@@ -155,6 +285,7 @@ impl TryInto<InfixOp> for InfixSourceOp {
             Self::LessThanEqual => Err(()),
             Self::And => Ok(InfixOp::And),
             Self::Or => Ok(InfixOp::Or),
+            Self::Concat => Err(()),
         }
     }
 }
@@ -166,6 +297,7 @@ impl TryInto<ComparisonType> for InfixSourceOp {
         match self {
             Self::Multiply => Err(()),
             Self::Divide => Err(()),
+            Self::Modulo => Err(()),
             Self::Add => Err(()),
             Self::Subtract => Err(()),
             Self::Equal => Ok(ComparisonType::Equal),
@@ -176,6 +308,7 @@ impl TryInto<ComparisonType> for InfixSourceOp {
             Self::LessThanEqual => Ok(ComparisonType::LessThanEqual),
             Self::And => Err(()),
             Self::Or => Err(()),
+            Self::Concat => Err(()),
         }
     }
 }
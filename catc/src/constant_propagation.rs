@@ -0,0 +1,339 @@
+/*
+ * Sparse conditional constant propagation
+ *
+ * Finds the symbols of a function in SSA form that hold the same integer
+ * however control reaches them, and the edges control can't take when they
+ * do, following Wegman and Zadeck. Symbols start out unknown and blocks
+ * unreached, and both only ever move one way: a block is visited once some
+ * edge into it can be taken, and an instruction is visited again whenever a
+ * symbol it reads changes.
+ *
+ * Constant symbols are then assigned with IntLits, conditional jumps on
+ * constants become jumps or go away, and unreachable blocks are removed.
+ */
+use crate::common::{Comparison, ComparisonType, InfixOp, Label, Symbol, SymbolGenerator};
+use crate::lir::LIRInstruction;
+use crate::ssa::{assigned, uses, SSAFunction};
+
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Value {
+    // Not assigned on any path found so far
+    Unknown,
+    Constant(i64),
+    // Can hold different values
+    Varying,
+}
+
+fn meet(left: Value, right: Value) -> Value {
+    match (left, right) {
+        (Value::Unknown, value) | (value, Value::Unknown) => value,
+        (Value::Constant(l), Value::Constant(r)) if l == r => Value::Constant(l),
+        _ => Value::Varying,
+    }
+}
+
+/*
+ * Fold an operation on constants the way the generated code computes it.
+ * Operations that overflow or divide by zero aren't folded, so that they
+ * still trap at runtime where they would have.
+ */
+fn fold(op: InfixOp, left: i64, right: i64) -> Option<i64> {
+    match op {
+        InfixOp::Multiply => left.checked_mul(right),
+        InfixOp::Divide => left.checked_div(right),
+        InfixOp::Modulo => left.checked_rem(right),
+        InfixOp::Add => left.checked_add(right),
+        InfixOp::Subtract => left.checked_sub(right),
+        InfixOp::And => Some(left & right),
+        InfixOp::Or => Some(left | right),
+    }
+}
+
+fn compare(c: ComparisonType, left: i64, right: i64) -> bool {
+    match c {
+        ComparisonType::Equal => left == right,
+        ComparisonType::NotEqual => left != right,
+        ComparisonType::GreaterThan => left > right,
+        ComparisonType::LessThan => left < right,
+        ComparisonType::GreaterThanEqual => left >= right,
+        ComparisonType::LessThanEqual => left <= right,
+    }
+}
+
+/*
+ * Propagate constants
+ *
+ * A Pass which folds what sparse conditional constant propagation proves
+ * constant and removes the blocks it proves unreachable.
+ */
+pub fn propagate_constants(function: &mut SSAFunction, _symbol_gen: &mut SymbolGenerator) {
+    let mut propagator = Propagator::new(function);
+    propagator.run();
+    let Propagator { values, .. } = propagator;
+    let value = |symbol: &Symbol| values.get(symbol).copied().unwrap_or(Value::Varying);
+
+    for block in function.blocks.iter_mut() {
+        // A constant phi becomes an IntLit at the top of its block
+        let mut instructions = vec![];
+        block.phis.retain(|phi| match value(&phi.assign_to) {
+            Value::Constant(constant) => {
+                instructions.push(LIRInstruction::IntLit {
+                    assign_to: phi.assign_to,
+                    value: constant,
+                });
+                false
+            }
+            _ => true,
+        });
+
+        for instruction in block.instructions.drain(..) {
+            let condition = match &instruction {
+                LIRInstruction::JumpC { condition, .. } => {
+                    Some(evaluate_comparison(condition, &value))
+                }
+                _ => None,
+            };
+            match (instruction, condition) {
+                (LIRInstruction::JumpC { to, .. }, Some(Value::Constant(taken))) => {
+                    if taken != 0 {
+                        instructions.push(LIRInstruction::Jump { to });
+                        break;
+                    }
+                }
+                (instruction @ LIRInstruction::Jump { .. }, _) => {
+                    instructions.push(instruction);
+                    break;
+                }
+                (instruction, _) => {
+                    match assigned(&instruction).map(|symbol| (symbol, value(&symbol))) {
+                        Some((assign_to, Value::Constant(constant))) => {
+                            instructions.push(LIRInstruction::IntLit {
+                                assign_to,
+                                value: constant,
+                            })
+                        }
+                        _ => instructions.push(instruction),
+                    }
+                }
+            }
+        }
+        block.instructions = instructions;
+    }
+
+    // Jumps that were resolved leave phis with sources that aren't predecessors
+    function.remove_unreachable_blocks();
+    let predecessors = function.predecessors();
+    for block in function.blocks.iter_mut() {
+        let predecessors = &predecessors[&block.label];
+        for phi in block.phis.iter_mut() {
+            phi.sources
+                .retain(|(label, _)| predecessors.contains(label));
+        }
+    }
+}
+
+fn evaluate_comparison(condition: &Comparison, value: &impl Fn(&Symbol) -> Value) -> Value {
+    match (value(&condition.left), value(&condition.right)) {
+        (Value::Constant(left), Value::Constant(right)) => {
+            Value::Constant(compare(condition.c, left, right) as i64)
+        }
+        (Value::Varying, _) | (_, Value::Varying) => Value::Varying,
+        _ => Value::Unknown,
+    }
+}
+
+/*
+ * Where a symbol is read: a phi or an instruction, by block and position
+ */
+#[derive(Debug, Copy, Clone)]
+enum Site {
+    Phi(usize, usize),
+    Instruction(usize, usize),
+}
+
+struct Propagator<'a> {
+    function: &'a SSAFunction,
+    indices: HashMap<Label, usize>,
+    readers: HashMap<Symbol, Vec<Site>>,
+    // Arguments, and symbols the function never assigns, vary
+    values: HashMap<Symbol, Value>,
+    executable: HashSet<(Label, Label)>,
+    reached: HashSet<Label>,
+    edge_work_list: Vec<(Label, Label)>,
+    symbol_work_list: Vec<Symbol>,
+}
+
+impl<'a> Propagator<'a> {
+    fn new(function: &'a SSAFunction) -> Self {
+        let mut readers: HashMap<Symbol, Vec<Site>> = HashMap::new();
+        let mut values = HashMap::new();
+        for (block_index, block) in function.blocks.iter().enumerate() {
+            for (index, phi) in block.phis.iter().enumerate() {
+                values.insert(phi.assign_to, Value::Unknown);
+                for (_, source) in phi.sources.iter() {
+                    readers
+                        .entry(*source)
+                        .or_default()
+                        .push(Site::Phi(block_index, index));
+                }
+            }
+            for (index, instruction) in block.instructions.iter().enumerate() {
+                values.extend(assigned(instruction).map(|symbol| (symbol, Value::Unknown)));
+                for symbol in uses(instruction) {
+                    readers
+                        .entry(symbol)
+                        .or_default()
+                        .push(Site::Instruction(block_index, index));
+                }
+            }
+        }
+
+        Propagator {
+            function,
+            indices: function.block_indices(),
+            readers,
+            values,
+            executable: HashSet::new(),
+            reached: HashSet::new(),
+            edge_work_list: vec![],
+            symbol_work_list: vec![],
+        }
+    }
+
+    fn value(&self, symbol: &Symbol) -> Value {
+        self.values.get(symbol).copied().unwrap_or(Value::Varying)
+    }
+
+    fn set(&mut self, symbol: Symbol, value: Value) {
+        let old = self.value(&symbol);
+        let new = meet(old, value);
+        if new != old {
+            self.values.insert(symbol, new);
+            self.symbol_work_list.push(symbol);
+        }
+    }
+
+    fn run(&mut self) {
+        let entry = self.function.blocks[0].label;
+        self.reached.insert(entry);
+        self.visit_block(0);
+
+        loop {
+            if let Some((from, to)) = self.edge_work_list.pop() {
+                if !self.executable.insert((from, to)) {
+                    continue;
+                }
+                let block_index = self.indices[&to];
+                for index in 0..self.function.blocks[block_index].phis.len() {
+                    self.visit_phi(block_index, index);
+                }
+                if self.reached.insert(to) {
+                    self.visit_block(block_index);
+                }
+            } else if let Some(symbol) = self.symbol_work_list.pop() {
+                for site in self.readers.get(&symbol).cloned().unwrap_or_default() {
+                    match site {
+                        Site::Phi(block_index, index) => {
+                            if self.is_reached(block_index) {
+                                self.visit_phi(block_index, index)
+                            }
+                        }
+                        Site::Instruction(block_index, index) => {
+                            if self.is_reached(block_index) {
+                                self.visit_instruction(block_index, index)
+                            }
+                        }
+                    }
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn is_reached(&self, block_index: usize) -> bool {
+        self.reached
+            .contains(&self.function.blocks[block_index].label)
+    }
+
+    fn visit_block(&mut self, block_index: usize) {
+        for index in 0..self.function.blocks[block_index].instructions.len() {
+            self.visit_instruction(block_index, index);
+        }
+    }
+
+    // A phi only meets the sources whose edges can be taken
+    fn visit_phi(&mut self, block_index: usize, index: usize) {
+        let block = &self.function.blocks[block_index];
+        let phi = &block.phis[index];
+        let value = phi
+            .sources
+            .iter()
+            .filter(|(label, _)| self.executable.contains(&(*label, block.label)))
+            .fold(Value::Unknown, |value, (_, source)| {
+                meet(value, self.value(source))
+            });
+        self.set(phi.assign_to, value);
+    }
+
+    fn visit_instruction(&mut self, block_index: usize, index: usize) {
+        let instruction = &self.function.blocks[block_index].instructions[index];
+        let value = match instruction {
+            LIRInstruction::Jump { .. } | LIRInstruction::JumpC { .. } => {
+                return self.visit_jumps(block_index)
+            }
+            LIRInstruction::IntLit { value, .. } => Value::Constant(*value),
+            LIRInstruction::Assign { id, .. } => self.value(id),
+            LIRInstruction::Negate { value, .. } => match self.value(value) {
+                Value::Constant(constant) => constant
+                    .checked_neg()
+                    .map_or(Value::Varying, Value::Constant),
+                value => value,
+            },
+            LIRInstruction::BinaryOp {
+                left, op, right, ..
+            } => match (self.value(left), self.value(right)) {
+                (Value::Constant(left), Value::Constant(right)) => {
+                    fold(*op, left, right).map_or(Value::Varying, Value::Constant)
+                }
+                (Value::Varying, _) | (_, Value::Varying) => Value::Varying,
+                _ => Value::Unknown,
+            },
+            _ => Value::Varying,
+        };
+        if let Some(symbol) = assigned(instruction) {
+            self.set(symbol, value);
+        }
+    }
+
+    /*
+     * Follow the jumps at the end of a block in order, taking the edges that
+     * can be taken. Control stops at a jump that is always taken, or at a
+     * condition not known yet.
+     */
+    fn visit_jumps(&mut self, block_index: usize) {
+        let block = &self.function.blocks[block_index];
+        for instruction in block.instructions.iter() {
+            match instruction {
+                LIRInstruction::Jump { to } => {
+                    self.edge_work_list.push((block.label, *to));
+                    return;
+                }
+                LIRInstruction::JumpC { to, condition } => {
+                    match evaluate_comparison(condition, &|symbol| self.value(symbol)) {
+                        Value::Constant(0) => (),
+                        Value::Constant(_) => {
+                            self.edge_work_list.push((block.label, *to));
+                            return;
+                        }
+                        Value::Varying => self.edge_work_list.push((block.label, *to)),
+                        Value::Unknown => return,
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+}
@@ -49,7 +49,7 @@ impl GenKill for LIRAssembly {
                 location,
                 offset,
                 value,
-            }) => vec![value],
+            }) => vec![location, offset, value],
             LIRAssembly::Instruction(LoadFromMemoryAtOffset {
                 assign_to,
                 location,
@@ -85,11 +85,8 @@ impl GenKill for LIRAssembly {
             LIRAssembly::Instruction(Nop) => vec![],
             LIRAssembly::Instruction(IntLit { assign_to, value }) => vec![assign_to],
             LIRAssembly::Instruction(StringLit { assign_to, value }) => vec![assign_to],
-            LIRAssembly::Instruction(StoreToMemoryAtOffset {
-                location,
-                offset,
-                value,
-            }) => vec![location, offset],
+            // A store writes memory, not its symbols
+            LIRAssembly::Instruction(StoreToMemoryAtOffset { .. }) => vec![],
             LIRAssembly::Instruction(LoadFromMemoryAtOffset {
                 assign_to,
                 location,
@@ -247,6 +244,14 @@ impl GenKill for X64SAssembly {
                 op_code: X64opCode::Jle,
                 ..
             })
+            | X64SAssembly::Instruction(X64SInstruction {
+                op_code: X64opCode::Jo,
+                ..
+            })
+            | X64SAssembly::Instruction(X64SInstruction {
+                op_code: X64opCode::Cqto,
+                ..
+            })
             | X64SAssembly::Instruction(X64SInstruction {
                 op_code: X64opCode::Ret,
                 ..
@@ -363,6 +368,14 @@ impl GenKill for X64SAssembly {
                 op_code: X64opCode::Jle,
                 ..
             })
+            | X64SAssembly::Instruction(X64SInstruction {
+                op_code: X64opCode::Jo,
+                ..
+            })
+            | X64SAssembly::Instruction(X64SInstruction {
+                op_code: X64opCode::Cqto,
+                ..
+            })
             | X64SAssembly::Instruction(X64SInstruction {
                 op_code: X64opCode::Ret,
                 ..
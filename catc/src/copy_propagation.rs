@@ -0,0 +1,105 @@
+/*
+ * Copy propagation
+ *
+ * Lowering reads variables, binds lets and writes lvalues through chains of
+ * Assigns. In SSA form the symbol an Assign writes holds the value of the
+ * symbol it reads wherever it is read, so its readers can read that symbol
+ * instead and the Assign goes away. A phi reading the same symbol along
+ * every edge, or itself, is a copy of that symbol too.
+ */
+use crate::common::{Symbol, SymbolGenerator};
+use crate::lir::LIRInstruction;
+use crate::ssa::{uses_mut, SSAFunction};
+
+use std::collections::HashMap;
+
+/*
+ * Propagate copies
+ *
+ * A Pass which replaces the reads of every copy with reads of what it copies,
+ * repeating since removing copies can leave phis that copy. Copies between a
+ * pointer and a symbol that isn't one are kept, so the collector still finds
+ * every pointer.
+ */
+pub fn propagate_copies(function: &mut SSAFunction, _symbol_gen: &mut SymbolGenerator) {
+    let predecessors = function.predecessors();
+    loop {
+        let mut copies = HashMap::new();
+        for block in function.blocks.iter() {
+            // A phi missing a source leaves its symbol unassigned on that edge
+            for phi in block.phis.iter() {
+                if phi.sources.len() != predecessors[&block.label].len() {
+                    continue;
+                }
+                let mut sources = phi
+                    .sources
+                    .iter()
+                    .map(|(_, source)| *source)
+                    .filter(|source| *source != phi.assign_to);
+                if let Some(first) = sources.next() {
+                    if sources.all(|source| source == first) {
+                        copies.insert(phi.assign_to, first);
+                    }
+                }
+            }
+            for instruction in block.instructions.iter() {
+                if let LIRInstruction::Assign { assign_to, id } = instruction {
+                    copies.insert(*assign_to, *id);
+                }
+            }
+        }
+        let pointers = &function.pointers;
+        copies.retain(|assign_to, id| pointers.contains(assign_to) == pointers.contains(id));
+
+        let copies = originals(&copies);
+        if copies.is_empty() {
+            break;
+        }
+        let original = |symbol: Symbol| copies.get(&symbol).copied().unwrap_or(symbol);
+
+        for block in function.blocks.iter_mut() {
+            block
+                .phis
+                .retain(|phi| !copies.contains_key(&phi.assign_to));
+            for phi in block.phis.iter_mut() {
+                for (_, source) in phi.sources.iter_mut() {
+                    *source = original(*source);
+                }
+            }
+            block.instructions.retain(|instruction| {
+                !matches!(instruction, LIRInstruction::Assign { assign_to, .. }
+                    if copies.contains_key(assign_to))
+            });
+            for instruction in block.instructions.iter_mut() {
+                for used in uses_mut(instruction) {
+                    *used = original(*used);
+                }
+            }
+        }
+        function.return_symbol = original(function.return_symbol);
+    }
+}
+
+/*
+ * Follow each copy back through the copies it reads to the symbol it
+ * started from. Phis copying each other in a cycle only happen where control
+ * never reaches, and are left alone.
+ */
+fn originals(copies: &HashMap<Symbol, Symbol>) -> HashMap<Symbol, Symbol> {
+    let mut originals = HashMap::new();
+    for symbol in copies.keys() {
+        let mut original = copies[symbol];
+        let mut steps = 0;
+        while let Some(next) = copies.get(&original) {
+            original = *next;
+            steps += 1;
+            if steps > copies.len() {
+                break;
+            }
+        }
+        if !copies.contains_key(&original) {
+            originals.insert(*symbol, original);
+        }
+    }
+    originals
+}
@@ -0,0 +1,116 @@
+/*
+ * Dead code elimination
+ *
+ * Removes the instructions of an LIRFunction whose only effect is to assign
+ * a symbol that isn't live afterwards, using the liveness analysis of the
+ * control flow graph. Removing one can make the instructions computing its
+ * operands dead in turn, so this repeats until nothing more goes.
+ */
+use crate::common::InfixOp;
+use crate::control_flow_graph::{construct_control_flow_graph_lir, liveness, Node};
+use crate::lir::{LIRAssembly, LIRFunction, LIRInstruction};
+use crate::lowering::collect_locals;
+
+use std::collections::HashSet;
+
+/*
+ * Eliminate dead code
+ *
+ * trap_overflow: arithmetic that can overflow exits the program, so it is
+ * kept even when its result is never read.
+ */
+pub fn eliminate_dead_code(function: &mut LIRFunction, trap_overflow: bool) {
+    loop {
+        let dead = dead_instructions(function, trap_overflow);
+        if dead.is_empty() {
+            break;
+        }
+        let listing = std::mem::take(&mut function.instruction_listing);
+        function.instruction_listing = listing
+            .into_iter()
+            .enumerate()
+            .filter(|(index, _)| !dead.contains(index))
+            .map(|(_, assembly)| assembly)
+            .collect();
+    }
+
+    // Symbols no longer assigned are no longer locals
+    function.locals = collect_locals(&function.instruction_listing, &function.arguments);
+    let symbols: HashSet<_> = function
+        .arguments
+        .iter()
+        .chain(function.locals.iter())
+        .collect();
+    function.pointers.retain(|symbol| symbols.contains(symbol));
+}
+
+/*
+ * Whether an instruction does anything besides assigning its kill set.
+ * Calls and stores do, and so do jumps, which assign nothing.
+ */
+fn is_pure(instruction: &LIRInstruction, trap_overflow: bool) -> bool {
+    match instruction {
+        LIRInstruction::Nop
+        | LIRInstruction::IntLit { .. }
+        | LIRInstruction::StringLit { .. }
+        | LIRInstruction::LoadFromMemoryAtOffset { .. }
+        | LIRInstruction::Assign { .. } => true,
+        LIRInstruction::Negate { .. } => !trap_overflow,
+        // Division checks its divisor before it gets here
+        LIRInstruction::BinaryOp { op, .. } => match op {
+            InfixOp::Add | InfixOp::Subtract | InfixOp::Multiply => !trap_overflow,
+            InfixOp::Divide | InfixOp::Modulo | InfixOp::And | InfixOp::Or => true,
+        },
+        LIRInstruction::StoreToMemoryAtOffset { .. }
+        | LIRInstruction::Call { .. }
+        | LIRInstruction::Jump { .. }
+        | LIRInstruction::JumpC { .. } => false,
+    }
+}
+
+fn dead_instructions(function: &LIRFunction, trap_overflow: bool) -> HashSet<usize> {
+    // The caller reads the return symbol once control falls off the end,
+    // which a read at the end of the listing stands in for
+    let mut listing = function.instruction_listing.clone();
+    listing.push(LIRAssembly::Instruction(LIRInstruction::Assign {
+        assign_to: function.return_symbol,
+        id: function.return_symbol,
+    }));
+    let cfg = liveness(construct_control_flow_graph_lir(&LIRFunction {
+        locals: vec![],
+        arguments: vec![],
+        return_symbol: function.return_symbol,
+        instruction_listing: listing,
+        pointers: HashSet::new(),
+    }));
+
+    let mut dead = HashSet::new();
+    for node in cfg.node_indices() {
+        let index = match cfg.node_weight(node) {
+            Node::Index(index) if index < function.instruction_listing.len() => index,
+            _ => continue,
+        };
+        match &function.instruction_listing[index] {
+            LIRAssembly::Instruction(instruction) if is_pure(instruction, trap_overflow) => (),
+            _ => continue,
+        }
+
+        // The data of a node holds what is live into it and out of it, and
+        // what is live into a node is what it reads and what is live out of
+        // it that it doesn't assign
+        let mut live_out = HashSet::new();
+        for successor in cfg.succ(node) {
+            let kill = cfg.kill_node(successor);
+            live_out.extend(cfg.gen_node(successor));
+            live_out.extend(
+                cfg.node_data(successor)
+                    .into_iter()
+                    .filter(|symbol| !kill.contains(symbol)),
+            );
+        }
+        if cfg.kill_node(node).is_disjoint(&live_out) {
+            dead.insert(index);
+        }
+    }
+    dead
+}
@@ -0,0 +1,194 @@
+use lalrpop_util::ParseError;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/*
+ * A range of byte offsets into the source file, as produced by the parser's
+ * @L and @R locations.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+}
+
+/*
+ * An error found in a Cat program. Besides the message it records where the
+ * error happened, the expected and found types (by name) for mismatches, the
+ * identifier (or token) involved, if any, and notes printed below the source.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Span,
+    pub expected: Option<String>,
+    pub found: Option<String>,
+    pub identifier: Option<String>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new<S: Into<String>>(message: S, span: Span) -> Self {
+        Diagnostic {
+            message: message.into(),
+            span,
+            expected: None,
+            found: None,
+            identifier: None,
+            notes: vec![],
+        }
+    }
+
+    /*
+     * Convert an error from the LALRPOP generated parser. Integer literals
+     * that don't fit are already diagnostics, raised as user errors.
+     */
+    pub fn from_parse_error<T: fmt::Display>(
+        error: ParseError<usize, T, Diagnostic>,
+        source: &str,
+    ) -> Self {
+        match error {
+            ParseError::InvalidToken { location } => {
+                let text: String = source[location..].chars().take(1).collect();
+                Diagnostic::new(
+                    format!("invalid token `{}`", text),
+                    Span::new(location, location + text.len()),
+                )
+                .with_identifier(&text)
+            }
+            ParseError::UnrecognizedEOF { location, expected } => {
+                Diagnostic::new("unexpected end of file", Span::new(location, location))
+                    .with_expected_tokens(&expected)
+            }
+            ParseError::UnrecognizedToken {
+                token: (start, token, end),
+                expected,
+            } => Diagnostic::new(
+                format!("unexpected token `{}`", token),
+                Span::new(start, end),
+            )
+            .with_identifier(&token.to_string())
+            .with_expected_tokens(&expected),
+            ParseError::ExtraToken {
+                token: (start, token, end),
+            } => Diagnostic::new(format!("extra token `{}`", token), Span::new(start, end))
+                .with_identifier(&token.to_string()),
+            ParseError::User { error } => error,
+        }
+    }
+
+    pub fn with_types(mut self, expected: String, found: String) -> Self {
+        self.expected = Some(expected);
+        self.found = Some(found);
+        self
+    }
+
+    pub fn with_identifier(mut self, identifier: &str) -> Self {
+        self.identifier = Some(identifier.to_string());
+        self
+    }
+
+    pub fn with_note(mut self, note: String) -> Self {
+        self.notes.push(note);
+        self
+    }
+
+    fn with_expected_tokens(self, expected: &[String]) -> Self {
+        let expected: Vec<String> = expected.iter().map(|t| describe_token(t)).collect();
+        match expected.len() {
+            0 => self,
+            1 => self.with_note(format!("expected {}", expected[0])),
+            _ => self.with_note(format!("expected one of {}", expected.join(", "))),
+        }
+    }
+
+    fn label(&self) -> Option<String> {
+        match (&self.expected, &self.found) {
+            (Some(expected), Some(found)) => {
+                Some(format!("expected `{}`, found `{}`", expected, found))
+            }
+            _ => None,
+        }
+    }
+
+    /*
+     * Render the diagnostic the way rustc does:
+     *
+     * error: mismatched types
+     *  --> file.cat:3:24
+     *   |
+     * 3 |     let var a : int := "x" in
+     *   |                        ^^^ expected `int`, found `string`
+     */
+    pub fn render(&self, file_name: &str, source: &str) -> String {
+        let start = self.span.start.min(source.len());
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |i| start + i);
+        let line_number = source[..start].matches('\n').count() + 1;
+        let line = &source[line_start..line_end];
+
+        let column = source[line_start..start].chars().count();
+        let width = source[start..line_end]
+            .char_indices()
+            .take_while(|(i, _)| start + i < self.span.end)
+            .count()
+            .max(1);
+
+        let gutter = " ".repeat(line_number.to_string().len());
+        let padding: String = source[line_start..start]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        let mut rendered = format!("error: {}\n", self.message);
+        rendered.push_str(&format!(
+            "{}--> {}:{}:{}\n",
+            gutter,
+            file_name,
+            line_number,
+            column + 1
+        ));
+        rendered.push_str(&format!("{} |\n", gutter));
+        rendered.push_str(&format!("{} | {}\n", line_number, line));
+        rendered.push_str(&format!("{} | {}{}", gutter, padding, "^".repeat(width)));
+        if let Some(label) = self.label() {
+            rendered.push_str(&format!(" {}", label));
+        }
+        rendered.push('\n');
+        for note in self.notes.iter() {
+            rendered.push_str(&format!("{} = note: {}\n", gutter, note));
+        }
+        rendered
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(label) = self.label() {
+            write!(f, ": {}", label)?;
+        }
+        write!(f, " at {}..{}", self.span.start, self.span.end)
+    }
+}
+
+/*
+ * LALRPOP names terminals the way they are written in the grammar, in
+ * quotes. Show punctuation and keywords in backticks, and the identifier and
+ * literal terminals as plain words.
+ */
+fn describe_token(terminal: &str) -> String {
+    let name = terminal.trim_matches('"');
+    match name {
+        "identifier" | "integer literal" | "string literal" => name.to_string(),
+        _ => format!("`{}`", name),
+    }
+}
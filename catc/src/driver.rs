@@ -0,0 +1,155 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/*
+ * The runtime as a static library, built from src/runtime.c by the build
+ * script and shipped inside the compiler.
+ */
+const RUNTIME_LIBRARY: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/libcatrt.a"));
+
+/*
+ * How far the driver takes a compiled program
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OutputKind {
+    Assembly,
+    Object,
+    Executable,
+}
+
+impl OutputKind {
+    pub fn default_output(self) -> &'static str {
+        match self {
+            OutputKind::Assembly => "a.s",
+            OutputKind::Object => "a.o",
+            OutputKind::Executable => "a.out",
+        }
+    }
+}
+
+/*
+ * Build
+ *
+ * Write the assembly to output, or assemble it with the system assembler and,
+ * for an executable, link it against the runtime with the system C compiler.
+ * The tools can be overridden with the AS and CC environment variables.
+ */
+pub fn build(assembly: &str, kind: OutputKind, output: &Path) -> io::Result<()> {
+    if kind == OutputKind::Assembly {
+        return fs::write(output, assembly);
+    }
+
+    let scratch = Scratch::new()?;
+    let assembly_file = scratch.path("program.s");
+    fs::write(&assembly_file, assembly)?;
+
+    // Assemble straight to the output unless there is still linking to do
+    let object_file = match kind {
+        OutputKind::Object => output.to_path_buf(),
+        _ => scratch.path("program.o"),
+    };
+    run_tool(
+        Command::new(tool("AS", "as"))
+            .arg(&assembly_file)
+            .arg("-o")
+            .arg(&object_file),
+    )?;
+
+    if kind == OutputKind::Executable {
+        let runtime_file = scratch.path("libcatrt.a");
+        fs::write(&runtime_file, RUNTIME_LIBRARY)?;
+        run_tool(
+            Command::new(tool("CC", "cc"))
+                .arg(&object_file)
+                .arg(&runtime_file)
+                .arg("-o")
+                .arg(output),
+        )?;
+    }
+
+    Ok(())
+}
+
+/*
+ * Run
+ *
+ * Build an executable, at output if one is given and in a scratch directory
+ * otherwise, and run it. Returns its exit status, or 128 plus the signal
+ * number if a signal killed it, as a shell would.
+ */
+pub fn run(assembly: &str, output: Option<&Path>) -> io::Result<i32> {
+    let scratch = Scratch::new()?;
+    let executable = match output {
+        Some(output) => output.to_path_buf(),
+        None => scratch.path("a.out"),
+    };
+    build(assembly, OutputKind::Executable, &executable)?;
+
+    // A bare file name would be looked up on the PATH
+    let executable = if executable.is_relative() {
+        Path::new(".").join(executable)
+    } else {
+        executable
+    };
+    let status = Command::new(&executable).status()?;
+    Ok(status
+        .code()
+        .unwrap_or_else(|| 128 + status.signal().unwrap_or(0)))
+}
+
+fn tool(variable: &str, default: &str) -> String {
+    env::var(variable).unwrap_or_else(|_| default.to_string())
+}
+
+fn run_tool(command: &mut Command) -> io::Result<()> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let status = command.status().map_err(|error| {
+        io::Error::new(
+            error.kind(),
+            format!("could not run `{}`: {}", program, error),
+        )
+    })?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "`{}` failed with {}",
+            program, status
+        )))
+    }
+}
+
+// Numbers the scratch directories this process makes, which may overlap
+static SCRATCH_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/*
+ * A directory for intermediate files, removed with everything in it when
+ * dropped.
+ */
+struct Scratch {
+    directory: PathBuf,
+}
+
+impl Scratch {
+    fn new() -> io::Result<Self> {
+        let count = SCRATCH_COUNT.fetch_add(1, Ordering::Relaxed);
+        let directory = env::temp_dir().join(format!("catc-{}-{}", process::id(), count));
+        fs::create_dir_all(&directory)?;
+        Ok(Scratch { directory })
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.directory.join(name)
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.directory);
+    }
+}
@@ -2,12 +2,15 @@ use crate::checked_grammar::CheckedDec;
 use crate::checked_grammar::{CheckedExp, CheckedLValue, CheckedProgram, CheckedTopLevelDec};
 use crate::common::{InfixSourceOp, Label, Symbol};
 use std::collections::HashMap;
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
+use std::io::{self, Write};
 use std::iter;
+use std::process;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Value {
     Void,
+    Nil,
     Str(String),
     Int(i64),
     Array(Vec<Value>),
@@ -19,8 +22,8 @@ fn find_function(prog: &CheckedProgram, name: Label) -> &CheckedTopLevelDec {
         .dec_list
         .iter()
         .filter_map(|f| match f {
-            dec @ CheckedTopLevelDec::FunDec { .. } => {
-                if name == Label::Main {
+            dec @ CheckedTopLevelDec::FunDec { name: fun_name, .. } => {
+                if *fun_name == name {
                     Some(dec)
                 } else {
                     None
@@ -34,11 +37,18 @@ fn find_function(prog: &CheckedProgram, name: Label) -> &CheckedTopLevelDec {
 
 pub fn eval(prog: &CheckedProgram) -> Value {
     let main = find_function(prog, Label::Main);
-    eval_fn(prog, main, vec![])
+    eval_fn(prog, main, vec![], false)
+}
+
+// Evaluate as if compiled with --trap-overflow
+pub fn eval_trapping_overflow(prog: &CheckedProgram) -> Value {
+    let main = find_function(prog, Label::Main);
+    eval_fn(prog, main, vec![], true)
 }
 
 pub struct Env {
     values: HashMap<Symbol, Value>,
+    trap_overflow: bool,
 }
 
 impl Env {
@@ -54,28 +64,45 @@ impl Env {
     }
 }
 
-pub fn eval_fn(prog: &CheckedProgram, fun: &CheckedTopLevelDec, arguments: Vec<Value>) -> Value {
+pub fn eval_fn(
+    prog: &CheckedProgram,
+    fun: &CheckedTopLevelDec,
+    arguments: Vec<Value>,
+    trap_overflow: bool,
+) -> Value {
     match fun {
         CheckedTopLevelDec::FunDec { args, body, .. } => {
             assert_eq!(args.len(), arguments.len());
             let mut env = Env {
-                values: args.iter().copied().zip(arguments.into_iter()).collect(),
+                values: args.iter().copied().zip(arguments).collect(),
+                trap_overflow,
             };
             eval_exp(prog, &mut env, body).expect("Break broke out of function improperly")
         }
     }
 }
 
+// The same check, and message, as the runtime's index_out_of_bounds
+fn checked_index(index: i64, length: usize) -> usize {
+    match usize::try_from(index) {
+        Ok(i) if i < length => i,
+        _ => panic!(
+            "index out of bounds: the length is {} but the index is {}",
+            length, index
+        ),
+    }
+}
+
 fn eval_lvalue(prog: &CheckedProgram, env: &mut Env, exp: &CheckedLValue) -> Value {
     match exp {
         CheckedLValue::Id { name } => dbg!(env.get(*name)),
         CheckedLValue::Subscript { array, index } => {
-            let index: usize = match eval_exp(prog, env, index).expect("Broke from array index") {
-                Value::Int(index) => index.try_into().unwrap(),
+            let index = match eval_exp(prog, env, index).expect("Broke from array index") {
+                Value::Int(index) => index,
                 _ => panic!("Subscript index is not an int"),
             };
             match eval_lvalue(prog, env, array) {
-                Value::Array(v) => v.get(index).unwrap().clone(),
+                Value::Array(v) => v[checked_index(index, v.len())].clone(),
                 _ => panic!("Subscripting non array value"),
             }
         }
@@ -102,13 +129,15 @@ fn eval_lvalue_ref<'a>(
     match exp {
         CheckedLValue::Id { name } => env.values.get_mut(name).unwrap(),
         CheckedLValue::Subscript { array, index } => {
-            let i: usize =
-                match eval_exp(prog, env, index).expect("Index had 'break' in assignment") {
-                    Value::Int(i) => i.try_into().unwrap(),
-                    _ => panic!("Non-Int index in array"),
-                };
+            let i = match eval_exp(prog, env, index).expect("Index had 'break' in assignment") {
+                Value::Int(i) => i,
+                _ => panic!("Non-Int index in array"),
+            };
             match eval_lvalue_ref(prog, env, array) {
-                Value::Array(vec) => vec.get_mut(i).expect("Index out of range"),
+                Value::Array(vec) => {
+                    let i = checked_index(i, vec.len());
+                    &mut vec[i]
+                }
                 _ => panic!("Index into non-vec"),
             }
         }
@@ -127,10 +156,96 @@ fn eval_lvalue_ref<'a>(
     }
 }
 
+// The functions implemented in the runtime, or None for a function defined in
+// the program
+fn eval_builtin(function_name: Label, args: &[Value]) -> Option<Value> {
+    let value = match (function_name, args) {
+        (Label::PrintInt, [Value::Int(i)]) => {
+            print!("{}", i);
+            Value::Void
+        }
+        (Label::PrintlnInt, [Value::Int(i)]) => {
+            println!("{}", i);
+            Value::Void
+        }
+        (Label::PrintString, [Value::Str(s)]) => {
+            print!("{}", s);
+            Value::Void
+        }
+        (Label::PrintlnString, [Value::Str(s)]) => {
+            println!("{}", s);
+            Value::Void
+        }
+        (Label::StringCompare, [Value::Str(l), Value::Str(r)]) => Value::Int(l.cmp(r) as i64),
+        (Label::StringConcat, [Value::Str(l), Value::Str(r)]) => Value::Str(format!("{}{}", l, r)),
+        (Label::ArrayLength, [Value::Array(v)]) => Value::Int(v.len() as i64),
+        (Label::Size, [Value::Str(s)]) => Value::Int(s.len() as i64),
+        (Label::Substring, [Value::Str(s), Value::Int(first), Value::Int(length)]) => {
+            let range = (*first as usize)..((first + length) as usize);
+            if *first < 0 || *length < 0 || range.end > s.len() {
+                panic!("substring out of range");
+            }
+            Value::Str(s[range].to_string())
+        }
+        (Label::Ord, [Value::Str(s)]) => Value::Int(s.bytes().next().map_or(-1, i64::from)),
+        (Label::Chr, [Value::Int(code)]) => match u8::try_from(*code) {
+            Ok(code) if (1..=127).contains(&code) => Value::Str((code as char).to_string()),
+            _ => panic!("chr argument is not an ASCII character code"),
+        },
+        (Label::Not, [Value::Int(v)]) => Value::Int((*v == 0) as i64),
+        (Label::ReadInt, []) => Value::Int(
+            read_line()
+                .trim()
+                .parse()
+                .expect("read_int: input is not an integer"),
+        ),
+        (Label::ReadLine, []) => Value::Str(read_line()),
+        (Label::Flush, []) => {
+            io::stdout().flush().unwrap();
+            Value::Void
+        }
+        (Label::Exit, [Value::Int(status)]) => {
+            io::stdout().flush().unwrap();
+            process::exit(*status as i32)
+        }
+        _ => return None,
+    };
+    Some(value)
+}
+
+// A line of standard input without its newline, empty at the end of input
+fn read_line() -> String {
+    let mut line = String::new();
+    io::stdin().read_line(&mut line).unwrap();
+    if line.ends_with('\n') {
+        line.pop();
+    }
+    line
+}
+
+// Ints wrap around on overflow, unless overflow is trapped, in which case this
+// panics with the runtime's message
+fn overflowing((value, overflowed): (i64, bool), trap_overflow: bool) -> i64 {
+    if overflowed && trap_overflow {
+        panic!("integer overflow");
+    }
+    value
+}
+
+// The result of `and` and `or`: 1 for a nonzero int, otherwise 0
+fn truth(value: Value) -> Value {
+    match value {
+        Value::Int(0) => Value::Int(0),
+        Value::Int(_) => Value::Int(1),
+        _ => panic!("Logical operand is not an int"),
+    }
+}
+
 pub fn eval_exp(prog: &CheckedProgram, env: &mut Env, exp: &CheckedExp) -> Option<Value> {
     match exp {
         CheckedExp::Break => None,
-        CheckedExp::IntLit { value } => Some(Value::Int((*value).into())),
+        CheckedExp::Nil => Some(Value::Nil),
+        CheckedExp::IntLit { value } => Some(Value::Int(*value)),
         CheckedExp::StringLit { value } => Some(Value::Str(value.clone())),
         CheckedExp::LValue { lvalue } => Some(eval_lvalue(prog, env, dbg!(lvalue))),
         CheckedExp::Sequence { sequence } => {
@@ -141,7 +256,10 @@ pub fn eval_exp(prog: &CheckedProgram, env: &mut Env, exp: &CheckedExp) -> Optio
             Some(result)
         }
         CheckedExp::Negate { exp } => match eval_exp(prog, env, exp) {
-            Some(Value::Int(value)) => Some(Value::Int(-value)),
+            Some(Value::Int(value)) => Some(Value::Int(overflowing(
+                value.overflowing_neg(),
+                env.trap_overflow,
+            ))),
             None => None,
             _ => panic!("Negate doesn't contain int value"),
         },
@@ -160,7 +278,10 @@ pub fn eval_exp(prog: &CheckedProgram, env: &mut Env, exp: &CheckedExp) -> Optio
             } else {
                 panic!("evaluated right side of infix op to non int type")
             };
-            Some(Value::Int(left_value + right_value))
+            Some(Value::Int(overflowing(
+                left_value.overflowing_add(right_value),
+                env.trap_overflow,
+            )))
         }
         CheckedExp::Infix {
             left,
@@ -177,7 +298,10 @@ pub fn eval_exp(prog: &CheckedProgram, env: &mut Env, exp: &CheckedExp) -> Optio
             } else {
                 panic!("evaluated right side of infix op to non int type")
             };
-            Some(Value::Int(left_value - right_value))
+            Some(Value::Int(overflowing(
+                left_value.overflowing_sub(right_value),
+                env.trap_overflow,
+            )))
         }
         CheckedExp::Infix {
             left,
@@ -194,7 +318,10 @@ pub fn eval_exp(prog: &CheckedProgram, env: &mut Env, exp: &CheckedExp) -> Optio
             } else {
                 panic!("evaluated right side of infix op to non int type")
             };
-            Some(Value::Int(left_value * right_value))
+            Some(Value::Int(overflowing(
+                left_value.overflowing_mul(right_value),
+                env.trap_overflow,
+            )))
         }
         CheckedExp::Infix {
             left,
@@ -211,7 +338,33 @@ pub fn eval_exp(prog: &CheckedProgram, env: &mut Env, exp: &CheckedExp) -> Optio
             } else {
                 panic!("evaluated right side of infix op to non int type")
             };
-            Some(Value::Int(left_value.checked_div(right_value)?))
+            if right_value == 0 {
+                panic!("division by zero")
+            }
+            Some(Value::Int(overflowing(
+                left_value.overflowing_div(right_value),
+                env.trap_overflow,
+            )))
+        }
+        CheckedExp::Infix {
+            left,
+            op: InfixSourceOp::Modulo,
+            right,
+        } => {
+            let left_value = if let Value::Int(v) = eval_exp(prog, env, left)? {
+                v
+            } else {
+                panic!("evaluated left side of infix op to non Int type")
+            };
+            let right_value = if let Value::Int(v) = eval_exp(prog, env, right)? {
+                v
+            } else {
+                panic!("evaluated right side of infix op to non int type")
+            };
+            if right_value == 0 {
+                panic!("division by zero")
+            }
+            Some(Value::Int(left_value.wrapping_rem(right_value)))
         }
         CheckedExp::Infix {
             left,
@@ -347,7 +500,7 @@ pub fn eval_exp(prog: &CheckedProgram, env: &mut Env, exp: &CheckedExp) -> Optio
             if let Value::Int(0) = eval_exp(prog, env, left)? {
                 Some(Value::Int(0))
             } else {
-                eval_exp(prog, env, right)
+                Some(truth(eval_exp(prog, env, right)?))
             }
         }
         CheckedExp::Infix {
@@ -355,15 +508,20 @@ pub fn eval_exp(prog: &CheckedProgram, env: &mut Env, exp: &CheckedExp) -> Optio
             op: InfixSourceOp::Or,
             right,
         } => {
-            if let Value::Int(1) = eval_exp(prog, env, left)? {
-                Some(Value::Int(1))
+            if let Value::Int(0) = eval_exp(prog, env, left)? {
+                Some(truth(eval_exp(prog, env, right)?))
             } else {
-                eval_exp(prog, env, right)
+                Some(Value::Int(1))
             }
         }
+        CheckedExp::Infix {
+            op: InfixSourceOp::Concat,
+            ..
+        } => unreachable!("concatenation is type checked into a call to string_concat"),
         CheckedExp::ArrayCreate {
             length,
             initial_value,
+            ..
         } => {
             let len = if let Value::Int(v) = eval_exp(prog, env, length)? {
                 v
@@ -372,10 +530,10 @@ pub fn eval_exp(prog: &CheckedProgram, env: &mut Env, exp: &CheckedExp) -> Optio
             };
             let init = eval_exp(prog, env, initial_value)?;
             Some(Value::Array(
-                iter::repeat(init).take(len.try_into().unwrap()).collect(),
+                iter::repeat_n(init, len.try_into().unwrap()).collect(),
             ))
         }
-        CheckedExp::RecordCreate { fields } => {
+        CheckedExp::RecordCreate { fields, .. } => {
             let mut f = vec![];
             for (s, v) in fields.iter() {
                 f.push((s.clone(), eval_exp(prog, env, v)?));
@@ -442,6 +600,10 @@ pub fn eval_exp(prog: &CheckedProgram, env: &mut Env, exp: &CheckedExp) -> Optio
                     Some(_) => (),
                     None => break,
                 }
+                // Like the compiled loop, never step past the end value
+                if i == end_value {
+                    break;
+                }
                 env.insert(*id, Value::Int(i + 1))
             }
             Some(Value::Void)
@@ -465,8 +627,13 @@ pub fn eval_exp(prog: &CheckedProgram, env: &mut Env, exp: &CheckedExp) -> Optio
                 .iter()
                 .map(|exp| eval_exp(prog, env, exp))
                 .collect::<Option<_>>()?;
-            let fun = find_function(prog, *function_name);
-            Some(eval_fn(prog, fun, arg_values))
+            match eval_builtin(*function_name, &arg_values) {
+                Some(value) => Some(value),
+                None => {
+                    let fun = find_function(prog, *function_name);
+                    Some(eval_fn(prog, fun, arg_values, env.trap_overflow))
+                }
+            }
         }
     }
 }
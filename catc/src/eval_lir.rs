@@ -1,8 +1,10 @@
 use crate::common::{ComparisonType, InfixOp, Label, Symbol};
 use crate::lir::{LIRAssembly, LIRFunction, LIRInstruction, LIRProgram};
 use std::collections::{HashMap, HashSet};
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
+use std::io::{self, Write};
 use std::iter;
+use std::process;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Value {
@@ -11,15 +13,18 @@ pub enum Value {
     Int(i64),
 }
 
+// Memory is shared by every function call, so arrays and records can be
+// passed between functions. Locations are indices into it.
 #[derive(Debug)]
-pub struct State {
+pub struct State<'a> {
     pc: usize,
     values: HashMap<Symbol, Value>,
     all_symbols: HashSet<Symbol>,
-    memory: Vec<Value>,
+    memory: &'a mut Vec<Value>,
+    trap_overflow: bool,
 }
 
-impl State {
+impl<'a> State<'a> {
     fn insert(&mut self, s: Symbol, v: Value) {
         assert!(self.all_symbols.contains(&s));
         self.values.insert(s, v);
@@ -32,17 +37,20 @@ impl State {
 
     fn allocate(&mut self, size: i64) -> usize {
         assert!(size >= 0);
-        self.allocate_and_memset(Value::Void, size.try_into().unwrap())
+        let allocated_loc = self.memory.len();
+        self.memory
+            .append(&mut iter::repeat_n(Value::Void, size.try_into().unwrap()).collect::<Vec<_>>());
+        allocated_loc
     }
 
+    // Like the runtime, store the length just before the first element and
+    // return the location of the first element
     fn allocate_and_memset(&mut self, value: Value, size: i64) -> usize {
         assert!(size >= 0);
+        self.memory.push(Value::Int(size));
         let allocated_loc = self.memory.len();
-        self.memory.append(
-            &mut iter::repeat(value)
-                .take(size.try_into().unwrap())
-                .collect::<Vec<_>>(),
-        );
+        self.memory
+            .append(&mut iter::repeat_n(value, size.try_into().unwrap()).collect::<Vec<_>>());
         allocated_loc
     }
 
@@ -57,7 +65,16 @@ impl State {
     }
 }
 
-fn find_label(instruction_listing: &Vec<LIRAssembly>, label: Label) -> usize {
+// Ints wrap around on overflow, unless overflow is trapped, in which case this
+// panics with the runtime's message
+fn overflowing((value, overflowed): (i64, bool), trap_overflow: bool) -> i64 {
+    if overflowed && trap_overflow {
+        panic!("integer overflow");
+    }
+    value
+}
+
+fn find_label(instruction_listing: &[LIRAssembly], label: Label) -> usize {
     instruction_listing
         .iter()
         .enumerate()
@@ -70,21 +87,30 @@ fn find_label(instruction_listing: &Vec<LIRAssembly>, label: Label) -> usize {
 }
 
 pub fn eval(lir: &LIRProgram) -> Value {
-    eval_fn(lir, &lir.main_function, vec![])
+    let mut memory = vec![];
+    eval_fn(lir, &lir.main_function, vec![], &mut memory, false)
+}
+
+// Evaluate as if compiled with --trap-overflow
+pub fn eval_trapping_overflow(lir: &LIRProgram) -> Value {
+    let mut memory = vec![];
+    eval_fn(lir, &lir.main_function, vec![], &mut memory, true)
 }
 
-fn eval_fn(lir_prog: &LIRProgram, lir: &LIRFunction, args: Vec<Value>) -> Value {
+fn eval_fn(
+    lir_prog: &LIRProgram,
+    lir: &LIRFunction,
+    args: Vec<Value>,
+    memory: &mut Vec<Value>,
+    trap_overflow: bool,
+) -> Value {
     assert_eq!(lir.arguments.len(), args.len());
     let mut state = State {
         pc: 0,
-        values: lir
-            .arguments
-            .iter()
-            .copied()
-            .zip(args.into_iter())
-            .collect(),
+        values: lir.arguments.iter().copied().zip(args).collect(),
         all_symbols: lir.get_all_symbols().into_iter().collect(),
-        memory: vec![],
+        memory,
+        trap_overflow,
     };
     eval_listing(lir_prog, &lir.instruction_listing, &mut state);
     state
@@ -94,7 +120,7 @@ fn eval_fn(lir_prog: &LIRProgram, lir: &LIRFunction, args: Vec<Value>) -> Value
         .clone()
 }
 
-fn eval_listing(lir: &LIRProgram, instruction_listing: &Vec<LIRAssembly>, state: &mut State) {
+fn eval_listing(lir: &LIRProgram, instruction_listing: &[LIRAssembly], state: &mut State) {
     while state.pc < instruction_listing.len() {
         match instruction_listing.get(state.pc).unwrap() {
             LIRAssembly::Label(_) => state.pc += 1,
@@ -109,6 +135,61 @@ fn eval_listing(lir: &LIRProgram, instruction_listing: &Vec<LIRAssembly>, state:
     }
 }
 
+// The runtime builtins other than allocation and printing, or None for a
+// function defined in the program
+fn eval_builtin(function_name: Label, args: &[Value]) -> Option<Value> {
+    let value = match (function_name, args) {
+        (Label::DivisionByZero, []) => panic!("division by zero"),
+        (Label::IndexOutOfBounds, [Value::Int(index), Value::Int(length)]) => panic!(
+            "index out of bounds: the length is {} but the index is {}",
+            length, index
+        ),
+        (Label::StringCompare, [Value::Str(l), Value::Str(r)]) => Value::Int(l.cmp(r) as i64),
+        (Label::StringConcat, [Value::Str(l), Value::Str(r)]) => Value::Str(format!("{}{}", l, r)),
+        (Label::Size, [Value::Str(s)]) => Value::Int(s.len() as i64),
+        (Label::Substring, [Value::Str(s), Value::Int(first), Value::Int(length)]) => {
+            let range = (*first as usize)..((first + length) as usize);
+            if *first < 0 || *length < 0 || range.end > s.len() {
+                panic!("substring out of range");
+            }
+            Value::Str(s[range].to_string())
+        }
+        (Label::Ord, [Value::Str(s)]) => Value::Int(s.bytes().next().map_or(-1, i64::from)),
+        (Label::Chr, [Value::Int(code)]) => match u8::try_from(*code) {
+            Ok(code) if (1..=127).contains(&code) => Value::Str((code as char).to_string()),
+            _ => panic!("chr argument is not an ASCII character code"),
+        },
+        (Label::Not, [Value::Int(v)]) => Value::Int((*v == 0) as i64),
+        (Label::ReadInt, []) => Value::Int(
+            read_line()
+                .trim()
+                .parse()
+                .expect("read_int: input is not an integer"),
+        ),
+        (Label::ReadLine, []) => Value::Str(read_line()),
+        (Label::Flush, []) => {
+            io::stdout().flush().unwrap();
+            Value::Void
+        }
+        (Label::Exit, [Value::Int(status)]) => {
+            io::stdout().flush().unwrap();
+            process::exit(*status as i32)
+        }
+        _ => return None,
+    };
+    Some(value)
+}
+
+// A line of standard input without its newline, empty at the end of input
+fn read_line() -> String {
+    let mut line = String::new();
+    io::stdin().read_line(&mut line).unwrap();
+    if line.ends_with('\n') {
+        line.pop();
+    }
+    line
+}
+
 fn eval_inst(lir: &LIRProgram, instruction: &LIRInstruction, state: &mut State) -> Option<Label> {
     let mut next_label = None;
     match instruction {
@@ -145,7 +226,7 @@ fn eval_inst(lir: &LIRProgram, instruction: &LIRInstruction, state: &mut State)
         }
         LIRInstruction::Negate { assign_to, value } => {
             let negated = match state.get(*value) {
-                Value::Int(v) => Value::Int(-v),
+                Value::Int(v) => Value::Int(overflowing(v.overflowing_neg(), state.trap_overflow)),
                 _ => panic!("Negating non int"),
             };
             state.insert(*assign_to, negated);
@@ -158,10 +239,11 @@ fn eval_inst(lir: &LIRProgram, instruction: &LIRInstruction, state: &mut State)
         } => {
             let value = match (state.get(*left), state.get(*right)) {
                 (Value::Int(l), Value::Int(r)) => match op {
-                    InfixOp::Multiply => l * r,
-                    InfixOp::Divide => l / r,
-                    InfixOp::Add => l + r,
-                    InfixOp::Subtract => l - r,
+                    InfixOp::Multiply => overflowing(l.overflowing_mul(r), state.trap_overflow),
+                    InfixOp::Divide => overflowing(l.overflowing_div(r), state.trap_overflow),
+                    InfixOp::Modulo => l.wrapping_rem(r),
+                    InfixOp::Add => overflowing(l.overflowing_add(r), state.trap_overflow),
+                    InfixOp::Subtract => overflowing(l.overflowing_sub(r), state.trap_overflow),
                     InfixOp::And => l & r,
                     InfixOp::Or => l | r,
                 },
@@ -174,7 +256,8 @@ fn eval_inst(lir: &LIRProgram, instruction: &LIRInstruction, state: &mut State)
             function_name: Label::Allocate,
             args,
         } => {
-            assert_eq!(args.len(), 1);
+            // The second argument is the pointer map, only the collector needs it
+            assert_eq!(args.len(), 2);
             let size = match state.get(args[0]) {
                 Value::Int(size) => size,
                 _ => panic!("Allocate called with non-int"),
@@ -187,7 +270,8 @@ fn eval_inst(lir: &LIRProgram, instruction: &LIRInstruction, state: &mut State)
             function_name: Label::AllocateAndMemset,
             args,
         } => {
-            assert_eq!(args.len(), 2);
+            // The third argument says whether the elements are pointers
+            assert_eq!(args.len(), 3);
             let size = match state.get(args[0]) {
                 Value::Int(size) => size,
                 _ => panic!("Allocate called with non-int"),
@@ -237,10 +321,13 @@ fn eval_inst(lir: &LIRProgram, instruction: &LIRInstruction, state: &mut State)
             function_name: Label::Main,
             args,
         } => {
+            let arg_values = args.iter().map(|s| state.get(*s)).collect();
             let res = eval_fn(
                 lir,
                 &lir.main_function,
-                args.iter().map(|s| state.get(*s)).collect(),
+                arg_values,
+                state.memory,
+                state.trap_overflow,
             );
             state.insert(*assign_to, res);
         }
@@ -249,13 +336,19 @@ fn eval_inst(lir: &LIRProgram, instruction: &LIRInstruction, state: &mut State)
             function_name,
             args,
         } => {
-            let res = eval_fn(
-                lir,
-                &lir.other_functions
-                    .get(function_name)
-                    .expect("function not found in function call"),
-                args.iter().map(|s| state.get(*s)).collect(),
-            );
+            let arg_values: Vec<_> = args.iter().map(|s| state.get(*s)).collect();
+            let res = match eval_builtin(*function_name, &arg_values) {
+                Some(value) => value,
+                None => eval_fn(
+                    lir,
+                    lir.other_functions
+                        .get(function_name)
+                        .expect("function not found in function call"),
+                    arg_values,
+                    state.memory,
+                    state.trap_overflow,
+                ),
+            };
             state.insert(*assign_to, res);
         }
         LIRInstruction::Jump { to } => next_label = Some(*to),
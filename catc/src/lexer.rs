@@ -0,0 +1,365 @@
+use crate::diagnostic::{Diagnostic, Span};
+use std::fmt;
+
+/*
+ * Tokens of the Cat language. Identifiers and literals borrow their text
+ * from the source; string literals keep their quotes and escapes, which the
+ * parser decodes.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token<'input> {
+    // Keywords
+    And,
+    Array,
+    Break,
+    Do,
+    Else,
+    End,
+    For,
+    Function,
+    If,
+    In,
+    Let,
+    Nil,
+    Of,
+    Or,
+    Then,
+    To,
+    Type,
+    Var,
+    While,
+
+    // Punctuation and operators
+    Arrow,
+    Assign,
+    Colon,
+    Comma,
+    Dot,
+    Semicolon,
+    LeftParen,
+    RightParen,
+    LeftBracket,
+    RightBracket,
+    LeftBrace,
+    RightBrace,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    Caret,
+    Equal,
+    NotEqual,
+    LessThan,
+    LessThanEqual,
+    GreaterThan,
+    GreaterThanEqual,
+
+    Id(&'input str),
+    IntLit(&'input str),
+    StringLit(&'input str),
+}
+
+fn keyword(word: &str) -> Option<Token<'static>> {
+    let token = match word {
+        "and" => Token::And,
+        "array" => Token::Array,
+        "break" => Token::Break,
+        "do" => Token::Do,
+        "else" => Token::Else,
+        "end" => Token::End,
+        "for" => Token::For,
+        "function" => Token::Function,
+        "if" => Token::If,
+        "in" => Token::In,
+        "let" => Token::Let,
+        "nil" => Token::Nil,
+        "of" => Token::Of,
+        "or" => Token::Or,
+        "then" => Token::Then,
+        "to" => Token::To,
+        "type" => Token::Type,
+        "var" => Token::Var,
+        "while" => Token::While,
+        _ => return None,
+    };
+    Some(token)
+}
+
+impl<'input> fmt::Display for Token<'input> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Token::And => "and",
+            Token::Array => "array",
+            Token::Break => "break",
+            Token::Do => "do",
+            Token::Else => "else",
+            Token::End => "end",
+            Token::For => "for",
+            Token::Function => "function",
+            Token::If => "if",
+            Token::In => "in",
+            Token::Let => "let",
+            Token::Nil => "nil",
+            Token::Of => "of",
+            Token::Or => "or",
+            Token::Then => "then",
+            Token::To => "to",
+            Token::Type => "type",
+            Token::Var => "var",
+            Token::While => "while",
+            Token::Arrow => "->",
+            Token::Assign => ":=",
+            Token::Colon => ":",
+            Token::Comma => ",",
+            Token::Dot => ".",
+            Token::Semicolon => ";",
+            Token::LeftParen => "(",
+            Token::RightParen => ")",
+            Token::LeftBracket => "[",
+            Token::RightBracket => "]",
+            Token::LeftBrace => "{",
+            Token::RightBrace => "}",
+            Token::Plus => "+",
+            Token::Minus => "-",
+            Token::Star => "*",
+            Token::Slash => "/",
+            Token::Percent => "%",
+            Token::Caret => "^",
+            Token::Equal => "=",
+            Token::NotEqual => "<>",
+            Token::LessThan => "<",
+            Token::LessThanEqual => "<=",
+            Token::GreaterThan => ">",
+            Token::GreaterThanEqual => ">=",
+            Token::Id(text) | Token::IntLit(text) | Token::StringLit(text) => text,
+        };
+        write!(f, "{}", text)
+    }
+}
+
+pub type Spanned<'input> = (usize, Token<'input>, usize);
+
+/*
+ * Lexer
+ *
+ * Splits source text into tokens with their byte spans, the form LALRPOP
+ * expects from an external lexer. Whitespace and comments are skipped.
+ * Comments are either `// ...` to the end of the line or `/* ... */`, which
+ * nest.
+ */
+pub struct Lexer<'input> {
+    source: &'input str,
+    position: usize,
+}
+
+impl<'input> Lexer<'input> {
+    pub fn new(source: &'input str) -> Self {
+        Lexer {
+            source,
+            position: 0,
+        }
+    }
+
+    fn peek(&self, offset: usize) -> Option<u8> {
+        self.source.as_bytes().get(self.position + offset).copied()
+    }
+
+    fn advance_while<F: Fn(u8) -> bool>(&mut self, predicate: F) {
+        while self.peek(0).is_some_and(&predicate) {
+            self.position += 1;
+        }
+    }
+
+    fn skip_block_comment(&mut self) -> Result<(), Diagnostic> {
+        let start = self.position;
+        let mut depth = 0;
+        loop {
+            match (self.peek(0), self.peek(1)) {
+                (None, _) => {
+                    return Err(Diagnostic::new(
+                        "unterminated block comment",
+                        Span::new(start, start + 2),
+                    ))
+                }
+                (Some(b'/'), Some(b'*')) => {
+                    depth += 1;
+                    self.position += 2;
+                }
+                (Some(b'*'), Some(b'/')) => {
+                    depth -= 1;
+                    self.position += 2;
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                }
+                _ => self.position += 1,
+            }
+        }
+    }
+
+    fn string_literal(&mut self) -> Result<Token<'input>, Diagnostic> {
+        let start = self.position;
+        self.position += 1;
+        loop {
+            match self.peek(0) {
+                None => {
+                    return Err(Diagnostic::new(
+                        "unterminated string literal",
+                        Span::new(start, start + 1),
+                    ))
+                }
+                Some(b'"') => {
+                    self.position += 1;
+                    return Ok(Token::StringLit(&self.source[start..self.position]));
+                }
+                Some(b'\\') if self.peek(1).is_some() => self.position += 2,
+                Some(_) => self.position += 1,
+            }
+        }
+    }
+
+    fn operator(&mut self) -> Option<Token<'input>> {
+        let (token, length) = match (self.peek(0)?, self.peek(1)) {
+            (b'-', Some(b'>')) => (Token::Arrow, 2),
+            (b':', Some(b'=')) => (Token::Assign, 2),
+            (b'<', Some(b'>')) => (Token::NotEqual, 2),
+            (b'<', Some(b'=')) => (Token::LessThanEqual, 2),
+            (b'>', Some(b'=')) => (Token::GreaterThanEqual, 2),
+            (b':', _) => (Token::Colon, 1),
+            (b',', _) => (Token::Comma, 1),
+            (b'.', _) => (Token::Dot, 1),
+            (b';', _) => (Token::Semicolon, 1),
+            (b'(', _) => (Token::LeftParen, 1),
+            (b')', _) => (Token::RightParen, 1),
+            (b'[', _) => (Token::LeftBracket, 1),
+            (b']', _) => (Token::RightBracket, 1),
+            (b'{', _) => (Token::LeftBrace, 1),
+            (b'}', _) => (Token::RightBrace, 1),
+            (b'+', _) => (Token::Plus, 1),
+            (b'-', _) => (Token::Minus, 1),
+            (b'*', _) => (Token::Star, 1),
+            (b'/', _) => (Token::Slash, 1),
+            (b'%', _) => (Token::Percent, 1),
+            (b'^', _) => (Token::Caret, 1),
+            (b'=', _) => (Token::Equal, 1),
+            (b'<', _) => (Token::LessThan, 1),
+            (b'>', _) => (Token::GreaterThan, 1),
+            _ => return None,
+        };
+        self.position += length;
+        Some(token)
+    }
+}
+
+impl<'input> Iterator for Lexer<'input> {
+    type Item = Result<Spanned<'input>, Diagnostic>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Skip whitespace and comments
+        loop {
+            match (self.peek(0)?, self.peek(1)) {
+                (c, _) if c.is_ascii_whitespace() => self.position += 1,
+                (b'/', Some(b'/')) => self.advance_while(|c| c != b'\n'),
+                (b'/', Some(b'*')) => {
+                    if let Err(diagnostic) = self.skip_block_comment() {
+                        return Some(Err(diagnostic));
+                    }
+                }
+                _ => break,
+            }
+        }
+
+        let start = self.position;
+        let c = self.peek(0)?;
+        let token = if c.is_ascii_alphabetic() || c == b'_' {
+            self.advance_while(|c| c.is_ascii_alphanumeric() || c == b'_');
+            let word = &self.source[start..self.position];
+            keyword(word).unwrap_or(Token::Id(word))
+        } else if c.is_ascii_digit() {
+            self.advance_while(|c| c.is_ascii_digit());
+            Token::IntLit(&self.source[start..self.position])
+        } else if c == b'"' {
+            match self.string_literal() {
+                Ok(token) => token,
+                Err(diagnostic) => return Some(Err(diagnostic)),
+            }
+        } else if let Some(token) = self.operator() {
+            token
+        } else {
+            let text: String = self.source[start..].chars().take(1).collect();
+            // Resume after the bad character so the error isn't repeated
+            self.position += text.len();
+            return Some(Err(Diagnostic::new(
+                format!("invalid token `{}`", text),
+                Span::new(start, self.position),
+            )
+            .with_identifier(&text)));
+        };
+
+        Some(Ok((start, token, self.position)))
+    }
+}
+
+/*
+ * Decode a string literal, including its surrounding quotes, as written at
+ * byte offset start. The escapes are \n, \t, \", \\ and \ddd for the ASCII
+ * character with decimal code ddd, other than NUL.
+ */
+pub fn unescape_string(literal: &str, start: usize) -> Result<String, Diagnostic> {
+    let body = &literal[1..literal.len() - 1];
+    let mut value = String::new();
+    let mut chars = body.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+
+        // The escape starts after the opening quote and i bytes of the body
+        let escape_start = start + 1 + i;
+        let escaped = match chars.next() {
+            Some((_, 'n')) => '\n',
+            Some((_, 't')) => '\t',
+            Some((_, '"')) => '"',
+            Some((_, '\\')) => '\\',
+            Some((j, d)) if d.is_ascii_digit() => {
+                let digits = body.get(j..j + 3).unwrap_or("");
+                if digits.len() != 3 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+                    return Err(Diagnostic::new(
+                        "numeric escape must have exactly three digits",
+                        Span::new(escape_start, start + 1 + j + d.len_utf8()),
+                    ));
+                }
+                chars.next();
+                chars.next();
+                let code: u32 = digits.parse().unwrap();
+                // The runtime's strings end at their first NUL
+                if code == 0 {
+                    return Err(Diagnostic::new(
+                        "numeric escape `\\000` is a NUL, which strings can't contain",
+                        Span::new(escape_start, escape_start + 4),
+                    ));
+                }
+                if code > 127 {
+                    return Err(Diagnostic::new(
+                        format!("numeric escape `\\{}` is not an ASCII character", digits),
+                        Span::new(escape_start, escape_start + 4),
+                    ));
+                }
+                std::char::from_u32(code).unwrap()
+            }
+            Some((j, other)) => {
+                return Err(Diagnostic::new(
+                    format!("unknown escape `\\{}`", other),
+                    Span::new(escape_start, start + 1 + j + other.len_utf8()),
+                ))
+            }
+            None => unreachable!("the lexer never ends a string literal with a backslash"),
+        };
+        value.push(escaped);
+    }
+
+    Ok(value)
+}
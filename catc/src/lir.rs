@@ -5,10 +5,10 @@
 
 use serde::{Deserialize, Serialize};
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 
-use crate::common::{Comparison, InfixOp, Label, Symbol};
+use crate::common::{Comparison, InfixOp, Label, LabelGenerator, Symbol, SymbolGenerator};
 
 #[macro_export]
 macro_rules! linst {
@@ -29,6 +29,34 @@ pub struct LIRFunction {
     pub arguments: Vec<Symbol>,
     pub return_symbol: Symbol,
     pub instruction_listing: Vec<LIRAssembly>,
+    // The symbols which hold pointers to the heap, roots for the collector
+    pub pointers: HashSet<Symbol>,
+}
+
+impl LIRProgram {
+    /*
+     * Generators of labels and symbols the program doesn't use yet, for a
+     * program that was read in rather than lowered.
+     */
+    pub fn generators(&self) -> (LabelGenerator, SymbolGenerator) {
+        let functions =
+            || std::iter::once(&self.main_function).chain(self.other_functions.values());
+        let labels = functions()
+            .flat_map(|function| function.instruction_listing.iter())
+            .filter_map(|assembly| match assembly {
+                LIRAssembly::Label(label) => Some(*label),
+                _ => None,
+            })
+            .chain(self.other_functions.keys().copied())
+            .collect();
+        let symbols = functions()
+            .flat_map(|function| function.get_all_symbols())
+            .collect();
+        (
+            LabelGenerator::from_vec(&labels),
+            SymbolGenerator::from_vec(&symbols),
+        )
+    }
 }
 
 impl LIRFunction {
@@ -116,6 +144,31 @@ pub enum LIRInstruction {
     // Continue execution at the label "to" only id the condition is met.
 }
 
+impl fmt::Display for LIRProgram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", Label::Main, self.main_function)?;
+        for (label, function) in self.other_functions.iter() {
+            write!(f, "\n{}{}", label, function)?;
+        }
+        Ok(())
+    }
+}
+
+// The arguments and return symbol, then the body with instructions indented
+impl fmt::Display for LIRFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let arguments: Vec<String> = self.arguments.iter().map(Symbol::to_string).collect();
+        writeln!(f, "({}) -> {}:", arguments.join(", "), self.return_symbol)?;
+        for assembly in self.instruction_listing.iter() {
+            match assembly {
+                LIRAssembly::Label(_) => writeln!(f, "{}", assembly)?,
+                LIRAssembly::Instruction(_) => writeln!(f, "\t{}", assembly)?,
+            }
+        }
+        Ok(())
+    }
+}
+
 impl fmt::Display for LIRAssembly {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -1,7 +1,7 @@
 use crate::checked_grammar::*;
 use crate::common::*;
 use crate::lir::*;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
 
 #[derive(Debug)]
@@ -10,19 +10,52 @@ pub struct LoweringGlobal {
     pub types: HashMap<TypeId, Type>,
     pub gen_sym: SymbolGenerator,
     pub gen_label: LabelGenerator,
+    pub options: LoweringOptions,
+    pub function_types: HashMap<Label, FunctionType>,
+    pub pointers: HashSet<Symbol>,
+}
+
+/*
+ * Options that change the code lowering generates
+ *
+ * bounds_checks: check every array subscript against the length stored in
+ * the array's header, exiting through the runtime when it is out of range.
+ */
+#[derive(Debug, Copy, Clone)]
+pub struct LoweringOptions {
+    pub bounds_checks: bool,
+}
+
+impl Default for LoweringOptions {
+    fn default() -> Self {
+        LoweringOptions {
+            bounds_checks: true,
+        }
+    }
 }
 
 #[allow(dead_code, unused_variables)]
 pub fn lower(
     type_checked_program: CheckedProgram,
+    options: LoweringOptions,
 ) -> (LIRProgram, LabelGenerator, SymbolGenerator) {
     let mut lowering_global = LoweringGlobal {
         symbol_table: type_checked_program.symbol_table,
         types: type_checked_program.types,
         gen_sym: type_checked_program.gen_sym,
         gen_label: type_checked_program.gen_label,
+        options,
+        function_types: type_checked_program.function_symbols,
+        pointers: HashSet::new(),
     };
 
+    // Variables and arguments of pointer types
+    for (symbol, type_id) in lowering_global.symbol_table.iter() {
+        if is_pointer_type(&lowering_global.types[type_id]) {
+            lowering_global.pointers.insert(*symbol);
+        }
+    }
+
     /*
     pub struct CheckedProgram {
         pub function_symbols: HashMap<Label, FunctionType>,
@@ -41,6 +74,7 @@ pub fn lower(
         arguments: vec![],
         return_symbol: lowering_global.gen_sym.new_symbol(),
         instruction_listing: vec![],
+        pointers: HashSet::new(),
     };
     let mut other_functions = HashMap::new();
 
@@ -51,11 +85,20 @@ pub fn lower(
                 let (body_assembly, return_symbol) = lower_exp(*body, &mut lowering_global, None);
 
                 // Create LIR function
+                let arguments = Vec::from(args);
+                let locals = collect_locals(&body_assembly, &arguments);
+                let pointers = arguments
+                    .iter()
+                    .chain(locals.iter())
+                    .filter(|symbol| lowering_global.pointers.contains(symbol))
+                    .copied()
+                    .collect();
                 let lir_function = LIRFunction {
-                    locals: vec![],
-                    arguments: args.try_into().unwrap(),
+                    locals,
+                    arguments,
                     return_symbol: return_symbol,
                     instruction_listing: body_assembly,
+                    pointers,
                 };
 
                 // Update LIR program
@@ -80,6 +123,71 @@ pub fn lower(
     )
 }
 
+/*
+ * Locals
+ *
+ * Every symbol a function body assigns to, other than its arguments, in the
+ * order they are first assigned.
+ */
+pub fn collect_locals(instruction_listing: &[LIRAssembly], arguments: &[Symbol]) -> Vec<Symbol> {
+    let mut locals = vec![];
+    let mut seen: HashSet<Symbol> = arguments.iter().copied().collect();
+    for assembly in instruction_listing.iter() {
+        let assigned = match assembly {
+            LIRAssembly::Instruction(LIRInstruction::IntLit { assign_to, .. })
+            | LIRAssembly::Instruction(LIRInstruction::StringLit { assign_to, .. })
+            | LIRAssembly::Instruction(LIRInstruction::LoadFromMemoryAtOffset {
+                assign_to, ..
+            })
+            | LIRAssembly::Instruction(LIRInstruction::Assign { assign_to, .. })
+            | LIRAssembly::Instruction(LIRInstruction::Negate { assign_to, .. })
+            | LIRAssembly::Instruction(LIRInstruction::BinaryOp { assign_to, .. })
+            | LIRAssembly::Instruction(LIRInstruction::Call { assign_to, .. }) => *assign_to,
+            _ => continue,
+        };
+        if seen.insert(assigned) {
+            locals.push(assigned);
+        }
+    }
+    locals
+}
+
+/*
+ * Pointers
+ *
+ * Strings, records and arrays live on the garbage collected heap, so the
+ * symbols holding them are what the collector looks for in stack frames. nil
+ * is the null pointer, which the collector skips.
+ */
+fn is_pointer_type(type_value: &Type) -> bool {
+    matches!(type_value, Type::Str | Type::Record(_) | Type::Array(_))
+}
+
+fn mark_pointer(symbol: Symbol, type_value: &Type, lowering_global: &mut LoweringGlobal) {
+    if is_pointer_type(type_value) {
+        lowering_global.pointers.insert(symbol);
+    }
+}
+
+/*
+ * Pointer map
+ *
+ * The runtime's description of which words of an object are pointers: a 'p'
+ * for each word that is and an 'n' for each word that isn't.
+ */
+fn pointer_map(type_ids: &[TypeId], lowering_global: &LoweringGlobal) -> String {
+    type_ids
+        .iter()
+        .map(|type_id| {
+            if is_pointer_type(&lowering_global.types[type_id]) {
+                'p'
+            } else {
+                'n'
+            }
+        })
+        .collect()
+}
+
 // Returns a sequence of LIR instructions and the symbol that will hold the result of those computations
 fn lower_exp(
     checked_exp: CheckedExp,
@@ -100,13 +208,23 @@ fn lower_exp(
 
             (vec![jump_assembly], break_symbol)
         }
+        CheckedExp::Nil => {
+            // nil is the null pointer
+            let nil_symbol = lowering_global.gen_sym.new_symbol();
+            let nil_instruction = LIRInstruction::IntLit {
+                assign_to: nil_symbol,
+                value: 0,
+            };
+            let nil_assembly = LIRAssembly::Instruction(nil_instruction);
+            (vec![nil_assembly], nil_symbol)
+        }
         CheckedExp::IntLit { value } => {
             // Create temporary symbol
             let int_lit_symbol = lowering_global.gen_sym.new_symbol();
             // Assign Int to temporary symbol
             let int_lit_instruction = LIRInstruction::IntLit {
                 assign_to: int_lit_symbol,
-                value: value as i64,
+                value: value,
             };
             let int_lit_assembly = LIRAssembly::Instruction(int_lit_instruction);
             // Return assembly instruction and temporary symbol
@@ -115,6 +233,7 @@ fn lower_exp(
         CheckedExp::StringLit { value } => {
             // Create temporary symbol
             let string_symbol = lowering_global.gen_sym.new_symbol();
+            lowering_global.pointers.insert(string_symbol);
             // Assign String to temporary symbol
             let string_instruction = LIRInstruction::StringLit {
                 assign_to: string_symbol,
@@ -163,6 +282,79 @@ fn lower_exp(
 
             (negate_assembly, negate_symbol)
         }
+        CheckedExp::Infix { left, op, right }
+            if op == InfixSourceOp::And || op == InfixSourceOp::Or =>
+        {
+            // `and` stops at the first zero operand and `or` at the first
+            // nonzero one, which decides the result without evaluating the
+            // right-hand operand.
+            let (short_circuit_comparison, short_circuit_value) = match op {
+                InfixSourceOp::And => (ComparisonType::Equal, 0),
+                _ => (ComparisonType::NotEqual, 1),
+            };
+
+            // Generate short circuit, right, and end labels
+            let short_circuit_label = lowering_global.gen_label.new_label();
+            let right_label = lowering_global.gen_label.new_label();
+            let end_label = lowering_global.gen_label.new_label();
+
+            // Generate a new temp symbol
+            let infix_symbol = lowering_global.gen_sym.new_symbol();
+
+            let mut infix_assembly = vec![];
+
+            // Load zero to compare the operands against
+            let zero_symbol = lowering_global.gen_sym.new_symbol();
+            infix_assembly.push(linst!(LIRInstruction::IntLit {
+                assign_to: zero_symbol,
+                value: 0,
+            }));
+
+            // Evaluate the left-hand operand, short circuiting if it decides the result
+            let (mut left_assembly, left_symbol) = lower_exp(*left, lowering_global, None);
+            infix_assembly.append(&mut left_assembly);
+            infix_assembly.push(linst!(LIRInstruction::JumpC {
+                to: short_circuit_label,
+                condition: Comparison {
+                    c: short_circuit_comparison,
+                    left: left_symbol,
+                    right: zero_symbol,
+                },
+            }));
+            infix_assembly.push(linst!(LIRInstruction::Jump { to: right_label }));
+
+            // Evaluate the right-hand operand, which decides the result
+            infix_assembly.push(LIRAssembly::Label(right_label));
+            let (mut right_assembly, right_symbol) = lower_exp(*right, lowering_global, None);
+            infix_assembly.append(&mut right_assembly);
+            infix_assembly.push(linst!(LIRInstruction::JumpC {
+                to: short_circuit_label,
+                condition: Comparison {
+                    c: short_circuit_comparison,
+                    left: right_symbol,
+                    right: zero_symbol,
+                },
+            }));
+
+            // Neither operand short circuited
+            infix_assembly.push(linst!(LIRInstruction::IntLit {
+                assign_to: infix_symbol,
+                value: 1 - short_circuit_value,
+            }));
+            infix_assembly.push(linst!(LIRInstruction::Jump { to: end_label }));
+
+            // Emit short circuit label
+            infix_assembly.push(LIRAssembly::Label(short_circuit_label));
+            infix_assembly.push(linst!(LIRInstruction::IntLit {
+                assign_to: infix_symbol,
+                value: short_circuit_value,
+            }));
+
+            // Emit end label
+            infix_assembly.push(LIRAssembly::Label(end_label));
+
+            (infix_assembly, infix_symbol)
+        }
         CheckedExp::Infix { left, op, right } => {
             // Call lower_exp on the left-hand operand to get left_assembly and left_symbol
             let (mut left_assembly, left_symbol) = lower_exp(*left, lowering_global, None);
@@ -178,6 +370,15 @@ fn lower_exp(
 
             // Try converting InfixSourceOp into InfixOp
             match op.try_into() {
+                Ok(infix_op @ InfixOp::Divide) | Ok(infix_op @ InfixOp::Modulo) => {
+                    infix_assembly.append(&mut lower_division(
+                        infix_symbol,
+                        left_symbol,
+                        infix_op,
+                        right_symbol,
+                        lowering_global,
+                    ));
+                }
                 Ok(infix_op) => {
                     // Concatenate sequence1 + sequence2 + BinaryOp(symbol3, symbol1, Add, symbol2)
                     let binary_op_instruction = LIRInstruction::BinaryOp {
@@ -252,22 +453,34 @@ fn lower_exp(
             (infix_assembly, infix_symbol)
         }
         CheckedExp::ArrayCreate {
+            type_id,
             length,
             initial_value,
         } => {
             // Create new symbol
             let array_symbol = lowering_global.gen_sym.new_symbol();
+            lowering_global.pointers.insert(array_symbol);
 
             // Load length and initial_value expressions into temporary symbols
             let (mut length_assembly, length_symbol) = lower_exp(*length, lowering_global, None);
             let (mut initial_value_assembly, initial_value_symbol) =
                 lower_exp(*initial_value, lowering_global, None);
 
+            // Tell the collector whether the elements are pointers
+            let array_type = lowering_global.types[&type_id].clone();
+            let element_type = array_element_type(array_type, lowering_global);
+            let pointers_symbol = lowering_global.gen_sym.new_symbol();
+            let pointers_instruction = LIRInstruction::IntLit {
+                assign_to: pointers_symbol,
+                value: is_pointer_type(&element_type) as i64,
+            };
+            let pointers_assembly = LIRAssembly::Instruction(pointers_instruction);
+
             // Call allocate_and_memset and set result to new symbol
             let call_instruction = LIRInstruction::Call {
                 assign_to: array_symbol,
                 function_name: Label::AllocateAndMemset,
-                args: vec![length_symbol, initial_value_symbol],
+                args: vec![length_symbol, initial_value_symbol, pointers_symbol],
             };
             let call_assembly = LIRAssembly::Instruction(call_instruction);
 
@@ -275,14 +488,16 @@ fn lower_exp(
             let mut array_assembly = vec![];
             array_assembly.append(&mut length_assembly);
             array_assembly.append(&mut initial_value_assembly);
+            array_assembly.push(pointers_assembly);
             array_assembly.push(call_assembly);
 
             // Return the result
             (array_assembly, array_symbol)
         }
-        CheckedExp::RecordCreate { fields } => {
+        CheckedExp::RecordCreate { type_id, fields } => {
             // Create new symbol for record
             let record_symbol = lowering_global.gen_sym.new_symbol();
+            lowering_global.pointers.insert(record_symbol);
 
             let mut record_assembly = vec![];
 
@@ -297,11 +512,24 @@ fn lower_exp(
             let length_assembly = LIRAssembly::Instruction(length_instruction);
             record_assembly.push(length_assembly);
 
+            // Describe which fields are pointers for the collector
+            let field_type_ids = match &lowering_global.types[&type_id] {
+                Type::Record(v) => v.iter().map(|(_, id)| *id).collect::<Vec<_>>(),
+                _ => panic!("Unexpected type value"),
+            };
+            let pointer_map_symbol = lowering_global.gen_sym.new_symbol();
+            let pointer_map_instruction = LIRInstruction::StringLit {
+                assign_to: pointer_map_symbol,
+                value: pointer_map(&field_type_ids, lowering_global),
+            };
+            let pointer_map_assembly = LIRAssembly::Instruction(pointer_map_instruction);
+            record_assembly.push(pointer_map_assembly);
+
             // Allocate length of fields vector and set record to address
             let call_instruction = LIRInstruction::Call {
                 assign_to: record_symbol,
                 function_name: Label::Allocate,
-                args: vec![length_symbol],
+                args: vec![length_symbol, pointer_map_symbol],
             };
             let call_assembly = LIRAssembly::Instruction(call_instruction);
             record_assembly.push(call_assembly);
@@ -427,6 +655,13 @@ fn lower_exp(
             let (mut else_assembly, else_symbol) = lower_exp(*else_exp, lowering_global, None);
             if_then_else_assembly.append(&mut else_assembly);
 
+            // The result is a pointer if either branch is (the other may be nil)
+            if lowering_global.pointers.contains(&then_symbol)
+                || lowering_global.pointers.contains(&else_symbol)
+            {
+                lowering_global.pointers.insert(if_then_else_symbol);
+            }
+
             // Assign else_symbol to if_then_else_symbol
             let assign_else_symbol_instruction = LIRInstruction::Assign {
                 assign_to: if_then_else_symbol,
@@ -610,6 +845,20 @@ fn lower_exp(
             let (mut do_assembly, do_symbol) = lower_exp(*do_exp, lowering_global, Some(end_label));
             for_loop_assembly.append(&mut do_assembly);
 
+            // Stop after the last iteration rather than incrementing id past
+            // to_symbol, which would overflow when to_symbol is the largest int
+            let last_iteration_comparison = Comparison {
+                c: ComparisonType::GreaterThanEqual,
+                left: id,
+                right: to_symbol,
+            };
+            let jump_last_instruction = LIRInstruction::JumpC {
+                to: end_label,
+                condition: last_iteration_comparison,
+            };
+            let jump_last_assembly = LIRAssembly::Instruction(jump_last_instruction);
+            for_loop_assembly.push(jump_last_assembly);
+
             // Increment id
             let increment_instruction = LIRInstruction::BinaryOp {
                 assign_to: id,
@@ -670,7 +919,19 @@ fn lower_exp(
                 arg_symbols.push(arg_symbol);
             }
 
+            // The length of an array is read from its header, not called
+            if function_name == Label::ArrayLength {
+                let (mut length_assembly, length_symbol) =
+                    lower_array_length(arg_symbols[0], lowering_global);
+                call_assembly.append(&mut length_assembly);
+                return (call_assembly, length_symbol);
+            }
+
             let result_symbol = lowering_global.gen_sym.new_symbol();
+            if let Some(function_type) = lowering_global.function_types.get(&function_name) {
+                let return_type = lowering_global.types[&function_type.return_type].clone();
+                mark_pointer(result_symbol, &return_type, lowering_global);
+            }
 
             let lir_call_instruction = LIRInstruction::Call {
                 assign_to: result_symbol,
@@ -707,7 +968,9 @@ fn lower_lvalue_value(
             let type_value = lowering_global.types.get(&type_id).unwrap();
 
             // Return assembly instruction and temporary symbol
-            (type_value.clone(), vec![assembly], symbol)
+            let type_value = type_value.clone();
+            mark_pointer(symbol, &type_value, lowering_global);
+            (type_value, vec![assembly], symbol)
         }
         CheckedLValue::Subscript { array, index } => {
             let mut subscript_assembly = vec![];
@@ -724,6 +987,11 @@ fn lower_lvalue_value(
             let (mut index_assembly, index_symbol) = lower_exp(*index, lowering_global, None);
             subscript_assembly.append(&mut index_assembly);
 
+            // Check the index against the array length
+            let mut bounds_check_assembly =
+                lower_bounds_check(array_symbol, index_symbol, lowering_global);
+            subscript_assembly.append(&mut bounds_check_assembly);
+
             // Load from memory offset
             let load_from_memory_at_offset_instruction = LIRInstruction::LoadFromMemoryAtOffset {
                 assign_to: symbol,
@@ -736,7 +1004,9 @@ fn lower_lvalue_value(
             subscript_assembly.push(load_from_memory_at_offset_assembly);
 
             // Return assembly instruction and temporary symbol
-            (type_value, subscript_assembly, symbol)
+            let element_type = array_element_type(type_value, lowering_global);
+            mark_pointer(symbol, &element_type, lowering_global);
+            (element_type, subscript_assembly, symbol)
         }
         CheckedLValue::FieldExp { record, field } => {
             let mut field_assembly = vec![];
@@ -777,7 +1047,9 @@ fn lower_lvalue_value(
                     field_assembly.push(load_from_memory_at_offset_assembly);
 
                     // Return field assembly and symbol
-                    (element_type.clone(), field_assembly, symbol)
+                    let element_type = element_type.clone();
+                    mark_pointer(symbol, &element_type, lowering_global);
+                    (element_type, field_assembly, symbol)
                 }
                 _ => {
                     panic!("Unexpected type value");
@@ -821,6 +1093,11 @@ fn lower_lvalue_assigning_symbol(
             let (mut index_assembly, index_symbol) = lower_exp(*index, lowering_global, None);
             subscript_assembly.append(&mut index_assembly);
 
+            // Check the index against the array length
+            let mut bounds_check_assembly =
+                lower_bounds_check(array_symbol, index_symbol, lowering_global);
+            subscript_assembly.append(&mut bounds_check_assembly);
+
             // Load from memory offset
             let store_to_memory_at_offset_instruction = LIRInstruction::StoreToMemoryAtOffset {
                 location: array_symbol,
@@ -833,7 +1110,8 @@ fn lower_lvalue_assigning_symbol(
             subscript_assembly.push(store_to_memory_at_offset_assembly);
 
             // Return assembly instruction and temporary symbol
-            (type_value, subscript_assembly, array_symbol)
+            let element_type = array_element_type(type_value, lowering_global);
+            (element_type, subscript_assembly, array_symbol)
         }
         CheckedLValue::FieldExp { record, field } => {
             let mut field_assembly = vec![];
@@ -880,3 +1158,192 @@ fn lower_lvalue_assigning_symbol(
         }
     }
 }
+
+// The type of a subscript is the element type of the array
+fn array_element_type(array_type: Type, lowering_global: &LoweringGlobal) -> Type {
+    match array_type {
+        Type::Array(element_type_id) => {
+            lowering_global.types.get(&element_type_id).unwrap().clone()
+        }
+        _ => panic!("Unexpected type value"),
+    }
+}
+
+/*
+ * Array length
+ *
+ * Arrays are allocated by the runtime's allocate_and_memset with their length
+ * in a header word just before the first element, so an array points at
+ * element 0 and its length is at offset -1.
+ */
+fn lower_array_length(
+    array_symbol: Symbol,
+    lowering_global: &mut LoweringGlobal,
+) -> (Vec<LIRAssembly>, Symbol) {
+    let header_offset_symbol = lowering_global.gen_sym.new_symbol();
+    let length_symbol = lowering_global.gen_sym.new_symbol();
+    let length_assembly = vec![
+        linst!(LIRInstruction::IntLit {
+            assign_to: header_offset_symbol,
+            value: -1,
+        }),
+        linst!(LIRInstruction::LoadFromMemoryAtOffset {
+            assign_to: length_symbol,
+            location: array_symbol,
+            offset: header_offset_symbol,
+        }),
+    ];
+    (length_assembly, length_symbol)
+}
+
+/*
+ * Bounds check
+ *
+ * Load the array's length from its header and jump to a call to the runtime's index_out_of_bounds,
+ * which does not return, when the index is negative or not below the length.
+ * Emits nothing when bounds checks are turned off.
+ */
+fn lower_bounds_check(
+    array_symbol: Symbol,
+    index_symbol: Symbol,
+    lowering_global: &mut LoweringGlobal,
+) -> Vec<LIRAssembly> {
+    if !lowering_global.options.bounds_checks {
+        return vec![];
+    }
+
+    // Generate out of bounds and in bounds labels
+    let out_of_bounds_label = lowering_global.gen_label.new_label();
+    let in_bounds_label = lowering_global.gen_label.new_label();
+
+    // Load the length from the header word
+    let (mut bounds_check_assembly, length_symbol) =
+        lower_array_length(array_symbol, lowering_global);
+    let zero_symbol = lowering_global.gen_sym.new_symbol();
+    bounds_check_assembly.push(linst!(LIRInstruction::IntLit {
+        assign_to: zero_symbol,
+        value: 0,
+    }));
+
+    // Jump out of bounds if index < 0 or index >= length
+    bounds_check_assembly.push(linst!(LIRInstruction::JumpC {
+        to: out_of_bounds_label,
+        condition: Comparison {
+            c: ComparisonType::LessThan,
+            left: index_symbol,
+            right: zero_symbol,
+        },
+    }));
+    bounds_check_assembly.push(linst!(LIRInstruction::JumpC {
+        to: out_of_bounds_label,
+        condition: Comparison {
+            c: ComparisonType::GreaterThanEqual,
+            left: index_symbol,
+            right: length_symbol,
+        },
+    }));
+    bounds_check_assembly.push(linst!(LIRInstruction::Jump {
+        to: in_bounds_label
+    }));
+
+    // Report the error from the runtime
+    let unused_symbol = lowering_global.gen_sym.new_symbol();
+    bounds_check_assembly.push(LIRAssembly::Label(out_of_bounds_label));
+    bounds_check_assembly.push(linst!(LIRInstruction::Call {
+        assign_to: unused_symbol,
+        function_name: Label::IndexOutOfBounds,
+        args: vec![index_symbol, length_symbol],
+    }));
+    bounds_check_assembly.push(LIRAssembly::Label(in_bounds_label));
+
+    bounds_check_assembly
+}
+
+/*
+ * Division
+ *
+ * Divide or take the remainder, jumping to a call to the runtime's division_by_zero, which does
+ * not return, when the divisor is zero. A divisor of -1 is handled without idivq, which faults
+ * on the minimum int: the quotient is the negated dividend (so it overflows like any other
+ * negation) and the remainder is 0.
+ */
+fn lower_division(
+    assign_to: Symbol,
+    left: Symbol,
+    op: InfixOp,
+    right: Symbol,
+    lowering_global: &mut LoweringGlobal,
+) -> Vec<LIRAssembly> {
+    // Generate labels for each kind of divisor
+    let zero_label = lowering_global.gen_label.new_label();
+    let minus_one_label = lowering_global.gen_label.new_label();
+    let divide_label = lowering_global.gen_label.new_label();
+    let end_label = lowering_global.gen_label.new_label();
+
+    let zero_symbol = lowering_global.gen_sym.new_symbol();
+    let minus_one_symbol = lowering_global.gen_sym.new_symbol();
+    let mut division_assembly = vec![
+        linst!(LIRInstruction::IntLit {
+            assign_to: zero_symbol,
+            value: 0,
+        }),
+        linst!(LIRInstruction::IntLit {
+            assign_to: minus_one_symbol,
+            value: -1,
+        }),
+    ];
+
+    // Jump to the special cases if right == 0 or right == -1
+    division_assembly.push(linst!(LIRInstruction::JumpC {
+        to: zero_label,
+        condition: Comparison {
+            c: ComparisonType::Equal,
+            left: right,
+            right: zero_symbol,
+        },
+    }));
+    division_assembly.push(linst!(LIRInstruction::JumpC {
+        to: minus_one_label,
+        condition: Comparison {
+            c: ComparisonType::Equal,
+            left: right,
+            right: minus_one_symbol,
+        },
+    }));
+    division_assembly.push(linst!(LIRInstruction::Jump { to: divide_label }));
+
+    // Report the error from the runtime
+    let unused_symbol = lowering_global.gen_sym.new_symbol();
+    division_assembly.push(LIRAssembly::Label(zero_label));
+    division_assembly.push(linst!(LIRInstruction::Call {
+        assign_to: unused_symbol,
+        function_name: Label::DivisionByZero,
+        args: vec![],
+    }));
+
+    // Divide by -1 without dividing
+    division_assembly.push(LIRAssembly::Label(minus_one_label));
+    division_assembly.push(match op {
+        InfixOp::Divide => linst!(LIRInstruction::Negate {
+            assign_to,
+            value: left,
+        }),
+        _ => linst!(LIRInstruction::IntLit {
+            assign_to,
+            value: 0,
+        }),
+    });
+    division_assembly.push(linst!(LIRInstruction::Jump { to: end_label }));
+
+    // Otherwise divide
+    division_assembly.push(LIRAssembly::Label(divide_label));
+    division_assembly.push(linst!(LIRInstruction::BinaryOp {
+        assign_to,
+        left,
+        op,
+        right,
+    }));
+    division_assembly.push(LIRAssembly::Label(end_label));
+
+    division_assembly
+}
@@ -1,4 +1,10 @@
+// Diagnostic is the error type throughout the front end and is returned by value
+#![allow(clippy::result_large_err)]
+
 mod common;
+mod constant_propagation;
+mod copy_propagation;
+mod dead_code;
 #[macro_use]
 mod x64;
 #[macro_use]
@@ -8,15 +14,64 @@ mod backend;
 mod x64s;
 mod check_type;
 mod checked_grammar;
+mod diagnostic;
+mod driver;
+mod lexer;
 mod lowering;
 mod source_grammar;
+mod ssa;
+mod stage;
+mod target;
+mod value_numbering;
+
+#[cfg(test)]
+mod test_common;
 
 #[cfg(test)]
 mod test_type_check;
 
-use crate::backend::compile;
-use crate::check_type::type_check;
-use lowering::lower;
+#[cfg(test)]
+mod test_backend;
+
+#[cfg(test)]
+mod test_parser;
+
+#[cfg(test)]
+mod eval_checked_grammar;
+
+#[cfg(test)]
+mod eval_lir;
+
+#[cfg(test)]
+mod test_eval;
+
+#[cfg(test)]
+mod test_driver;
+
+#[cfg(test)]
+mod test_stage;
+
+#[cfg(test)]
+mod test_ssa;
+
+#[cfg(test)]
+mod test_constant_propagation;
+
+#[cfg(test)]
+mod test_copy_propagation;
+
+#[cfg(test)]
+mod test_value_numbering;
+
+#[cfg(test)]
+mod test_dead_code;
+
+use crate::backend::BackendOptions;
+use crate::diagnostic::Diagnostic;
+use crate::driver::OutputKind;
+use crate::stage::{Format, PipelineOptions, Stage, StagedProgram};
+use lowering::LoweringOptions;
+use target::Target;
 
 #[macro_use]
 extern crate lalrpop_util;
@@ -27,6 +82,7 @@ extern crate clap;
 
 use std::fs::File;
 use std::io::prelude::*;
+use std::path::Path;
 
 /*
  * CFG
@@ -42,30 +98,137 @@ fn main() -> std::io::Result<()> {
         (version: "1.0.0")
         (author: "Chris Phifer <cphifer@galois.com>")
         (about: "A compiler for the Cat programming language.")
-        (@arg INFILE: +required "The Cat source file to be compiled")
-        (@arg OUTFILE: -o --output +takes_value "Sets a custom output file, defaulting to a.s")
+        (@arg INFILE: +required "The Cat source file to be compiled, or a program written out at the --from stage")
+        (@arg OUTFILE: -o --output +takes_value "Sets a custom output file, defaulting to a.s, a.o or a.out")
+        (@arg ASSEMBLY: -S conflicts_with[OBJECT RUN] "Only compiles, writing assembly")
+        (@arg OBJECT: -c conflicts_with[RUN] "Compiles and assembles, but does not link")
+        (@arg RUN: --run "Runs the executable once built and exits with its exit status")
+        (@arg EMIT: --emit +takes_value possible_values(&Stage::NAMES) conflicts_with[ASSEMBLY OBJECT RUN] "Writes the program out at a stage of the pipeline, to the output file or standard output")
+        (@arg FROM: --from +takes_value possible_values(&Stage::NAMES) "Reads INFILE as a program written out at a stage and compiles it from there")
+        (@arg FORMAT: --format +takes_value possible_values(&Format::NAMES) "Sets the format of --emit and --from, defaulting to ron")
+        (@arg OPTIMIZE: -O "Optimizes the program")
+        (@arg NO_BOUNDS_CHECKS: --("no-bounds-checks") "Turns off runtime checks of array subscripts")
+        (@arg TRAP_OVERFLOW: --("trap-overflow") "Exits with an error when integer arithmetic overflows instead of wrapping around")
+        (@arg TARGET: --target +takes_value possible_values(&Target::NAMES) "Sets the platform to write assembly for, defaulting to x86_64-linux")
     )
     .get_matches();
 
     // Get source file from clargs, read into a string to parse
-    let mut source_file = File::open(matches.value_of("INFILE").unwrap())?;
-    let mut program = String::new();
-    source_file.read_to_string(&mut program)?;
+    let source_name = matches.value_of("INFILE").unwrap();
+    let mut source_file = File::open(source_name)?;
+    let mut source = String::new();
+    source_file.read_to_string(&mut source)?;
+
+    let options = PipelineOptions {
+        lowering: LoweringOptions {
+            bounds_checks: !matches.is_present("NO_BOUNDS_CHECKS"),
+        },
+        optimize: matches.is_present("OPTIMIZE"),
+        backend: BackendOptions {
+            trap_overflow: matches.is_present("TRAP_OVERFLOW"),
+            target: value_t!(matches, "TARGET", Target).unwrap_or_default(),
+        },
+    };
+    let emit = value_t!(matches, "EMIT", Stage).ok();
+    let from = value_t!(matches, "FROM", Stage).ok();
+    let format = value_t!(matches, "FORMAT", Format).unwrap_or_default();
+    if let (Some(emit), Some(from)) = (emit, from) {
+        if emit < from {
+            error_and_exit(format!("can't emit {} from the later stage {}", emit, from));
+        }
+    }
 
     // Begin compiling!
-    let parser = parser::ProgramParser::new();
+    let mut program = match from {
+        Some(stage) => {
+            let program = StagedProgram::read(stage, format, &source).unwrap_or_else(|error| {
+                error_and_exit(format!(
+                    "{} is not a {} program: {}",
+                    source_name, stage, error
+                ))
+            });
+            if options.optimize {
+                program
+                    .optimize(options)
+                    .unwrap_or_else(|error| error_and_exit(error))
+            } else {
+                program
+            }
+        }
+        None => {
+            let parser = parser::ProgramParser::new();
+            match parser.parse(lexer::Lexer::new(&source)) {
+                Ok(program) => StagedProgram::Ast(program),
+                Err(error) => {
+                    let diagnostic = Diagnostic::from_parse_error(error, &source);
+                    report_and_exit(&[diagnostic], source_name, &source)
+                }
+            }
+        }
+    };
 
-    let program = parser
-        .parse(&program)
-        .expect("There was an error while parsing.");
-    let type_checked_program =
-        type_check(program).expect("There was an error while checking types.");
-    let (lir_program, label_gen, symbol_gen) = lower(type_checked_program);
-    let compiled_program = compile(lir_program, label_gen, symbol_gen);
+    // Run the pipeline to the stage being emitted, or to the end
+    while Some(program.stage()) != emit && program.stage() != Stage::Asm {
+        program = match program.advance(options) {
+            Ok(program) => program,
+            // Without the source there is nothing to point at
+            Err(diagnostics) if from.is_some() => {
+                let messages: Vec<_> = diagnostics.iter().map(|d| d.to_string()).collect();
+                error_and_exit(messages.join("\nerror: "))
+            }
+            Err(diagnostics) => report_and_exit(&diagnostics, source_name, &source),
+        };
+    }
+    if emit.is_some() {
+        let text = program
+            .write(format)
+            .unwrap_or_else(|error| error_and_exit(error));
+        match matches.value_of("OUTFILE") {
+            Some(output) => File::create(output)?.write_all(text.as_bytes())?,
+            None => print!("{}", text),
+        }
+        return Ok(());
+    }
+    let compiled_program = match program {
+        StagedProgram::Asm(compiled_program) => compiled_program,
+        _ => unreachable!("the pipeline ends with assembly"),
+    };
 
     // Output file handling
-    let mut output_file = File::create(matches.value_of("OUTFILE").unwrap_or("a.s"))?;
-    output_file.write_all(format!("{}\n", compiled_program).as_bytes())?;
+    let assembly = format!("{}\n", compiled_program);
+    let output = matches.value_of("OUTFILE");
+    if matches.is_present("RUN") {
+        let status = driver::run(&assembly, output.map(Path::new))
+            .unwrap_or_else(|error| error_and_exit(error));
+        std::process::exit(status);
+    }
+
+    let kind = if matches.is_present("ASSEMBLY") {
+        OutputKind::Assembly
+    } else if matches.is_present("OBJECT") {
+        OutputKind::Object
+    } else {
+        OutputKind::Executable
+    };
+    let output = Path::new(output.unwrap_or_else(|| kind.default_output()));
+    driver::build(&assembly, kind, output).unwrap_or_else(|error| error_and_exit(error));
 
     Ok(())
 }
+
+fn error_and_exit<E: std::fmt::Display>(error: E) -> ! {
+    eprintln!("error: {}", error);
+    std::process::exit(1);
+}
+
+fn report_and_exit(diagnostics: &[Diagnostic], source_name: &str, source: &str) -> ! {
+    for diagnostic in diagnostics.iter() {
+        eprintln!("{}", diagnostic.render(source_name, source));
+    }
+    eprintln!(
+        "error: aborting due to {} previous error{}",
+        diagnostics.len(),
+        if diagnostics.len() == 1 { "" } else { "s" }
+    );
+    std::process::exit(1);
+}
@@ -2,22 +2,235 @@
 #include <stdio.h>
 #include <stdint.h>
 #include <inttypes.h>
+#include <string.h>
+#include <errno.h>
+#include <sys/types.h>
 
-// allocate memory and leak it
-uint64_t* allocate(uint64_t length) {
-        uint64_t *memory = malloc(sizeof(*memory) * length);
-        return memory;
+// exit status of a program that indexes outside of an array
+#define INDEX_OUT_OF_BOUNDS_STATUS 120
+// exit status of a program that passes a builtin an argument it can't handle
+#define BAD_ARGUMENT_STATUS 121
+// exit status of a program that runs out of memory
+#define OUT_OF_MEMORY_STATUS 122
+// exit status of a program whose arithmetic overflows, compiled with
+// --trap-overflow
+#define INTEGER_OVERFLOW_STATUS 123
+#define DIVISION_BY_ZERO_STATUS 124
+
+/*
+ * Garbage collection
+ *
+ * Records, arrays and strings live on a heap managed by a mark-sweep
+ * collector. Every object is preceded by a header, and a Cat pointer points
+ * at the first word after it, so an array's length is at offset -1.
+ *
+ * The header's pointer map says which words of the object hold pointers: a
+ * 'p' for each word that does and an 'n' for each word that doesn't. The last
+ * character repeats for the rest of the object, so an array's map is a single
+ * character. Strings have no map. String literals are compiled with a header
+ * of their own, marked PINNED so the collector never touches them.
+ */
+#define UNMARKED 0
+#define MARKED 1
+#define PINNED 2
+
+struct header {
+        struct header *next;
+        uint64_t mark;
+        const char *pointers;
+        uint64_t length;
+};
+
+/*
+ * The roots are found through a shadow stack. A compiled function whose
+ * frame holds pointers across a call keeps them in consecutive stack slots
+ * just above a frame record, which it links onto gc_frames on entry and
+ * unlinks before returning.
+ */
+struct frame {
+        struct frame *previous;
+        uint64_t count;
+        uint64_t *slots[];
+};
+
+struct frame *gc_frames = NULL;
+
+// pointers held by the runtime itself while it allocates
+static uint64_t *runtime_roots[4];
+static int runtime_root_count = 0;
+
+// every object on the heap, newest first
+static struct header *objects = NULL;
+static size_t heap_bytes = 0;
+// collect when the heap grows past this many bytes
+static size_t heap_limit = 1 << 20;
+// setting CAT_GC_STRESS collects before every allocation and poisons freed
+// objects, which shakes out missing roots
+static int stress = -1;
+
+static struct header **mark_stack = NULL;
+static size_t mark_stack_length = 0;
+static size_t mark_stack_capacity = 0;
+
+static void out_of_memory(void) {
+        fflush(stdout);
+        fprintf(stderr, "out of memory\n");
+        exit(OUT_OF_MEMORY_STATUS);
+}
+
+static struct header *header_of(uint64_t *object) {
+        return (struct header *) object - 1;
+}
+
+static void mark(uint64_t *object) {
+        if (object == NULL) {
+                return;
+        }
+        struct header *header = header_of(object);
+        if (header->mark != UNMARKED) {
+                return;
+        }
+        header->mark = MARKED;
+        if (mark_stack_length == mark_stack_capacity) {
+                mark_stack_capacity = mark_stack_capacity ? 2 * mark_stack_capacity : 256;
+                mark_stack = realloc(mark_stack, mark_stack_capacity * sizeof(*mark_stack));
+                if (mark_stack == NULL) {
+                        out_of_memory();
+                }
+        }
+        mark_stack[mark_stack_length++] = header;
+}
+
+// mark the objects an object points to
+static void scan(struct header *header) {
+        const char *pointers = header->pointers;
+        if (pointers == NULL) {
+                return;
+        }
+        uint64_t *words = (uint64_t *) (header + 1);
+        for (uint64_t i = 0; i < header->length; i++) {
+                if (*pointers == 'p') {
+                        mark((uint64_t *) words[i]);
+                }
+                if (pointers[1] != '\0') {
+                        pointers++;
+                }
+        }
+}
+
+static void collect(void) {
+        for (struct frame *frame = gc_frames; frame != NULL; frame = frame->previous) {
+                for (uint64_t i = 0; i < frame->count; i++) {
+                        mark(frame->slots[i]);
+                }
+        }
+        for (int i = 0; i < runtime_root_count; i++) {
+                mark(runtime_roots[i]);
+        }
+        while (mark_stack_length > 0) {
+                scan(mark_stack[--mark_stack_length]);
+        }
+
+        struct header **link = &objects;
+        heap_bytes = 0;
+        while (*link != NULL) {
+                struct header *header = *link;
+                if (header->mark == MARKED) {
+                        header->mark = UNMARKED;
+                        heap_bytes += sizeof(*header) + header->length * sizeof(uint64_t);
+                        link = &header->next;
+                } else {
+                        *link = header->next;
+                        if (stress) {
+                                // make use of a freed object fail loudly
+                                memset(header, 0xdb, sizeof(*header) + header->length * sizeof(uint64_t));
+                        }
+                        free(header);
+                }
+        }
+        if (heap_limit < 2 * heap_bytes) {
+                heap_limit = 2 * heap_bytes;
+        }
+}
+
+// allocate a zeroed object of length words, collecting first if the heap is full
+static uint64_t *gc_allocate(uint64_t length, const char *pointers) {
+        size_t bytes = sizeof(struct header) + length * sizeof(uint64_t);
+        if (stress < 0) {
+                stress = getenv("CAT_GC_STRESS") != NULL;
+        }
+        if (stress || heap_bytes + bytes > heap_limit) {
+                collect();
+        }
+        struct header *header = calloc(1, bytes);
+        if (header == NULL) {
+                out_of_memory();
+        }
+        header->next = objects;
+        header->pointers = pointers;
+        header->length = length;
+        objects = header;
+        heap_bytes += bytes;
+        return (uint64_t *) (header + 1);
+}
+
+// keep an object alive while the runtime allocates
+static void push_root(uint64_t *object) {
+        runtime_roots[runtime_root_count++] = object;
+}
+
+static void pop_roots(int count) {
+        runtime_root_count -= count;
+}
+
+// a string of length characters plus its terminator
+static char *allocate_string(size_t length) {
+        return (char *) gc_allocate((length + sizeof(uint64_t)) / sizeof(uint64_t), NULL);
+}
+
+// allocate a record with the given pointer map
+uint64_t* allocate(uint64_t length, const char *pointers) {
+        return gc_allocate(length, pointers);
 }
 
-// allocate memory, initialize it and leak it
-uint64_t* allocate_and_memset(uint64_t length, int64_t inital_value) {
-        uint64_t *memory = malloc(sizeof(*memory) * length);
+// allocate an array and initialize it, its length is stored in the header
+uint64_t* allocate_and_memset(uint64_t length, int64_t inital_value, int64_t pointers) {
+        if (pointers) {
+                push_root((uint64_t *) inital_value);
+        }
+        uint64_t *memory = gc_allocate(length, pointers ? "p" : NULL);
+        if (pointers) {
+                pop_roots(1);
+        }
         for (uint64_t i = 0; i < length; i++) {
                 memory[i] = inital_value;
         }
         return memory;
 }
 
+// called by bounds checks on array subscripts; never returns
+void index_out_of_bounds(int64_t index, int64_t length) {
+        fflush(stdout);
+        fprintf(stderr, "index out of bounds: the length is %"PRId64" but the index is %"PRId64"\n", length, index);
+        exit(INDEX_OUT_OF_BOUNDS_STATUS);
+}
+
+// jumped to (not called) straight after an instruction that overflowed, so
+// the stack has to be realigned; never returns
+__attribute__((force_align_arg_pointer, noreturn))
+void integer_overflow(void) {
+        fflush(stdout);
+        fprintf(stderr, "integer overflow\n");
+        exit(INTEGER_OVERFLOW_STATUS);
+}
+
+// called by the check before every division; never returns
+void division_by_zero(void) {
+        fflush(stdout);
+        fprintf(stderr, "division by zero\n");
+        exit(DIVISION_BY_ZERO_STATUS);
+}
+
 void print_int(int64_t number) {
         printf("%"PRId64"", number);
 }
@@ -35,3 +248,129 @@ void print_line_string(uint64_t *string_arg) {
         char *string = (char *) string_arg;
         printf("%s\n", string);
 }
+
+// compare two strings, returning a negative, zero or positive number as the
+// first is less than, equal to or greater than the second
+int64_t string_compare(uint64_t *left_arg, uint64_t *right_arg) {
+        char *left = (char *) left_arg;
+        char *right = (char *) right_arg;
+        int result = strcmp(left, right);
+        return (result > 0) - (result < 0);
+}
+
+// concatenate two strings into a newly allocated one
+uint64_t* string_concat(uint64_t *left_arg, uint64_t *right_arg) {
+        char *left = (char *) left_arg;
+        char *right = (char *) right_arg;
+        size_t left_length = strlen(left);
+        size_t right_length = strlen(right);
+        push_root(left_arg);
+        push_root(right_arg);
+        char *result = allocate_string(left_length + right_length);
+        pop_roots(2);
+        memcpy(result, left, left_length);
+        memcpy(result + left_length, right, right_length + 1);
+        return (uint64_t *) result;
+}
+
+// report a bad argument to a builtin and exit
+static void bad_argument(const char *message) {
+        fflush(stdout);
+        fprintf(stderr, "%s\n", message);
+        exit(BAD_ARGUMENT_STATUS);
+}
+
+int64_t size(uint64_t *string_arg) {
+        char *string = (char *) string_arg;
+        return strlen(string);
+}
+
+// the length characters of a string starting at first, newly allocated
+uint64_t* substring(uint64_t *string_arg, int64_t first, int64_t length) {
+        char *string = (char *) string_arg;
+        int64_t string_length = strlen(string);
+        if (first < 0 || length < 0 || first + length > string_length) {
+                bad_argument("substring out of range");
+        }
+        push_root(string_arg);
+        char *result = allocate_string(length);
+        pop_roots(1);
+        memcpy(result, string + first, length);
+        result[length] = '\0';
+        return (uint64_t *) result;
+}
+
+// the character code of the first character of a string, or -1 if it is empty
+int64_t ord(uint64_t *string_arg) {
+        unsigned char *string = (unsigned char *) string_arg;
+        return string[0] == '\0' ? -1 : string[0];
+}
+
+// the one character string with the given ASCII code, which can't be NUL as
+// that ends a string
+uint64_t* chr(int64_t code) {
+        if (code < 1 || code > 127) {
+                bad_argument("chr argument is not an ASCII character code");
+        }
+        char *result = allocate_string(1);
+        result[0] = (char) code;
+        result[1] = '\0';
+        return (uint64_t *) result;
+}
+
+int64_t not(int64_t value) {
+        return value == 0;
+}
+
+// read a line from standard input into memory from malloc, without its
+// newline
+static char *read_raw_line(void) {
+        size_t capacity = 0;
+        char *line = NULL;
+        ssize_t length = getline(&line, &capacity, stdin);
+        if (length < 0) {
+                length = 0;
+                line = realloc(line, 1);
+        } else if (length > 0 && line[length - 1] == '\n') {
+                length--;
+        }
+        line[length] = '\0';
+        return line;
+}
+
+// read a line from standard input without its newline, or the empty string at
+// the end of input
+uint64_t* read_line(void) {
+        char *line = read_raw_line();
+        size_t length = strlen(line);
+        char *result = allocate_string(length);
+        memcpy(result, line, length + 1);
+        free(line);
+        return (uint64_t *) result;
+}
+
+// read a line from standard input holding a single integer
+int64_t read_int(void) {
+        char *line = read_raw_line();
+        char *end;
+        errno = 0;
+        int64_t value = strtoll(line, &end, 10);
+        while (*end == ' ' || *end == '\t' || *end == '\r') {
+                end++;
+        }
+        if (end == line || *end != '\0' || errno != 0) {
+                bad_argument("read_int: input is not an integer");
+        }
+        free(line);
+        return value;
+}
+
+void flush(void) {
+        fflush(stdout);
+}
+
+// exit is taken by the C library
+void exit_program(int64_t status) {
+        fflush(stdout);
+        exit(status);
+}
@@ -1,107 +1,179 @@
 pub use crate::common::InfixSourceOp;
+pub use crate::diagnostic::Span;
+
+use serde::{Deserialize, Serialize};
 
 use std::collections::VecDeque;
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
 pub struct Program {
     pub dec_list: VecDeque<TopLevelDec>,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
 pub enum TopLevelDec {
     TyDecArray {
         new_type: String,
         element_type: String,
+        span: Span,
     },
     TyDecRecord {
         new_type: String,
         field_decs: VecDeque<(String, String)>,
+        span: Span,
     },
     FunDec {
         name: String,
         return_type: String,
         args: VecDeque<(String, String)>,
         body: Box<Exp>,
+        span: Span,
     },
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
 pub enum Exp {
-    Break,
+    Break {
+        span: Span,
+    },
+    Nil {
+        span: Span,
+    },
     IntLit {
-        value: i32,
+        value: i64,
+        span: Span,
     },
     StringLit {
         value: String,
+        span: Span,
     },
     LValue {
         lvalue: LValue,
+        span: Span,
     },
     Sequence {
         sequence: VecDeque<Exp>,
+        span: Span,
     },
     Negate {
         exp: Box<Exp>,
+        span: Span,
     },
     Infix {
         left: Box<Exp>,
         op: InfixSourceOp,
         right: Box<Exp>,
+        span: Span,
     },
     ArrayCreate {
         type_id: String,
         length: Box<Exp>,
         initial_value: Box<Exp>,
+        span: Span,
     },
     RecordCreate {
         type_id: String,
         fields: VecDeque<(String, Exp)>,
+        span: Span,
     },
     Assign {
         left: LValue,
         right: Box<Exp>,
+        span: Span,
     },
     IfThenElse {
         if_exp: Box<Exp>,
         then_exp: Box<Exp>,
         else_exp: Box<Exp>,
+        span: Span,
     },
     IfThen {
         if_exp: Box<Exp>,
         then_exp: Box<Exp>,
+        span: Span,
     },
     While {
         while_exp: Box<Exp>,
         do_exp: Box<Exp>,
+        span: Span,
     },
     For {
         id: String,
         for_exp: Box<Exp>,
         to_exp: Box<Exp>,
         do_exp: Box<Exp>,
+        span: Span,
     },
     Let {
         let_exp: VecDeque<Dec>,
         in_exp: Box<Exp>,
+        span: Span,
     },
     Call {
         function_name: String,
         args: VecDeque<Exp>,
+        span: Span,
     },
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
 pub enum LValue {
-    Id { name: String },
-    Subscript { array: Box<LValue>, index: Box<Exp> },
-    FieldExp { record: Box<LValue>, field: String },
+    Id {
+        name: String,
+        span: Span,
+    },
+    Subscript {
+        array: Box<LValue>,
+        index: Box<Exp>,
+        span: Span,
+    },
+    FieldExp {
+        record: Box<LValue>,
+        field: String,
+        span: Span,
+    },
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
 pub enum Dec {
     VarDec {
         name: String,
         value_type: String,
         value: Exp,
+        span: Span,
     },
 }
+
+impl Exp {
+    pub fn span(&self) -> Span {
+        match self {
+            Exp::Break { span }
+            | Exp::Nil { span }
+            | Exp::IntLit { span, .. }
+            | Exp::StringLit { span, .. }
+            | Exp::LValue { span, .. }
+            | Exp::Sequence { span, .. }
+            | Exp::Negate { span, .. }
+            | Exp::Infix { span, .. }
+            | Exp::ArrayCreate { span, .. }
+            | Exp::RecordCreate { span, .. }
+            | Exp::Assign { span, .. }
+            | Exp::IfThenElse { span, .. }
+            | Exp::IfThen { span, .. }
+            | Exp::While { span, .. }
+            | Exp::For { span, .. }
+            | Exp::Let { span, .. }
+            | Exp::Call { span, .. } => *span,
+        }
+    }
+}
+
+impl LValue {
+    pub fn span(&self) -> Span {
+        match self {
+            LValue::Id { span, .. }
+            | LValue::Subscript { span, .. }
+            | LValue::FieldExp { span, .. } => *span,
+        }
+    }
+}
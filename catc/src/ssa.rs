@@ -0,0 +1,852 @@
+/*
+ * This is static single assignment form for LIR.
+ *
+ * construct_ssa splits an LIRFunction into basic blocks and renames its
+ * symbols so that each is assigned exactly once, joining the values that
+ * reach a block along different edges with phis. destruct_ssa turns the phis
+ * back into copies. Optimization passes run on the SSAFunction in between.
+ */
+use crate::common::{Label, LabelGenerator, Symbol, SymbolGenerator};
+use crate::constant_propagation::propagate_constants;
+use crate::control_flow_graph::{construct_control_flow_graph_lir, Node};
+use crate::copy_propagation::propagate_copies;
+use crate::dead_code::eliminate_dead_code;
+use crate::lir::{LIRAssembly, LIRFunction, LIRInstruction, LIRProgram};
+use crate::lowering::collect_locals;
+use crate::value_numbering::number_values;
+
+use petgraph::algo::dominators::simple_fast;
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/*
+ * A function in SSA form
+ *
+ * blocks:
+ *  the basic blocks in layout order. The first is the entry, which nothing
+ *  jumps to. Every other block but the exit ends in a Jump, maybe after a
+ *  JumpC, and jumps appear nowhere else. The exit block, if control can leave
+ *  the function at all, is last and falls through to the end.
+ * return_symbol:
+ *  the version of the return symbol that is live at the end of the exit
+ * pointers:
+ *  the symbols which hold pointers to the heap, every version of a pointer
+ *  symbol included
+ */
+#[derive(Debug, Clone)]
+pub struct SSAFunction {
+    pub arguments: Vec<Symbol>,
+    pub return_symbol: Symbol,
+    pub blocks: Vec<Block>,
+    pub pointers: HashSet<Symbol>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub label: Label,
+    pub phis: Vec<Phi>,
+    pub instructions: Vec<LIRInstruction>,
+}
+
+/*
+ * A phi assigns the symbol paired with the block control came from. A
+ * predecessor missing from sources never assigned the symbol.
+ */
+#[derive(Debug, Clone)]
+pub struct Phi {
+    pub assign_to: Symbol,
+    pub sources: Vec<(Label, Symbol)>,
+}
+
+/*
+ * An optimization pass, run on each function between construct_ssa and
+ * destruct_ssa. Passes must keep the shape of blocks described on
+ * SSAFunction and assign each symbol once.
+ */
+pub type Pass = fn(&mut SSAFunction, &mut SymbolGenerator);
+
+// The passes -O runs, in order
+pub const PASSES: &[Pass] = &[propagate_constants, propagate_copies, number_values];
+
+/*
+ * Optimize
+ *
+ * Run the passes in order on every function of the program in SSA form.
+ */
+pub fn optimize(
+    program: LIRProgram,
+    label_gen: &mut LabelGenerator,
+    symbol_gen: &mut SymbolGenerator,
+    passes: &[Pass],
+) -> LIRProgram {
+    let mut optimize_function = |function: &LIRFunction| {
+        let mut ssa_function = construct_ssa(function, label_gen, symbol_gen);
+        for pass in passes.iter() {
+            pass(&mut ssa_function, symbol_gen);
+        }
+        destruct_ssa(ssa_function, label_gen, symbol_gen)
+    };
+
+    LIRProgram {
+        main_function: optimize_function(&program.main_function),
+        other_functions: program
+            .other_functions
+            .iter()
+            .map(|(label, function)| (*label, optimize_function(function)))
+            .collect(),
+    }
+}
+
+/*
+ * Optimize a program
+ *
+ * What -O does to a lowered program: run PASSES on every function, then
+ * remove the dead code they leave. trap_overflow keeps arithmetic that can
+ * overflow, as it is said to on eliminate_dead_code.
+ */
+pub fn optimize_program(
+    program: LIRProgram,
+    label_gen: &mut LabelGenerator,
+    symbol_gen: &mut SymbolGenerator,
+    trap_overflow: bool,
+) -> LIRProgram {
+    let mut optimized_program = optimize(program, label_gen, symbol_gen, PASSES);
+    eliminate_dead_code(&mut optimized_program.main_function, trap_overflow);
+    for function in optimized_program.other_functions.values_mut() {
+        eliminate_dead_code(function, trap_overflow);
+    }
+    optimized_program
+}
+
+impl Block {
+    /*
+     * The blocks control can go to from this one, in the order of its jumps,
+     * each once.
+     */
+    pub fn successors(&self) -> Vec<Label> {
+        let mut successors = vec![];
+        for instruction in self.instructions.iter() {
+            match instruction {
+                LIRInstruction::Jump { to } | LIRInstruction::JumpC { to, .. }
+                    if !successors.contains(to) =>
+                {
+                    successors.push(*to)
+                }
+                _ => (),
+            }
+        }
+        successors
+    }
+
+    pub fn is_exit(&self) -> bool {
+        !matches!(self.instructions.last(), Some(LIRInstruction::Jump { .. }))
+    }
+}
+
+impl SSAFunction {
+    pub fn block_indices(&self) -> HashMap<Label, usize> {
+        self.blocks
+            .iter()
+            .enumerate()
+            .map(|(index, block)| (block.label, index))
+            .collect()
+    }
+
+    pub fn predecessors(&self) -> HashMap<Label, Vec<Label>> {
+        let mut predecessors: HashMap<Label, Vec<Label>> = self
+            .blocks
+            .iter()
+            .map(|block| (block.label, vec![]))
+            .collect();
+        for block in self.blocks.iter() {
+            for successor in block.successors() {
+                predecessors.get_mut(&successor).unwrap().push(block.label);
+            }
+        }
+        predecessors
+    }
+
+    /*
+     * The immediate dominator of every block but the entry, found on the
+     * control flow graph of the function's instructions.
+     */
+    pub fn immediate_dominators(&self) -> HashMap<Label, Label> {
+        let mut listing = vec![];
+        let mut block_of_index = vec![];
+        for block in self.blocks.iter() {
+            listing.push(LIRAssembly::Label(block.label));
+            listing.extend(
+                block
+                    .instructions
+                    .iter()
+                    .cloned()
+                    .map(LIRAssembly::Instruction),
+            );
+            block_of_index.resize(listing.len(), block.label);
+        }
+        let cfg = construct_control_flow_graph_lir(&LIRFunction {
+            locals: vec![],
+            arguments: vec![],
+            return_symbol: self.return_symbol,
+            instruction_listing: listing,
+            pointers: HashSet::new(),
+        });
+
+        let start = cfg
+            .node_indices()
+            .into_iter()
+            .find(|node| cfg.node_weight(*node) == Node::Start)
+            .unwrap();
+        let dominators = simple_fast(&cfg.graph, start);
+
+        // A block is dominated by the block of the node that dominates its label
+        let mut immediate_dominators = HashMap::new();
+        for node in cfg.node_indices() {
+            let label = match cfg.node_weight(node) {
+                Node::Index(index) => match cfg.instructions[index] {
+                    LIRAssembly::Label(label) => label,
+                    _ => continue,
+                },
+                _ => continue,
+            };
+            if let Some(dominator) = dominators.immediate_dominator(node) {
+                if let Node::Index(index) = cfg.node_weight(dominator) {
+                    immediate_dominators.insert(label, block_of_index[index]);
+                }
+            }
+        }
+        immediate_dominators
+    }
+
+    /*
+     * The blocks each block immediately dominates, in layout order.
+     */
+    pub fn dominator_tree(&self) -> HashMap<Label, Vec<Label>> {
+        let immediate_dominators = self.immediate_dominators();
+        let mut tree: HashMap<Label, Vec<Label>> = self
+            .blocks
+            .iter()
+            .map(|block| (block.label, vec![]))
+            .collect();
+        for block in self.blocks.iter() {
+            if let Some(dominator) = immediate_dominators.get(&block.label) {
+                tree.get_mut(dominator).unwrap().push(block.label);
+            }
+        }
+        tree
+    }
+
+    /*
+     * Dominance frontiers
+     *
+     * The blocks where a block's dominance ends: those it doesn't strictly
+     * dominate but which have a predecessor it dominates. Walk up the
+     * dominator tree from each predecessor of a join to the join's
+     * immediate dominator.
+     */
+    pub fn dominance_frontiers(&self) -> HashMap<Label, HashSet<Label>> {
+        let immediate_dominators = self.immediate_dominators();
+        let mut frontiers: HashMap<Label, HashSet<Label>> = self
+            .blocks
+            .iter()
+            .map(|block| (block.label, HashSet::new()))
+            .collect();
+        for (block, predecessors) in self.predecessors() {
+            if predecessors.len() < 2 {
+                continue;
+            }
+            let dominator = immediate_dominators.get(&block);
+            for predecessor in predecessors {
+                let mut runner = Some(predecessor);
+                while let Some(current) = runner {
+                    if Some(&current) == dominator {
+                        break;
+                    }
+                    frontiers.get_mut(&current).unwrap().insert(block);
+                    runner = immediate_dominators.get(&current).copied();
+                }
+            }
+        }
+        frontiers
+    }
+
+    /*
+     * Liveness
+     *
+     * The symbols each block may read before assigning them, the return
+     * symbol being read at the end of the exit block. Phis are left out.
+     */
+    pub fn live_in(&self) -> HashMap<Label, HashSet<Symbol>> {
+        // What each block reads before assigning, and what it assigns
+        let mut exposed = HashMap::new();
+        let mut assigns = HashMap::new();
+        for block in self.blocks.iter() {
+            let mut read = HashSet::new();
+            let mut assigned_here = HashSet::new();
+            for instruction in block.instructions.iter() {
+                for symbol in uses(instruction) {
+                    if !assigned_here.contains(&symbol) {
+                        read.insert(symbol);
+                    }
+                }
+                assigned_here.extend(assigned(instruction));
+            }
+            if block.is_exit() && !assigned_here.contains(&self.return_symbol) {
+                read.insert(self.return_symbol);
+            }
+            exposed.insert(block.label, read);
+            assigns.insert(block.label, assigned_here);
+        }
+
+        let mut live_in = exposed.clone();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in self.blocks.iter().rev() {
+                let mut live = exposed[&block.label].clone();
+                for successor in block.successors() {
+                    live.extend(
+                        live_in[&successor]
+                            .iter()
+                            .filter(|symbol| !assigns[&block.label].contains(symbol)),
+                    );
+                }
+                if live.len() != live_in[&block.label].len() {
+                    live_in.insert(block.label, live);
+                    changed = true;
+                }
+            }
+        }
+        live_in
+    }
+
+    /*
+     * Drop the blocks control can't reach from the entry, and their phi
+     * sources.
+     */
+    pub fn remove_unreachable_blocks(&mut self) {
+        let indices = self.block_indices();
+        let mut reachable = HashSet::new();
+        let mut work_list = vec![self.blocks[0].label];
+        while let Some(label) = work_list.pop() {
+            if reachable.insert(label) {
+                work_list.extend(self.blocks[indices[&label]].successors());
+            }
+        }
+
+        self.blocks.retain(|block| reachable.contains(&block.label));
+        for block in self.blocks.iter_mut() {
+            for phi in block.phis.iter_mut() {
+                phi.sources.retain(|(label, _)| reachable.contains(label));
+            }
+        }
+    }
+}
+
+/*
+ * The symbols an instruction reads
+ */
+pub fn uses(instruction: &LIRInstruction) -> Vec<Symbol> {
+    match instruction {
+        LIRInstruction::Nop
+        | LIRInstruction::IntLit { .. }
+        | LIRInstruction::StringLit { .. }
+        | LIRInstruction::Jump { .. } => vec![],
+        LIRInstruction::StoreToMemoryAtOffset {
+            location,
+            offset,
+            value,
+        } => vec![*location, *offset, *value],
+        LIRInstruction::LoadFromMemoryAtOffset {
+            location, offset, ..
+        } => vec![*location, *offset],
+        LIRInstruction::Assign { id, .. } => vec![*id],
+        LIRInstruction::Negate { value, .. } => vec![*value],
+        LIRInstruction::BinaryOp { left, right, .. } => vec![*left, *right],
+        LIRInstruction::Call { args, .. } => args.clone(),
+        LIRInstruction::JumpC { condition, .. } => vec![condition.left, condition.right],
+    }
+}
+
+pub fn uses_mut(instruction: &mut LIRInstruction) -> Vec<&mut Symbol> {
+    match instruction {
+        LIRInstruction::Nop
+        | LIRInstruction::IntLit { .. }
+        | LIRInstruction::StringLit { .. }
+        | LIRInstruction::Jump { .. } => vec![],
+        LIRInstruction::StoreToMemoryAtOffset {
+            location,
+            offset,
+            value,
+        } => vec![location, offset, value],
+        LIRInstruction::LoadFromMemoryAtOffset {
+            location, offset, ..
+        } => vec![location, offset],
+        LIRInstruction::Assign { id, .. } => vec![id],
+        LIRInstruction::Negate { value, .. } => vec![value],
+        LIRInstruction::BinaryOp { left, right, .. } => vec![left, right],
+        LIRInstruction::Call { args, .. } => args.iter_mut().collect(),
+        LIRInstruction::JumpC { condition, .. } => vec![&mut condition.left, &mut condition.right],
+    }
+}
+
+/*
+ * The symbol an instruction assigns, if any
+ */
+pub fn assigned(instruction: &LIRInstruction) -> Option<Symbol> {
+    match instruction {
+        LIRInstruction::IntLit { assign_to, .. }
+        | LIRInstruction::StringLit { assign_to, .. }
+        | LIRInstruction::LoadFromMemoryAtOffset { assign_to, .. }
+        | LIRInstruction::Assign { assign_to, .. }
+        | LIRInstruction::Negate { assign_to, .. }
+        | LIRInstruction::BinaryOp { assign_to, .. }
+        | LIRInstruction::Call { assign_to, .. } => Some(*assign_to),
+        _ => None,
+    }
+}
+
+fn assigned_mut(instruction: &mut LIRInstruction) -> Option<&mut Symbol> {
+    match instruction {
+        LIRInstruction::IntLit { assign_to, .. }
+        | LIRInstruction::StringLit { assign_to, .. }
+        | LIRInstruction::LoadFromMemoryAtOffset { assign_to, .. }
+        | LIRInstruction::Assign { assign_to, .. }
+        | LIRInstruction::Negate { assign_to, .. }
+        | LIRInstruction::BinaryOp { assign_to, .. }
+        | LIRInstruction::Call { assign_to, .. } => Some(assign_to),
+        _ => None,
+    }
+}
+
+/*
+ * Construct SSA
+ *
+ * Split the function into blocks, place phis where definitions of a symbol
+ * meet and rename every assignment to a fresh symbol.
+ */
+pub fn construct_ssa(
+    function: &LIRFunction,
+    label_gen: &mut LabelGenerator,
+    symbol_gen: &mut SymbolGenerator,
+) -> SSAFunction {
+    let mut ssa_function = SSAFunction {
+        arguments: function.arguments.clone(),
+        return_symbol: function.return_symbol,
+        blocks: split_blocks(&function.instruction_listing, label_gen),
+        pointers: function.pointers.clone(),
+    };
+    ssa_function.remove_unreachable_blocks();
+
+    let variables = place_phis(&mut ssa_function);
+    let mut renamer = Renamer {
+        variables,
+        tree: ssa_function.dominator_tree(),
+        indices: ssa_function.block_indices(),
+        versions: ssa_function
+            .arguments
+            .iter()
+            .map(|argument| (*argument, vec![*argument]))
+            .collect(),
+        return_variable: ssa_function.return_symbol,
+        symbol_gen,
+    };
+    let entry = ssa_function.blocks[0].label;
+    renamer.rename_block(&mut ssa_function, entry);
+
+    ssa_function
+}
+
+/*
+ * Split a listing into blocks, starting with a new entry block and making
+ * fall through between blocks an explicit jump. Instructions that follow a
+ * jump without a label start a block nothing reaches.
+ */
+fn split_blocks(listing: &[LIRAssembly], label_gen: &mut LabelGenerator) -> Vec<Block> {
+    let new_block = |label| Block {
+        label,
+        phis: vec![],
+        instructions: vec![],
+    };
+    let mut blocks = vec![new_block(label_gen.new_label())];
+    let mut ended = false;
+
+    for (index, assembly) in listing.iter().enumerate() {
+        let current = blocks.last_mut().unwrap();
+        match assembly {
+            LIRAssembly::Label(label) => {
+                if !ended {
+                    current
+                        .instructions
+                        .push(LIRInstruction::Jump { to: *label });
+                }
+                blocks.push(new_block(*label));
+                ended = false;
+            }
+            LIRAssembly::Instruction(instruction) => {
+                if ended {
+                    blocks.push(new_block(label_gen.new_label()));
+                }
+                let current = blocks.last_mut().unwrap();
+                current.instructions.push(instruction.clone());
+                ended = false;
+
+                match instruction {
+                    LIRInstruction::Jump { .. } => ended = true,
+                    // Fall through to the next label, or to a new block unless a
+                    // jump follows
+                    LIRInstruction::JumpC { .. } => {
+                        let next = match listing.get(index + 1) {
+                            Some(LIRAssembly::Label(label)) => *label,
+                            Some(LIRAssembly::Instruction(LIRInstruction::Jump { .. })) => continue,
+                            _ => {
+                                let label = label_gen.new_label();
+                                current
+                                    .instructions
+                                    .push(LIRInstruction::Jump { to: label });
+                                blocks.push(new_block(label));
+                                continue;
+                            }
+                        };
+                        current.instructions.push(LIRInstruction::Jump { to: next });
+                        ended = true;
+                    }
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    blocks
+}
+
+/*
+ * Place phis
+ *
+ * Put a phi for a symbol at the dominance frontier of every block that
+ * assigns it, including the blocks given phis, wherever the symbol is live.
+ * A phi for a dead symbol could copy from a version that was never assigned.
+ * Returns the symbol each phi is for, by block.
+ */
+fn place_phis(function: &mut SSAFunction) -> HashMap<Label, Vec<Symbol>> {
+    let entry = function.blocks[0].label;
+    let mut assigned_in: HashMap<Symbol, HashSet<Label>> = HashMap::new();
+    for argument in function.arguments.iter() {
+        assigned_in.entry(*argument).or_default().insert(entry);
+    }
+    for block in function.blocks.iter() {
+        for symbol in block.instructions.iter().filter_map(assigned) {
+            assigned_in.entry(symbol).or_default().insert(block.label);
+        }
+    }
+
+    let live_in = function.live_in();
+    let frontiers = function.dominance_frontiers();
+    let indices = function.block_indices();
+    let mut variables: HashMap<Label, Vec<Symbol>> = function
+        .blocks
+        .iter()
+        .map(|block| (block.label, vec![]))
+        .collect();
+
+    let mut symbols: Vec<Symbol> = assigned_in.keys().copied().collect();
+    symbols.sort();
+    for symbol in symbols {
+        let assigned_in = &assigned_in[&symbol];
+        let mut work_list: Vec<Label> = assigned_in.iter().copied().collect();
+        let mut has_phi = HashSet::new();
+        while let Some(label) = work_list.pop() {
+            for frontier in frontiers[&label].iter() {
+                if live_in[frontier].contains(&symbol) && has_phi.insert(*frontier) {
+                    function.blocks[indices[frontier]].phis.push(Phi {
+                        assign_to: symbol,
+                        sources: vec![],
+                    });
+                    variables.get_mut(frontier).unwrap().push(symbol);
+                    if !assigned_in.contains(frontier) {
+                        work_list.push(*frontier);
+                    }
+                }
+            }
+        }
+    }
+
+    variables
+}
+
+/*
+ * Renaming walks the dominator tree keeping, for each original symbol, the
+ * stack of versions assigned on the way down. The top is the version a read
+ * sees; a read with no version left the symbol unassigned and keeps its name.
+ */
+struct Renamer<'a> {
+    variables: HashMap<Label, Vec<Symbol>>,
+    tree: HashMap<Label, Vec<Label>>,
+    indices: HashMap<Label, usize>,
+    versions: HashMap<Symbol, Vec<Symbol>>,
+    return_variable: Symbol,
+    symbol_gen: &'a mut SymbolGenerator,
+}
+
+impl Renamer<'_> {
+    fn current(&self, symbol: Symbol) -> Symbol {
+        self.versions
+            .get(&symbol)
+            .and_then(|versions| versions.last())
+            .copied()
+            .unwrap_or(symbol)
+    }
+
+    fn new_version(&mut self, symbol: Symbol, pointers: &mut HashSet<Symbol>) -> Symbol {
+        let version = self.symbol_gen.new_symbol();
+        if pointers.contains(&symbol) {
+            pointers.insert(version);
+        }
+        self.versions.entry(symbol).or_default().push(version);
+        version
+    }
+
+    fn rename_block(&mut self, function: &mut SSAFunction, label: Label) {
+        let index = self.indices[&label];
+        let mut assigned_here = vec![];
+
+        let mut phis = std::mem::take(&mut function.blocks[index].phis);
+        for (phi, symbol) in phis.iter_mut().zip(self.variables[&label].clone()) {
+            phi.assign_to = self.new_version(symbol, &mut function.pointers);
+            assigned_here.push(symbol);
+        }
+        function.blocks[index].phis = phis;
+
+        let mut instructions = std::mem::take(&mut function.blocks[index].instructions);
+        for instruction in instructions.iter_mut() {
+            for used in uses_mut(instruction) {
+                *used = self.current(*used);
+            }
+            if let Some(symbol) = assigned_mut(instruction) {
+                let original = *symbol;
+                *symbol = self.new_version(original, &mut function.pointers);
+                assigned_here.push(original);
+            }
+        }
+        function.blocks[index].instructions = instructions;
+
+        if function.blocks[index].is_exit() {
+            function.return_symbol = self.current(self.return_variable);
+        }
+
+        // Tell the phis of each successor what this block leaves behind
+        for successor in function.blocks[index].successors() {
+            let successor_index = self.indices[&successor];
+            let symbols = &self.variables[&successor];
+            for (phi, symbol) in function.blocks[successor_index]
+                .phis
+                .iter_mut()
+                .zip(symbols.iter())
+            {
+                if let Some(version) = self.versions.get(symbol).and_then(|v| v.last()) {
+                    phi.sources.push((label, *version));
+                }
+            }
+        }
+
+        for child in self.tree[&label].clone() {
+            self.rename_block(function, child);
+        }
+
+        for symbol in assigned_here {
+            self.versions.get_mut(&symbol).unwrap().pop();
+        }
+    }
+}
+
+/*
+ * Destruct SSA
+ *
+ * Replace each phi with copies at the ends of its predecessors and lay the
+ * blocks back out as a listing. A predecessor with a conditional jump gets a
+ * new block on the edge for the copies, so they only run on the way to the
+ * phi's block and can't change the condition.
+ */
+pub fn destruct_ssa(
+    mut function: SSAFunction,
+    label_gen: &mut LabelGenerator,
+    symbol_gen: &mut SymbolGenerator,
+) -> LIRFunction {
+    split_edges_to_phis(&mut function, label_gen);
+
+    // Gather the copies each predecessor makes, which happen all at once
+    let mut copies: HashMap<Label, Vec<(Symbol, Symbol)>> = HashMap::new();
+    for block in function.blocks.iter_mut() {
+        for phi in block.phis.drain(..) {
+            for (predecessor, source) in phi.sources {
+                copies
+                    .entry(predecessor)
+                    .or_default()
+                    .push((phi.assign_to, source));
+            }
+        }
+    }
+    for block in function.blocks.iter_mut() {
+        if let Some(parallel_copies) = copies.remove(&block.label) {
+            let sequence = sequentialize(parallel_copies, symbol_gen, &mut function.pointers);
+            let jump = block.instructions.len() - 1;
+            block.instructions.splice(jump..jump, sequence);
+        }
+    }
+
+    // Lay out the blocks, leaving out jumps to the next block
+    let mut listing = vec![];
+    for (index, block) in function.blocks.iter().enumerate() {
+        listing.push(LIRAssembly::Label(block.label));
+        let mut instructions = block.instructions.clone();
+        if let (Some(LIRInstruction::Jump { to }), Some(next)) =
+            (instructions.last(), function.blocks.get(index + 1))
+        {
+            if *to == next.label {
+                instructions.pop();
+            }
+        }
+        listing.extend(instructions.into_iter().map(LIRAssembly::Instruction));
+    }
+
+    let locals = collect_locals(&listing, &function.arguments);
+    let pointers = function
+        .arguments
+        .iter()
+        .chain(locals.iter())
+        .filter(|symbol| function.pointers.contains(symbol))
+        .copied()
+        .collect();
+    LIRFunction {
+        locals,
+        arguments: function.arguments,
+        return_symbol: function.return_symbol,
+        instruction_listing: listing,
+        pointers,
+    }
+}
+
+/*
+ * Give every edge from a block with a conditional jump to a block with phis
+ * a block of its own, placed after the predecessor, which ends in a jump.
+ */
+fn split_edges_to_phis(function: &mut SSAFunction, label_gen: &mut LabelGenerator) {
+    let mut index = 0;
+    while index < function.blocks.len() {
+        let conditional = function.blocks[index]
+            .instructions
+            .iter()
+            .any(|instruction| matches!(instruction, LIRInstruction::JumpC { .. }));
+        if !conditional {
+            index += 1;
+            continue;
+        }
+
+        let predecessor = function.blocks[index].label;
+        let indices = function.block_indices();
+        for successor in function.blocks[index].successors() {
+            let successor_index = indices[&successor];
+            if function.blocks[successor_index].phis.is_empty() {
+                continue;
+            }
+
+            // Route the edge through the new block
+            let edge = label_gen.new_label();
+            for phi in function.blocks[successor_index].phis.iter_mut() {
+                for (label, _) in phi.sources.iter_mut() {
+                    if *label == predecessor {
+                        *label = edge;
+                    }
+                }
+            }
+            for instruction in function.blocks[index].instructions.iter_mut() {
+                match instruction {
+                    LIRInstruction::Jump { to } | LIRInstruction::JumpC { to, .. }
+                        if *to == successor =>
+                    {
+                        *to = edge
+                    }
+                    _ => (),
+                }
+            }
+            function.blocks.insert(
+                index + 1,
+                Block {
+                    label: edge,
+                    phis: vec![],
+                    instructions: vec![LIRInstruction::Jump { to: successor }],
+                },
+            );
+        }
+        index += 1;
+    }
+}
+
+/*
+ * Sequentialize parallel copies
+ *
+ * Order copies that happen all at once, (destination, source), into Assigns
+ * with the same effect. A copy can go once no other copy still reads its
+ * destination; when none can, the rest form cycles, so one destination is
+ * saved in a new symbol and read from there instead.
+ */
+pub fn sequentialize(
+    mut copies: Vec<(Symbol, Symbol)>,
+    symbol_gen: &mut SymbolGenerator,
+    pointers: &mut HashSet<Symbol>,
+) -> Vec<LIRInstruction> {
+    copies.retain(|(destination, source)| destination != source);
+    let mut sequence = vec![];
+    while !copies.is_empty() {
+        let ready = copies
+            .iter()
+            .position(|(destination, _)| copies.iter().all(|(_, source)| source != destination));
+        match ready {
+            Some(index) => {
+                let (destination, source) = copies.remove(index);
+                sequence.push(LIRInstruction::Assign {
+                    assign_to: destination,
+                    id: source,
+                });
+            }
+            None => {
+                let saved = copies[0].0;
+                let temporary = symbol_gen.new_symbol();
+                if pointers.contains(&saved) {
+                    pointers.insert(temporary);
+                }
+                sequence.push(LIRInstruction::Assign {
+                    assign_to: temporary,
+                    id: saved,
+                });
+                for (_, source) in copies.iter_mut() {
+                    if *source == saved {
+                        *source = temporary;
+                    }
+                }
+            }
+        }
+    }
+    sequence
+}
+
+impl fmt::Display for SSAFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for block in self.blocks.iter() {
+            writeln!(f, "{}:", block.label)?;
+            for phi in block.phis.iter() {
+                let sources: Vec<String> = phi
+                    .sources
+                    .iter()
+                    .map(|(label, symbol)| format!("{}: {}", label, symbol))
+                    .collect();
+                writeln!(f, "\t{} = phi({})", phi.assign_to, sources.join(", "))?;
+            }
+            for instruction in block.instructions.iter() {
+                writeln!(f, "\t{}", instruction)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,257 @@
+use crate::backend::{assign_homes, fix_up, select, BackendOptions, GlobalInfo};
+use crate::check_type::type_check;
+use crate::checked_grammar::CheckedProgram;
+use crate::common::{LabelGenerator, SymbolGenerator};
+use crate::diagnostic::Diagnostic;
+use crate::lir::LIRProgram;
+use crate::lowering::{lower, LoweringOptions};
+use crate::source_grammar::Program;
+use crate::ssa::optimize_program;
+use crate::x64::X64Program;
+use crate::x64s::X64SProgram;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/*
+ * The points in the pipeline where a program can be written out with --emit
+ * and read back in with --from: the parsed AST, the type checked program, LIR,
+ * x64s straight from instruction selection and after fix up, and the final
+ * assembly.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    Ast,
+    Checked,
+    Lir,
+    X64s,
+    FixedX64s,
+    Asm,
+}
+
+impl Stage {
+    pub const NAMES: [&'static str; 6] = ["ast", "checked", "lir", "x64s", "fixed-x64s", "asm"];
+}
+
+impl FromStr for Stage {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "ast" => Ok(Stage::Ast),
+            "checked" => Ok(Stage::Checked),
+            "lir" => Ok(Stage::Lir),
+            "x64s" => Ok(Stage::X64s),
+            "fixed-x64s" => Ok(Stage::FixedX64s),
+            "asm" => Ok(Stage::Asm),
+            _ => Err(format!("unknown stage `{}`", name)),
+        }
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Stage::Ast => "ast",
+            Stage::Checked => "checked",
+            Stage::Lir => "lir",
+            Stage::X64s => "x64s",
+            Stage::FixedX64s => "fixed-x64s",
+            Stage::Asm => "asm",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/*
+ * How a program is written out. RON and JSON can be read back in; pretty
+ * text, the Display of the stage (or Debug where there is none), is only for
+ * reading.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Format {
+    #[default]
+    Ron,
+    Json,
+    Pretty,
+}
+
+impl Format {
+    pub const NAMES: [&'static str; 3] = ["ron", "json", "pretty"];
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "ron" => Ok(Format::Ron),
+            "json" => Ok(Format::Json),
+            "pretty" => Ok(Format::Pretty),
+            _ => Err(format!("unknown format `{}`", name)),
+        }
+    }
+}
+
+/*
+ * The options for the whole pipeline, each stage taking the ones it needs
+ *
+ * optimize: optimize the program once it is lowered with
+ * ssa::optimize_program, which keeps the arithmetic the backend's
+ * trap_overflow makes exit.
+ */
+#[derive(Debug, Copy, Clone, Default)]
+pub struct PipelineOptions {
+    pub lowering: LoweringOptions,
+    pub optimize: bool,
+    pub backend: BackendOptions,
+}
+
+/*
+ * A program partway through the pipeline. LIR carries the generators that
+ * the backend makes new labels and symbols with.
+ */
+#[derive(Debug)]
+pub enum StagedProgram {
+    Ast(Program),
+    Checked(CheckedProgram),
+    Lir(LIRProgram, LabelGenerator, SymbolGenerator),
+    X64s(X64SProgram),
+    FixedX64s(X64SProgram),
+    Asm(X64Program),
+}
+
+impl StagedProgram {
+    pub fn stage(&self) -> Stage {
+        match self {
+            StagedProgram::Ast(_) => Stage::Ast,
+            StagedProgram::Checked(_) => Stage::Checked,
+            StagedProgram::Lir(..) => Stage::Lir,
+            StagedProgram::X64s(_) => Stage::X64s,
+            StagedProgram::FixedX64s(_) => Stage::FixedX64s,
+            StagedProgram::Asm(_) => Stage::Asm,
+        }
+    }
+
+    /*
+     * Run the next stage of the pipeline. Only type checking can fail.
+     */
+    pub fn advance(self, options: PipelineOptions) -> Result<StagedProgram, Vec<Diagnostic>> {
+        let advanced = match self {
+            StagedProgram::Ast(program) => StagedProgram::Checked(type_check(program)?),
+            StagedProgram::Checked(program) => {
+                let (mut lir_program, mut label_gen, mut symbol_gen) =
+                    lower(program, options.lowering);
+                if options.optimize {
+                    lir_program = optimize_program(
+                        lir_program,
+                        &mut label_gen,
+                        &mut symbol_gen,
+                        options.backend.trap_overflow,
+                    );
+                }
+                StagedProgram::Lir(lir_program, label_gen, symbol_gen)
+            }
+            StagedProgram::Lir(program, label_gen, symbol_gen) => {
+                let mut state = GlobalInfo {
+                    label_gen,
+                    symbol_gen,
+                    string_literals: HashMap::new(),
+                    options: options.backend,
+                };
+                StagedProgram::X64s(select(program, &mut state))
+            }
+            StagedProgram::X64s(program) => StagedProgram::FixedX64s(fix_up(program)),
+            StagedProgram::FixedX64s(program) => {
+                let mut compiled_program = assign_homes(program);
+                compiled_program.target = options.backend.target;
+                StagedProgram::Asm(compiled_program)
+            }
+            StagedProgram::Asm(program) => StagedProgram::Asm(program),
+        };
+        Ok(advanced)
+    }
+
+    /*
+     * Optimize a program read in at its stage, as advancing would have once
+     * it lowered it. Advancing still optimizes a program read before LIR, and
+     * a program after LIR is past where optimizing happens.
+     */
+    pub fn optimize(self, options: PipelineOptions) -> Result<StagedProgram, String> {
+        match self {
+            StagedProgram::Ast(_) | StagedProgram::Checked(_) => Ok(self),
+            StagedProgram::Lir(program, mut label_gen, mut symbol_gen) => {
+                let optimized_program = optimize_program(
+                    program,
+                    &mut label_gen,
+                    &mut symbol_gen,
+                    options.backend.trap_overflow,
+                );
+                Ok(StagedProgram::Lir(optimized_program, label_gen, symbol_gen))
+            }
+            _ => Err(format!(
+                "-O optimizes LIR, so can't optimize a {} program",
+                self.stage()
+            )),
+        }
+    }
+
+    /*
+     * Read a program written out at a stage in RON or JSON.
+     */
+    pub fn read(stage: Stage, format: Format, text: &str) -> Result<StagedProgram, String> {
+        let program = match stage {
+            Stage::Ast => StagedProgram::Ast(deserialize(format, text)?),
+            Stage::Checked => StagedProgram::Checked(deserialize(format, text)?),
+            Stage::Lir => {
+                let program: LIRProgram = deserialize(format, text)?;
+                let (label_gen, symbol_gen) = program.generators();
+                StagedProgram::Lir(program, label_gen, symbol_gen)
+            }
+            Stage::X64s => StagedProgram::X64s(deserialize(format, text)?),
+            Stage::FixedX64s => StagedProgram::FixedX64s(deserialize(format, text)?),
+            Stage::Asm => StagedProgram::Asm(deserialize(format, text)?),
+        };
+        Ok(program)
+    }
+
+    pub fn write(&self, format: Format) -> Result<String, String> {
+        match (self, format) {
+            (StagedProgram::Ast(program), Format::Pretty) => Ok(format!("{:#?}\n", program)),
+            (StagedProgram::Checked(program), Format::Pretty) => Ok(format!("{:#?}\n", program)),
+            (StagedProgram::Lir(program, ..), Format::Pretty) => Ok(format!("{}", program)),
+            (StagedProgram::X64s(program), Format::Pretty)
+            | (StagedProgram::FixedX64s(program), Format::Pretty) => Ok(format!("{}", program)),
+            (StagedProgram::Asm(program), Format::Pretty) => Ok(format!("{}\n", program)),
+            (StagedProgram::Ast(program), _) => serialize(format, program),
+            (StagedProgram::Checked(program), _) => serialize(format, program),
+            (StagedProgram::Lir(program, ..), _) => serialize(format, program),
+            (StagedProgram::X64s(program), _) | (StagedProgram::FixedX64s(program), _) => {
+                serialize(format, program)
+            }
+            (StagedProgram::Asm(program), _) => serialize(format, program),
+        }
+    }
+}
+
+fn serialize<T: Serialize>(format: Format, value: &T) -> Result<String, String> {
+    let text = match format {
+        Format::Ron => ron::ser::to_string_pretty(value, Default::default())
+            .map_err(|error| error.to_string())?,
+        Format::Json => serde_json::to_string_pretty(value).map_err(|error| error.to_string())?,
+        Format::Pretty => unreachable!("pretty text is written with Display"),
+    };
+    Ok(text + "\n")
+}
+
+fn deserialize<T: DeserializeOwned>(format: Format, text: &str) -> Result<T, String> {
+    match format {
+        Format::Ron => ron::de::from_str(text).map_err(|error| error.to_string()),
+        Format::Json => serde_json::from_str(text).map_err(|error| error.to_string()),
+        Format::Pretty => Err("pretty text can't be read back in".to_string()),
+    }
+}
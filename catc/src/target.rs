@@ -0,0 +1,75 @@
+use crate::common::Label;
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/*
+ * The platform the emitted assembly is for. Targets agree on instructions and
+ * the calling convention but not on how symbols are spelled or which sections
+ * and directives the assembler expects.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Target {
+    #[default]
+    Linux,
+    MacOs,
+}
+
+impl Target {
+    pub const NAMES: [&'static str; 2] = ["x86_64-linux", "x86_64-macos"];
+
+    /*
+     * The name of a label in the assembly. Mach-O prefixes C symbols with an
+     * underscore; labels the compiler generates are spelled the same on both
+     * targets.
+     */
+    pub fn symbol(self, label: Label) -> String {
+        match (self, label) {
+            (_, Label::Uid(_)) | (Target::Linux, _) => label.to_string(),
+            (Target::MacOs, _) => format!("_{}", label),
+        }
+    }
+
+    /*
+     * The section directive for string literals. They carry a garbage
+     * collector header holding zero words, so on macOS they go in __const rather
+     * than __cstring, which the linker splits and merges at every NUL.
+     */
+    pub fn string_section(self) -> &'static str {
+        match self {
+            Target::Linux => "\t.section\t.rodata",
+            Target::MacOs => "\t.section\t__TEXT,__const",
+        }
+    }
+
+    /*
+     * ELF symbols get a type and size, so that debuggers and profilers see
+     * functions; Mach-O has neither directive.
+     */
+    pub fn is_elf(self) -> bool {
+        self == Target::Linux
+    }
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "x86_64-linux" => Ok(Target::Linux),
+            "x86_64-macos" => Ok(Target::MacOs),
+            _ => Err(format!("unknown target `{}`", name)),
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Linux => write!(f, "x86_64-linux"),
+            Target::MacOs => write!(f, "x86_64-macos"),
+        }
+    }
+}
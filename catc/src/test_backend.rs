@@ -0,0 +1,451 @@
+use std::collections::HashMap;
+
+use crate::backend::{fix_up, register_alloc, select, BackendOptions, GlobalInfo};
+use crate::control_flow_graph::{construct_control_flow_graph_x64s, liveness, Node};
+use crate::lowering::LoweringOptions;
+use crate::target::Target;
+use crate::test_common::{compile_source, lower_source};
+use crate::x64::X64Register::*;
+use crate::x64::{
+    Operand, Operands, X64Assembly, X64Instruction, X64Register, X64Value, X64opCode,
+};
+use crate::x64s::{SOperand, SOperands, X64SAssembly, X64SFunction, X64SInstruction, X64SProgram};
+
+fn select_and_fix_up(program: &str) -> X64SProgram {
+    let (lir_program, label_gen, symbol_gen) = lower_source(program, LoweringOptions::default());
+    let mut state = GlobalInfo {
+        label_gen,
+        symbol_gen,
+        string_literals: HashMap::new(),
+        options: BackendOptions::default(),
+    };
+    fix_up(select(lir_program, &mut state))
+}
+
+fn functions(program: &X64SProgram) -> Vec<&X64SFunction> {
+    vec![&program.main_function]
+        .into_iter()
+        .chain(program.other_functions.values())
+        .collect()
+}
+
+// Every symbol written by an instruction must get a different color than
+// every other symbol live after that instruction, except for the source of a
+// movq, which holds the same value.
+fn assert_coloring_respects_liveness(function: &X64SFunction) {
+    let colors = register_alloc(function);
+    let cfg = liveness(construct_control_flow_graph_x64s(function));
+
+    for n in cfg.node_indices() {
+        let live_out: Vec<_> = cfg
+            .succ(n)
+            .into_iter()
+            .flat_map(|s| {
+                let mut live_in = cfg.gen_node(s);
+                for symbol in cfg.node_data(s) {
+                    if !cfg.kill_node(s).contains(&symbol) {
+                        live_in.insert(symbol);
+                    }
+                }
+                live_in
+            })
+            .collect();
+        let source = match cfg.node_weight(n) {
+            Node::Index(index) => match &function.body[index] {
+                X64SAssembly::Instruction(X64SInstruction {
+                    op_code: X64opCode::Movq,
+                    args: SOperands::Two(SOperand::Symbol(source), SOperand::Symbol(_)),
+                }) => Some(*source),
+                _ => None,
+            },
+            _ => None,
+        };
+        for def in cfg.kill_node(n) {
+            for live in live_out
+                .iter()
+                .filter(|live| **live != def && Some(**live) != source)
+            {
+                if let (Some(Some(a)), Some(Some(b))) = (colors.get(&def), colors.get(live)) {
+                    assert_ne!(a, b, "{} and {} share a register", def, live);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_register_alloc_fib() {
+    let program = select_and_fix_up(
+        r#"
+    function fib (n : int) -> int {
+        if n = 0 or n = 1 then
+            1
+        else
+            fib(n - 1) + fib(n - 2)
+    }
+
+    function main () -> void {
+        let var res : int := fib(30)
+        in print_line_int(res)
+        end
+    }
+    "#,
+    );
+    for function in functions(&program) {
+        assert_coloring_respects_liveness(function);
+    }
+}
+
+#[test]
+fn test_register_alloc_pressure() {
+    let program = select_and_fix_up(
+        r#"
+    type intArray = array of int
+    function mix (a : int, b : int, c : int) -> int {
+        let var ta : int := a + b
+            var tb : int := b + c
+            var tc : int := c + a
+            var td : int := ta * tb
+            var te : int := tb * tc
+            var tf : int := tc * ta
+            var tg : int := td + te + tf
+            var arr : intArray := intArray [10] of tg
+        in (arr[ta] := tb; ta + tb + tc + td + te + tf + tg + arr[0])
+        end
+    }
+
+    function main () -> int {
+        let var r : int := 0 in
+            (for i := 1 to 5 do r := r + mix(i, i + 1, i * 3);
+            r)
+        end
+    }
+    "#,
+    );
+    for function in functions(&program) {
+        assert_coloring_respects_liveness(function);
+    }
+}
+
+#[test]
+fn test_register_alloc_uses_registers() {
+    let program = select_and_fix_up("function main () -> int { -(9 + 10 * 10 - (9/10)) }");
+    let colors = register_alloc(&program.main_function);
+
+    assert!(!colors.is_empty());
+    assert!(colors.values().all(|color| color.is_some()));
+}
+
+#[test]
+fn test_register_alloc_coalesces_copies() {
+    // Each copy is dead once copied, so every copy can be coalesced
+    let program = select_and_fix_up(
+        "function main () -> int {
+            let var a : int := 3
+                var b : int := a
+                var c : int := b
+            in (a := c + 1; a) end
+        }",
+    );
+    let function = &program.main_function;
+    let colors = register_alloc(function);
+    assert_coloring_respects_liveness(function);
+
+    let mut copies = 0;
+    for assembly in function.body.iter() {
+        if let X64SAssembly::Instruction(X64SInstruction {
+            op_code: X64opCode::Movq,
+            args: SOperands::Two(SOperand::Symbol(source), SOperand::Symbol(destination)),
+        }) = assembly
+        {
+            copies += 1;
+            assert!(colors[source].is_some());
+            assert_eq!(colors[source], colors[destination]);
+        }
+    }
+    assert!(copies > 0);
+}
+
+#[test]
+fn test_callee_saved_registers_preserved() {
+    let listing = compile_source(
+        r#"
+    function id (n : int) -> int { n }
+    function main () -> int {
+        let var a : int := 5
+            var b : int := 6 in
+            (id(2); a + b)
+        end
+    }
+    "#,
+        LoweringOptions::default(),
+        BackendOptions::default(),
+    )
+    .main_function
+    .instruction_listing;
+
+    let pushed: Vec<X64Register> = listing
+        .iter()
+        .filter_map(|assembly| match assembly {
+            X64Assembly::Instruction(X64Instruction {
+                op_code: X64opCode::Push,
+                args: Operands::One(Operand::Register(r)),
+            }) => Some(*r),
+            _ => None,
+        })
+        .collect();
+    let mut popped: Vec<X64Register> = listing
+        .iter()
+        .filter_map(|assembly| match assembly {
+            X64Assembly::Instruction(X64Instruction {
+                op_code: X64opCode::Pop,
+                args: Operands::One(Operand::Register(r)),
+            }) => Some(*r),
+            _ => None,
+        })
+        .collect();
+    popped.reverse();
+
+    // a and b are live across the call so they need callee saved registers,
+    // which are pushed after %rbp and popped in reverse order.
+    assert!(pushed.len() >= 3);
+    assert_eq!(pushed, popped);
+    assert!(pushed[1..]
+        .iter()
+        .all(|r| [Rbx, R12, R13, R14, R15].contains(r)));
+
+    let reserved = listing
+        .iter()
+        .find_map(|assembly| match assembly {
+            X64Assembly::Instruction(X64Instruction {
+                op_code: X64opCode::Sub,
+                args: Operands::Two(Operand::Immediate(X64Value::Absolute(n)), Operand::Register(Rsp)),
+            }) => Some(*n),
+            _ => None,
+        })
+        .unwrap();
+    let pushed_bytes = (pushed.len() as i64 - 1) * 8;
+    assert_eq!((reserved + pushed_bytes) % 16, 0);
+}
+
+#[test]
+fn test_stack_arguments() {
+    let program = select_and_fix_up(
+        r#"
+    function seven (a : int, b : int, c : int, d : int, e : int, f : int, g : int) -> int {
+        a + b + c + d + e + f + g
+    }
+    function main () -> int { seven(1, 2, 3, 4, 5, 6, 7) }
+    "#,
+    );
+    for function in functions(&program) {
+        assert_coloring_respects_liveness(function);
+    }
+
+    let main = format!("{}", program.main_function);
+    let callee = format!("{}", program.other_functions.values().next().unwrap());
+
+    // One argument on the stack plus eight bytes of padding keeps the call
+    // 16 byte aligned, and the callee finds it at 16(%rbp).
+    assert!(main.contains("sub\t$8, %rsp"));
+    assert_eq!(main.matches("pushq").count(), 1);
+    assert!(main.contains("add\t$16, %rsp"));
+    assert!(callee.contains("16(%rbp)"));
+}
+
+#[test]
+fn test_string_literals_escaped() {
+    let assembly = format!(
+        "{}",
+        compile_source(
+            r#"function main () -> void { print_string("say \"hi\"\\\n\001") }"#,
+            LoweringOptions::default(),
+            BackendOptions::default()
+        )
+    );
+    assert!(assembly.contains(r#".string "say \"hi\"\\\n\001""#));
+}
+
+#[test]
+fn test_bounds_checks() {
+    let source = "type ints = array of int
+        function main () -> int {
+            let var a : ints := ints [3] of 0 in (a[1] := 2; a[1]) end
+        }";
+    let assembly = |options| {
+        format!(
+            "{}",
+            compile_source(source, options, BackendOptions::default())
+        )
+    };
+
+    // One check for the store and one for the load
+    let checked = assembly(LoweringOptions::default());
+    assert_eq!(checked.matches("index_out_of_bounds").count(), 2);
+
+    let unchecked = assembly(LoweringOptions {
+        bounds_checks: false,
+    });
+    assert!(!unchecked.contains("index_out_of_bounds"));
+}
+
+#[test]
+fn test_pointers_live_across_calls_are_on_the_stack() {
+    let source = "type list = {head: int, tail: list}
+        function sum(l : list) -> int {
+            if l = nil then 0 else l.head + sum(l.tail)
+        }
+        function main () -> int {
+            let var l : list := list {head = 1, tail = nil}
+                var s : string := concat(\"a\", \"b\")
+            in (l := list {head = 2, tail = l}; print_line_string(s); sum(l) + size(s)) end
+        }";
+    let program = select_and_fix_up(source);
+
+    // The collector only looks in stack frames, so no pointer may be in a
+    // register while a call runs
+    for function in functions(&program) {
+        let colors = register_alloc(function);
+        let cfg = liveness(construct_control_flow_graph_x64s(function));
+        for n in cfg.node_indices() {
+            let is_call = match cfg.node_weight(n) {
+                Node::Index(index) => matches!(
+                    function.body[index],
+                    X64SAssembly::Instruction(X64SInstruction {
+                        op_code: X64opCode::Call,
+                        ..
+                    })
+                ),
+                _ => false,
+            };
+            if !is_call {
+                continue;
+            }
+            for s in cfg.succ(n) {
+                let live_in = cfg
+                    .node_data(s)
+                    .into_iter()
+                    .filter(|symbol| !cfg.kill_node(s).contains(symbol))
+                    .chain(cfg.gen_node(s));
+                for symbol in live_in {
+                    if function.pointers.contains(&symbol) {
+                        assert_eq!(colors[&symbol], None, "{} is live across a call", symbol);
+                    }
+                }
+            }
+        }
+    }
+
+    // Functions with pointers on the stack link them onto the shadow stack
+    let assembly = format!(
+        "{}",
+        compile_source(
+            source,
+            LoweringOptions::default(),
+            BackendOptions::default()
+        )
+    );
+    assert!(assembly.contains("gc_frames(%rip)"));
+}
+
+#[test]
+fn test_trap_overflow() {
+    let source = "function main () -> int {
+            let var a : int := 2 in -(a + a - a * a) end
+        }";
+    let assembly = |options| {
+        format!(
+            "{}",
+            compile_source(source, LoweringOptions::default(), options)
+        )
+    };
+
+    // One check each for the add, sub, imulq and neg
+    let trapped = assembly(BackendOptions {
+        trap_overflow: true,
+        ..BackendOptions::default()
+    });
+    assert_eq!(trapped.matches("jo\tinteger_overflow").count(), 4);
+
+    let wrapped = assembly(BackendOptions::default());
+    assert!(!wrapped.contains("integer_overflow"));
+}
+
+#[test]
+fn test_no_self_moves() {
+    let source = "type intArray = array of int
+        function main () -> int {
+            let var a : intArray := intArray [10] of 2 in (
+                for i:= 1 to 9 do (a[i] := a[i-1] + a[i]);
+                a[9]
+            ) end
+        }";
+    let compiled_program = compile_source(
+        source,
+        LoweringOptions::default(),
+        BackendOptions::default(),
+    );
+
+    for assembly in compiled_program.main_function.instruction_listing.iter() {
+        if let X64Assembly::Instruction(X64Instruction {
+            op_code: X64opCode::Movq,
+            args: Operands::Two(Operand::Register(source), Operand::Register(destination)),
+        }) = assembly
+        {
+            assert_ne!(source, destination, "{} moves to itself", source);
+        }
+    }
+}
+
+#[test]
+fn test_division_sign_extends() {
+    let source = "function main () -> int {
+            let var a : int := -7 in a / 2 + a % 2 end
+        }";
+    let assembly = format!(
+        "{}",
+        compile_source(
+            source,
+            LoweringOptions::default(),
+            BackendOptions::default()
+        )
+    );
+
+    // Each idivq divides a sign extended %rdx:%rax, behind a zero check
+    assert_eq!(assembly.matches("cqto").count(), 2);
+    assert_eq!(assembly.matches("idivq").count(), 2);
+    assert_eq!(assembly.matches("call\tdivision_by_zero").count(), 2);
+}
+
+#[test]
+fn test_targets() {
+    let source = "function main () -> void { print_line_string(\"hi\") }";
+    let assembly = |target| {
+        let options = BackendOptions {
+            target,
+            ..BackendOptions::default()
+        };
+        format!(
+            "{}",
+            compile_source(source, LoweringOptions::default(), options)
+        )
+    };
+
+    // ELF symbols are spelled as in C, and carry a type and size
+    let linux = assembly(Target::Linux);
+    assert!(linux.contains("\t.globl\tmain\n"));
+    assert!(linux.contains("\t.type\tmain, @function\n"));
+    assert!(linux.contains("\t.size\tmain, .-main\n"));
+    assert!(linux.contains("call\tprint_line_string\n"));
+    assert!(linux.contains("\t.section\t.rodata\n"));
+    assert!(linux.contains(".note.GNU-stack"));
+
+    // Mach-O prefixes C symbols with an underscore but not local labels
+    let macos = assembly(Target::MacOs);
+    assert!(macos.contains("\t.globl\t_main\n_main:\n"));
+    assert!(macos.contains("call\t_print_line_string\n"));
+    assert!(macos.contains("\t.section\t__TEXT,__const\n"));
+    assert!(macos.contains("\n\t.quad 0, 2, 0, 0\nL"));
+    assert!(!macos.contains(".type"));
+    assert!(!macos.contains(".note.GNU-stack"));
+}
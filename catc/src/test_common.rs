@@ -0,0 +1,51 @@
+use crate::backend::{compile, BackendOptions};
+use crate::check_type::type_check;
+use crate::checked_grammar::CheckedProgram;
+use crate::common::{LabelGenerator, SymbolGenerator};
+use crate::lexer::Lexer;
+use crate::lir::{LIRAssembly, LIRFunction, LIRInstruction, LIRProgram};
+use crate::lowering::{lower, LoweringOptions};
+use crate::parser::ProgramParser;
+use crate::ssa::{optimize, Pass};
+use crate::x64::X64Program;
+
+// Parse and type check a program that is expected to be well typed
+pub fn check_source(source: &str) -> CheckedProgram {
+    type_check(ProgramParser::new().parse(Lexer::new(source)).unwrap()).unwrap()
+}
+
+pub fn lower_source(
+    source: &str,
+    options: LoweringOptions,
+) -> (LIRProgram, LabelGenerator, SymbolGenerator) {
+    lower(check_source(source), options)
+}
+
+pub fn compile_source(
+    source: &str,
+    lowering_options: LoweringOptions,
+    backend_options: BackendOptions,
+) -> X64Program {
+    let (lir_program, label_gen, symbol_gen) = lower_source(source, lowering_options);
+    compile(lir_program, label_gen, symbol_gen, backend_options)
+}
+
+/*
+ * Lower the program and run the passes over every function in SSA form
+ */
+pub fn optimized(source: &str, options: LoweringOptions, passes: &[Pass]) -> LIRProgram {
+    let (lir_program, mut label_gen, mut symbol_gen) = lower_source(source, options);
+    optimize(lir_program, &mut label_gen, &mut symbol_gen, passes)
+}
+
+// The instructions of a function, leaving out its labels
+pub fn instructions(function: &LIRFunction) -> Vec<&LIRInstruction> {
+    function
+        .instruction_listing
+        .iter()
+        .filter_map(|assembly| match assembly {
+            LIRAssembly::Instruction(instruction) => Some(instruction),
+            LIRAssembly::Label(_) => None,
+        })
+        .collect()
+}
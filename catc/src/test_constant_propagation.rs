@@ -0,0 +1,107 @@
+use crate::common::Symbol;
+use crate::constant_propagation::propagate_constants;
+use crate::eval_lir::{eval, eval_trapping_overflow, Value};
+use crate::lir::{LIRInstruction, LIRProgram};
+use crate::lowering::LoweringOptions;
+use crate::test_common::{instructions, optimized};
+
+fn propagated(source: &str) -> LIRProgram {
+    optimized(source, LoweringOptions::default(), &[propagate_constants])
+}
+
+fn all_instructions(program: &LIRProgram) -> Vec<&LIRInstruction> {
+    std::iter::once(&program.main_function)
+        .chain(program.other_functions.values())
+        .flat_map(instructions)
+        .collect()
+}
+
+fn assigns(instructions: &[&LIRInstruction], symbol: Symbol, constant: i64) -> bool {
+    instructions.iter().any(|instruction| {
+        matches!(instruction, LIRInstruction::IntLit { assign_to, value }
+            if *assign_to == symbol && *value == constant)
+    })
+}
+
+#[test]
+fn test_folds_arithmetic() {
+    let program = propagated("function main () -> int { -(9 + 10 * 10 - (9 / 10)) }");
+    assert_eq!(eval(&program), Value::Int(-109));
+
+    let instructions = all_instructions(&program);
+    assert!(instructions.iter().all(|instruction| !matches!(
+        instruction,
+        LIRInstruction::BinaryOp { .. } | LIRInstruction::Negate { .. }
+    )));
+    assert!(assigns(
+        &instructions,
+        program.main_function.return_symbol,
+        -109
+    ));
+}
+
+#[test]
+fn test_resolves_branches() {
+    let program = propagated(
+        "function main () -> int {
+            if 2 < 10 then 2 else 10
+        }",
+    );
+    assert_eq!(eval(&program), Value::Int(2));
+    assert!(all_instructions(&program)
+        .iter()
+        .all(|instruction| !matches!(instruction, LIRInstruction::JumpC { .. })));
+    assert!(!assigns(
+        &all_instructions(&program),
+        program.main_function.return_symbol,
+        10
+    ));
+}
+
+#[test]
+fn test_constants_around_loops() {
+    // x stays 1 since the else branch is never taken, which only following
+    // the branches that can be taken shows
+    let program = propagated(
+        "function f(n : int) -> int {
+            let var x : int := 1
+                var i : int := 0
+            in (while i < n do
+                    (if x = 1 then (x := 1) else (x := 2); i := i + 1);
+                x * 3)
+            end
+        }
+        function main () -> int { f(5) }",
+    );
+    assert_eq!(eval(&program), Value::Int(3));
+
+    let f = program.other_functions.values().next().unwrap();
+    let instructions = instructions(f);
+    let else_branch = instructions.iter().any(
+        |instruction| matches!(instruction, LIRInstruction::IntLit { value, .. } if *value == 2),
+    );
+    assert!(!else_branch, "x := 2 is left in\n{}", f);
+    assert!(assigns(&instructions, f.return_symbol, 3));
+}
+
+#[test]
+#[should_panic(expected = "integer overflow")]
+fn test_overflow_is_not_folded() {
+    let program = propagated(
+        "function main () -> int {
+            let var max : int := 9223372036854775807 in max * 2 end
+        }",
+    );
+    eval_trapping_overflow(&program);
+}
+
+#[test]
+#[should_panic(expected = "division by zero")]
+fn test_division_by_zero_is_not_folded() {
+    let program = propagated(
+        "function main () -> int {
+            let var zero : int := 0 in 7 / zero end
+        }",
+    );
+    eval(&program);
+}
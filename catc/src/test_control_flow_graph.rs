@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use crate::common::{Comparison, ComparisonType};
 use crate::common::{LabelGenerator, SymbolGenerator};
@@ -7,7 +7,7 @@ use crate::lir::LIRInstruction::*;
 use crate::lir::*;
 
 use crate::control_flow_graph::{
-    construct_control_flow_graph_lir, construct_control_flow_graph_x64s, liveness,
+    construct_control_flow_graph_lir, construct_control_flow_graph_x64s, liveness, GenKill, Node,
 };
 
 // Less simple examples use the other parts of your compiler to make the test
@@ -19,9 +19,10 @@ use crate::control_flow_graph::{
 // For more diagnostic information (like back traces and the information which)
 // the test prints out run:
 // RUST_BACKTRACE=1 cargo test test_control_flow_graph::lir_cfg_test_jumpc_label -- --nocapture
-use crate::backend::{fix_up, select, GlobalInfo};
+use crate::backend::{fix_up, select, BackendOptions, GlobalInfo};
 use crate::check_type::type_check;
-use crate::lowering::lower;
+use crate::lexer::Lexer;
+use crate::lowering::{lower, LoweringOptions};
 use crate::parser::ProgramParser;
 
 #[test]
@@ -44,6 +45,7 @@ fn lir_cfg_test_simple() {
                 id: s
             }),
         ],
+        pointers: HashSet::new(),
     };
     let label_gen = LabelGenerator::new();
     let symbol_gen = SymbolGenerator::new();
@@ -51,6 +53,7 @@ fn lir_cfg_test_simple() {
         label_gen,
         symbol_gen,
         string_literals: HashMap::new(),
+        options: BackendOptions::default(),
     };
     let selected_program = select(
         LIRProgram {
@@ -89,6 +92,7 @@ fn lir_cfg_test_jump() {
             }),
             LIRAssembly::Label(l),
         ],
+        pointers: HashSet::new(),
     };
 
     let cfg = liveness(construct_control_flow_graph_lir(&f));
@@ -96,6 +100,48 @@ fn lir_cfg_test_jump() {
     println!("--------------------------\n{}", cfg.to_dot());
 }
 
+#[test]
+fn lir_cfg_test_store() {
+    let mut sg = SymbolGenerator::new();
+    let location = sg.new_symbol();
+    let offset = sg.new_symbol();
+    let value = sg.new_symbol();
+
+    let f = LIRFunction {
+        locals: vec![location, offset, value],
+        arguments: vec![],
+        return_symbol: value,
+        instruction_listing: vec![
+            linst!(Call {
+                assign_to: location,
+                function_name: crate::common::Label::Allocate,
+                args: vec![],
+            }),
+            linst!(IntLit {
+                assign_to: offset,
+                value: 0
+            }),
+            linst!(IntLit {
+                assign_to: value,
+                value: 7
+            }),
+            linst!(StoreToMemoryAtOffset {
+                location,
+                offset,
+                value
+            }),
+        ],
+        pointers: HashSet::new(),
+    };
+
+    // The store reads all of its symbols and assigns none of them
+    let cfg = liveness(construct_control_flow_graph_lir(&f));
+    let store = vec![location, offset, value].into_iter().collect();
+    assert_eq!(f.instruction_listing[3].gen(), store);
+    assert!(f.instruction_listing[3].kill().is_empty());
+    assert_eq!(cfg.data[&Node::Index(3)], store);
+}
+
 #[test]
 fn lir_cfg_test_jumpc() {
     let mut sg = SymbolGenerator::new();
@@ -127,6 +173,7 @@ fn lir_cfg_test_jumpc() {
                 value: 3
             }),
         ],
+        pointers: HashSet::new(),
     };
 
     let cfg = liveness(construct_control_flow_graph_lir(&f));
@@ -165,6 +212,7 @@ fn lir_cfg_test_jumpc_label() {
             }),
             LIRAssembly::Label(l),
         ],
+        pointers: HashSet::new(),
     };
 
     let cfg = liveness(construct_control_flow_graph_lir(&f));
@@ -178,6 +226,7 @@ fn lir_cfg_test_jumpc_label() {
         label_gen,
         symbol_gen,
         string_literals: HashMap::new(),
+        options: BackendOptions::default(),
     };
     let selected_program = select(
         LIRProgram {
@@ -212,8 +261,9 @@ fn test_fib() {
         end
     }
     "#;
-    let checked_program = type_check(ProgramParser::new().parse(program).unwrap()).unwrap();
-    let (lir_program, _, _) = lower(checked_program);
+    let checked_program =
+        type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap();
+    let (lir_program, _, _) = lower(checked_program, LoweringOptions::default());
 
     let cfg = liveness(construct_control_flow_graph_lir(&lir_program.main_function));
     let cfgs: HashMap<_, _> = lir_program
@@ -237,8 +287,9 @@ fn test_if() {
             0) in i end
     }
     "#;
-    let checked_program = type_check(ProgramParser::new().parse(program).unwrap()).unwrap();
-    let (lir_program, _, _) = lower(checked_program);
+    let checked_program =
+        type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap();
+    let (lir_program, _, _) = lower(checked_program, LoweringOptions::default());
     println!("{:?}", lir_program);
 
     let cfg = liveness(construct_control_flow_graph_lir(&lir_program.main_function));
@@ -250,6 +301,7 @@ fn test_if() {
         label_gen,
         symbol_gen,
         string_literals: HashMap::new(),
+        options: BackendOptions::default(),
     };
     let selected_program = select(lir_program, &mut state);
     let single_memory_op = fix_up(selected_program);
@@ -269,8 +321,9 @@ fn test_for() {
         for i := 0 to 10 do ()
     }
     "#;
-    let checked_program = type_check(ProgramParser::new().parse(program).unwrap()).unwrap();
-    let (lir_program, _, _) = lower(checked_program);
+    let checked_program =
+        type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap();
+    let (lir_program, _, _) = lower(checked_program, LoweringOptions::default());
     println!("{:?}", lir_program);
 
     let cfg = liveness(construct_control_flow_graph_lir(&lir_program.main_function));
@@ -282,6 +335,7 @@ fn test_for() {
         label_gen,
         symbol_gen,
         string_literals: HashMap::new(),
+        options: BackendOptions::default(),
     };
     let selected_program = select(lir_program, &mut state);
     let single_memory_op = fix_up(selected_program);
@@ -302,8 +356,9 @@ fn test_for_sum() {
             for i := 0 to 10 do (sum := sum + 1; ()) end
     }
     "#;
-    let checked_program = type_check(ProgramParser::new().parse(program).unwrap()).unwrap();
-    let (lir_program, _, _) = lower(checked_program);
+    let checked_program =
+        type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap();
+    let (lir_program, _, _) = lower(checked_program, LoweringOptions::default());
 
     let cfg = liveness(construct_control_flow_graph_lir(&lir_program.main_function));
     let cfgs: HashMap<_, _> = lir_program
@@ -0,0 +1,130 @@
+use crate::copy_propagation::propagate_copies;
+use crate::eval_lir::{eval, Value};
+use crate::lir::{LIRInstruction, LIRProgram};
+use crate::lowering::LoweringOptions;
+use crate::ssa::{construct_ssa, optimize, SSAFunction};
+use crate::test_common::{instructions, lower_source};
+
+fn assigns(program: &LIRProgram) -> usize {
+    std::iter::once(&program.main_function)
+        .chain(program.other_functions.values())
+        .flat_map(instructions)
+        .filter(|instruction| matches!(instruction, LIRInstruction::Assign { .. }))
+        .count()
+}
+
+fn block_instructions(function: &SSAFunction) -> Vec<&LIRInstruction> {
+    function
+        .blocks
+        .iter()
+        .flat_map(|block| block.instructions.iter())
+        .collect()
+}
+
+#[test]
+fn test_removes_copies() {
+    let (lir_program, mut label_gen, mut symbol_gen) = lower_source(
+        "function f(n : int) -> int {
+            let var a : int := n
+                var b : int := a
+                var c : int := b + a
+            in c end
+        }
+        function main () -> int { f(4) }",
+        LoweringOptions::default(),
+    );
+    assert!(assigns(&lir_program) > 0);
+
+    let f = lir_program.other_functions.values().next().unwrap();
+    let mut ssa_function = construct_ssa(f, &mut label_gen, &mut symbol_gen);
+    propagate_copies(&mut ssa_function, &mut symbol_gen);
+    let instructions = block_instructions(&ssa_function);
+    assert!(
+        instructions
+            .iter()
+            .all(|instruction| !matches!(instruction, LIRInstruction::Assign { .. })),
+        "copies are left in\n{}",
+        ssa_function
+    );
+
+    // c adds the argument to itself and is returned directly
+    let n = f.arguments[0];
+    assert!(instructions.iter().any(|instruction| matches!(
+        instruction,
+        LIRInstruction::BinaryOp { assign_to, left, right, .. }
+            if *left == n && *right == n && *assign_to == ssa_function.return_symbol
+    )));
+
+    let optimized = optimize(
+        lir_program,
+        &mut label_gen,
+        &mut symbol_gen,
+        &[propagate_copies],
+    );
+    assert_eq!(assigns(&optimized), 0);
+    assert_eq!(eval(&optimized), Value::Int(8));
+}
+
+#[test]
+fn test_copies_around_loops() {
+    // The copies left are the ones destruct_ssa makes for phis, which the
+    // swap needs
+    let program = "function sum(n : int) -> int {
+            let var total : int := 0
+                var i : int := 0
+            in (while i < n do
+                    (let var next : int := total + i in total := next end;
+                     i := i + 1);
+                total)
+            end
+        }
+        function swap(n : int) -> int {
+            let var a : int := 1
+                var b : int := 2
+                var t : int := 0
+            in (for i := 1 to n do (t := a; a := b; b := t); a * 10 + b) end
+        }
+        function main () -> int { sum(10) * 100 + swap(3) }";
+    let (lir_program, mut label_gen, mut symbol_gen) =
+        lower_source(program, LoweringOptions::default());
+    let before = assigns(&lir_program);
+    assert_eq!(eval(&lir_program), Value::Int(4500 + 21));
+
+    let optimized = optimize(
+        lir_program,
+        &mut label_gen,
+        &mut symbol_gen,
+        &[propagate_copies],
+    );
+    assert_eq!(eval(&optimized), Value::Int(4500 + 21));
+    assert!(assigns(&optimized) < before);
+}
+
+#[test]
+fn test_pointers_keep_their_copies() {
+    // nil is an integer until it is copied into l
+    let (lir_program, mut label_gen, mut symbol_gen) = lower_source(
+        "type list = {head: int, tail: list}
+        function main () -> int {
+            let var l : list := nil
+            in (l := list {head = 1, tail = l}; l.head) end
+        }",
+        LoweringOptions::default(),
+    );
+    let mut ssa_function =
+        construct_ssa(&lir_program.main_function, &mut label_gen, &mut symbol_gen);
+    propagate_copies(&mut ssa_function, &mut symbol_gen);
+
+    let pointers = &ssa_function.pointers;
+    assert!(
+        block_instructions(&ssa_function)
+            .iter()
+            .any(|instruction| matches!(
+                instruction,
+                LIRInstruction::Assign { assign_to, id }
+                    if pointers.contains(assign_to) && !pointers.contains(id)
+            )),
+        "nil isn't copied into a pointer in\n{}",
+        ssa_function
+    );
+}
@@ -0,0 +1,125 @@
+use crate::common::InfixOp;
+use crate::dead_code::eliminate_dead_code;
+use crate::eval_lir::{eval, Value};
+use crate::lir::{LIRFunction, LIRInstruction};
+use crate::lowering::LoweringOptions;
+use crate::ssa::{assigned, uses};
+use crate::test_common::{instructions, lower_source};
+
+use std::collections::HashSet;
+
+fn binary_ops(function: &LIRFunction, op: InfixOp) -> usize {
+    instructions(function)
+        .iter()
+        .filter(
+            |instruction| matches!(instruction, LIRInstruction::BinaryOp { op: o, .. } if *o == op),
+        )
+        .count()
+}
+
+#[test]
+fn test_removes_dead_temporaries() {
+    let mut program = lower_source(
+        "function main () -> int {
+            let var total : int := 0 in
+                (for i := 1 to 4 do (7; total := total + i);
+                 while 1 do break;
+                 (1; 2; total))
+            end
+        }",
+        LoweringOptions::default(),
+    )
+    .0;
+    let before = instructions(&program.main_function).len();
+    eliminate_dead_code(&mut program.main_function, false);
+    assert_eq!(eval(&program), Value::Int(10));
+    assert!(instructions(&program.main_function).len() < before);
+
+    // Everything left that only assigns a symbol has its symbol read
+    let function = &program.main_function;
+    let read: HashSet<_> = instructions(function)
+        .into_iter()
+        .flat_map(uses)
+        .chain(std::iter::once(function.return_symbol))
+        .collect();
+    for instruction in instructions(function) {
+        if let Some(symbol) = assigned(instruction) {
+            assert!(
+                read.contains(&symbol) || matches!(instruction, LIRInstruction::Call { .. }),
+                "{} is dead in\n{}",
+                instruction,
+                function
+            );
+        }
+    }
+
+    // Locals no longer assigned are dropped
+    let assigned: HashSet<_> = instructions(function)
+        .into_iter()
+        .filter_map(assigned)
+        .collect();
+    assert!(function.locals.iter().all(|local| assigned.contains(local)));
+}
+
+#[test]
+fn test_removes_chains() {
+    // y only feeds x, which is never read, so both go
+    let mut program = lower_source(
+        "function f(n : int) -> int {
+            let var y : int := n * 3
+                var x : int := y - 1
+            in n end
+        }
+        function main () -> int { f(5) }",
+        LoweringOptions::default(),
+    )
+    .0;
+    let f = program.other_functions.values_mut().next().unwrap();
+    eliminate_dead_code(f, false);
+    assert_eq!(binary_ops(f, InfixOp::Multiply), 0);
+    assert_eq!(binary_ops(f, InfixOp::Subtract), 0);
+    assert_eq!(eval(&program), Value::Int(5));
+}
+
+#[test]
+fn test_keeps_effects() {
+    let mut program = lower_source(
+        "type ints = array of int
+        function main () -> int {
+            let var a : ints := ints [2] of 0
+                var unused : int := 0
+            in (print_line_int(3); a[1] := 4; unused := a[0]; a[1])
+            end
+        }",
+        LoweringOptions::default(),
+    )
+    .0;
+    eliminate_dead_code(&mut program.main_function, false);
+    assert_eq!(eval(&program), Value::Int(4));
+
+    let instructions = instructions(&program.main_function);
+    assert!(instructions
+        .iter()
+        .any(|instruction| matches!(instruction, LIRInstruction::StoreToMemoryAtOffset { .. })));
+    assert!(instructions.iter().any(
+        |instruction| matches!(instruction, LIRInstruction::Call { function_name, .. } if function_name.to_string() == "print_line_int")
+    ));
+}
+
+#[test]
+fn test_keeps_trapping_arithmetic() {
+    let source = "function f(n : int) -> int {
+            let var x : int := n * 3 in n end
+        }
+        function main () -> int { f(5) }";
+
+    let mut program = lower_source(source, LoweringOptions::default()).0;
+    let f = program.other_functions.values_mut().next().unwrap();
+    eliminate_dead_code(f, true);
+    assert_eq!(binary_ops(f, InfixOp::Multiply), 1);
+
+    let mut program = lower_source(source, LoweringOptions::default()).0;
+    let f = program.other_functions.values_mut().next().unwrap();
+    eliminate_dead_code(f, false);
+    assert_eq!(binary_ops(f, InfixOp::Multiply), 0);
+}
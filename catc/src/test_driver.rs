@@ -0,0 +1,48 @@
+use crate::backend::BackendOptions;
+use crate::driver::{build, run, OutputKind};
+use crate::lowering::LoweringOptions;
+use crate::test_common::compile_source;
+
+use std::env;
+use std::fs;
+
+fn assembly(program: &str) -> String {
+    let compiled_program = compile_source(
+        program,
+        LoweringOptions::default(),
+        BackendOptions::default(),
+    );
+    format!("{}\n", compiled_program)
+}
+
+#[test]
+fn test_run_forwards_exit_status() {
+    // Links against the embedded runtime, which allocates the array
+    let program = "type ints = array of int
+    function main () -> int {
+        let var a : ints := ints [6] of 7 in length(a) + a[5] end
+    }";
+    assert_eq!(run(&assembly(program), None).unwrap(), 13);
+
+    // Runtime errors exit with their own status
+    let program = "function main () -> int {
+        let var zero : int := 0 in 1 / zero end
+    }";
+    assert_eq!(run(&assembly(program), None).unwrap(), 124);
+
+    let program = "function main () -> int { size(chr(0)) }";
+    assert_eq!(run(&assembly(program), None).unwrap(), 121);
+}
+
+#[test]
+fn test_build_object() {
+    let output = env::temp_dir().join(format!("catc-test-{}.o", std::process::id()));
+    let program = "function main () -> int { 0 }";
+    build(&assembly(program), OutputKind::Object, &output).unwrap();
+
+    // An ELF relocatable object
+    let object = fs::read(&output).unwrap();
+    fs::remove_file(&output).unwrap();
+    assert_eq!(&object[..4], b"\x7fELF");
+    assert_eq!(object[16], 1);
+}
@@ -0,0 +1,158 @@
+use crate::eval_checked_grammar::{eval, eval_trapping_overflow, Value};
+use crate::eval_lir;
+use crate::lowering::LoweringOptions;
+use crate::test_common::{check_source, lower_source};
+
+fn eval_program(program: &str) -> Value {
+    eval(&check_source(program))
+}
+
+/*
+ * Lower a program and run it with the LIR interpreter, which makes integer
+ * overflow panic if trap_overflow is set
+ */
+fn eval_lowered(program: &str, options: LoweringOptions, trap_overflow: bool) -> eval_lir::Value {
+    let (lir_program, _, _) = lower_source(program, options);
+    if trap_overflow {
+        eval_lir::eval_trapping_overflow(&lir_program)
+    } else {
+        eval_lir::eval(&lir_program)
+    }
+}
+
+#[test]
+fn test_and_or_short_circuit() {
+    // The right-hand side of 1 / 0 is never evaluated
+    let program = "function main () -> int {
+        (0 and 1 / 0) + (2 and 1) * 10 + (0 or 3) * 100 + (4 or 1 / 0) * 1000
+    }";
+    assert_eq!(eval_program(program), Value::Int(1110));
+}
+
+#[test]
+fn test_string_operators() {
+    let program = r#"function main () -> int {
+        let var s : string := "ab" ^ "c" in
+            (s = "abc") + ("abd" > s) * 10 + (s < "ab") * 100 + (s ^ "" <> s) * 1000
+        end
+    }"#;
+    assert_eq!(eval_program(program), Value::Int(11));
+
+    let (lir_program, _, _) = lower_source(program, LoweringOptions::default());
+    assert_eq!(eval_lir::eval(&lir_program), eval_lir::Value::Int(11));
+}
+
+#[test]
+fn test_builtins() {
+    let program = r#"function main () -> int {
+        let var s : string := concat(substring("hello, world", 7, 5), chr(33)) in
+            (print_line_string(s);
+             size(s) + ord(s) * 10 + ord("") * 10000 + not(0) * 100000 + not(7))
+        end
+    }"#;
+    let expected = 6 + 119 * 10 - 10000 + 100000;
+    assert_eq!(eval_program(program), Value::Int(expected));
+
+    let (lir_program, _, _) = lower_source(program, LoweringOptions::default());
+    assert_eq!(eval_lir::eval(&lir_program), eval_lir::Value::Int(expected));
+}
+
+#[test]
+#[should_panic(expected = "chr argument is not an ASCII character code")]
+fn test_chr_of_nul() {
+    // The runtime's strings end at a NUL, so chr can't make one
+    eval_program("function main () -> int { size(chr(0)) }");
+}
+
+#[test]
+fn test_array_length() {
+    let program = r#"type ints = array of int
+    function sum(a : ints) -> int {
+        let var total : int := 0 in
+            (for i := 0 to length(a) - 1 do total := total + a[i]; total)
+        end
+    }
+    function main () -> int {
+        let var a : ints := ints [4] of 3 in
+            (a[1] := 10; sum(a) * 10 + length(ints [0] of 1))
+        end
+    }"#;
+    assert_eq!(eval_program(program), Value::Int(190));
+
+    let (lir_program, _, _) = lower_source(program, LoweringOptions::default());
+    assert_eq!(eval_lir::eval(&lir_program), eval_lir::Value::Int(190));
+}
+
+#[test]
+#[should_panic(expected = "index out of bounds: the length is 2 but the index is 2")]
+fn test_index_out_of_bounds() {
+    let program = r#"type ints = array of int
+    function main () -> int {
+        let var a : ints := ints [2] of 0 in a[2] end
+    }"#;
+    let (lir_program, _, _) = lower_source(program, LoweringOptions::default());
+    eval_lir::eval(&lir_program);
+}
+
+#[test]
+fn test_integer_overflow_wraps() {
+    // Ints are 64 bits and wrap around, and a for loop up to the largest int
+    // stops there rather than wrapping
+    let program = "function main () -> int {
+        let var max : int := 9223372036854775807
+            var count : int := 0
+        in (for i := max - 2 to max do count := count + 1;
+            (max + 1 = -max - 1) + (max * 2 = -2) * 10 + (-(-max - 1) = -max - 1) * 100
+                + count * 1000)
+        end
+    }";
+    assert_eq!(eval_program(program), Value::Int(3111));
+    assert_eq!(
+        eval_lowered(program, LoweringOptions::default(), false),
+        eval_lir::Value::Int(3111)
+    );
+}
+
+#[test]
+#[should_panic(expected = "integer overflow")]
+fn test_integer_overflow_trapped() {
+    let program = "function main () -> int {
+        let var max : int := 9223372036854775807 in max * 2 end
+    }";
+    eval_lowered(program, LoweringOptions::default(), true);
+}
+
+#[test]
+#[should_panic(expected = "integer overflow")]
+fn test_integer_overflow_trapped_checked() {
+    let program = "function main () -> int {
+        let var min : int := -9223372036854775807 - 1 in -min end
+    }";
+    eval_trapping_overflow(&check_source(program));
+}
+
+#[test]
+fn test_division_truncates() {
+    // Division rounds towards zero, the remainder takes the sign of the dividend,
+    // and dividing the smallest int by -1 wraps
+    let program = "function main () -> int {
+        let var min : int := -9223372036854775807 - 1
+        in (-7 / 2 = -3) + (7 / -2 = -3) * 10 + (-7 % 3 = -1) * 100 + (7 % -3 = 1) * 1000
+            + (min / -1 = min) * 10000 + (min % -1 = 0) * 100000
+        end
+    }";
+    assert_eq!(eval_program(program), Value::Int(111111));
+
+    let (lir_program, _, _) = lower_source(program, LoweringOptions::default());
+    assert_eq!(eval_lir::eval(&lir_program), eval_lir::Value::Int(111111));
+}
+
+#[test]
+#[should_panic(expected = "division by zero")]
+fn test_division_by_zero() {
+    let program = "function main () -> int {
+        let var zero : int := 0 in 7 % zero end
+    }";
+    let (lir_program, _, _) = lower_source(program, LoweringOptions::default());
+    eval_lir::eval(&lir_program);
+}
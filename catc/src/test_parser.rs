@@ -0,0 +1,154 @@
+use crate::diagnostic::{Diagnostic, Span};
+use crate::lexer::{Lexer, Token};
+use crate::parser::ProgramParser;
+use crate::source_grammar::{Exp, TopLevelDec};
+
+fn parse_diagnostic(program: &str) -> Diagnostic {
+    let error = ProgramParser::new().parse(Lexer::new(program)).unwrap_err();
+    Diagnostic::from_parse_error(error, program)
+}
+
+#[test]
+fn test_unrecognized_token() {
+    let program = "function main () -> int { 1 + end }";
+    let diagnostic = parse_diagnostic(program);
+    assert_eq!(diagnostic.message, "unexpected token `end`");
+    assert_eq!(diagnostic.identifier, Some("end".to_string()));
+    assert_eq!(diagnostic.span, Span::new(30, 33));
+    assert_eq!(diagnostic.notes.len(), 1);
+    assert!(diagnostic.notes[0].starts_with("expected one of `(`, `-`"));
+    assert!(diagnostic.notes[0].contains("integer literal"));
+}
+
+#[test]
+fn test_unrecognized_eof() {
+    let program = "function main () -> int { 1";
+    let diagnostic = parse_diagnostic(program);
+    assert_eq!(diagnostic.message, "unexpected end of file");
+    assert_eq!(diagnostic.span, Span::new(27, 27));
+    assert!(diagnostic.notes[0].contains("`}`"));
+}
+
+#[test]
+fn test_invalid_token() {
+    let program = "function main () -> int {\n    1 # 2\n}";
+    let diagnostic = parse_diagnostic(program);
+    assert_eq!(diagnostic.message, "invalid token `#`");
+    assert!(diagnostic
+        .render("test.cat", program)
+        .contains("--> test.cat:2:7\n"));
+}
+
+#[test]
+fn test_int_literal_overflow() {
+    let program = "function main () -> int { 9223372036854775808 }";
+    let diagnostic = parse_diagnostic(program);
+    assert_eq!(
+        diagnostic.message,
+        "integer literal `9223372036854775808` is too large for int"
+    );
+    assert_eq!(diagnostic.span, Span::new(26, 45));
+
+    // Ints are 64 bits
+    ProgramParser::new()
+        .parse(Lexer::new(
+            "function main () -> int { 9223372036854775807 }",
+        ))
+        .unwrap();
+}
+
+#[test]
+fn test_string_escapes() {
+    let program = r#"function main () -> string { "a\n\t\"\\\065" }"#;
+    let parsed = ProgramParser::new().parse(Lexer::new(program)).unwrap();
+    match &parsed.dec_list[0] {
+        TopLevelDec::FunDec { body, .. } => match body.as_ref() {
+            Exp::StringLit { value, .. } => assert_eq!(value, "a\n\t\"\\A"),
+            exp => panic!("expected a string literal, found {:?}", exp),
+        },
+        dec => panic!("expected a function, found {:?}", dec),
+    }
+}
+
+#[test]
+fn test_bad_string_escapes() {
+    let diagnostic = parse_diagnostic(r#"function main () -> string { "\q" }"#);
+    assert_eq!(diagnostic.message, "unknown escape `\\q`");
+    assert_eq!(diagnostic.span, Span::new(30, 32));
+
+    let diagnostic = parse_diagnostic(r#"function main () -> string { "\200" }"#);
+    assert_eq!(
+        diagnostic.message,
+        "numeric escape `\\200` is not an ASCII character"
+    );
+
+    let diagnostic = parse_diagnostic(r#"function main () -> string { "a\000b" }"#);
+    assert_eq!(
+        diagnostic.message,
+        "numeric escape `\\000` is a NUL, which strings can't contain"
+    );
+    assert_eq!(diagnostic.span, Span::new(31, 35));
+
+    let diagnostic = parse_diagnostic(r#"function main () -> string { "\12" }"#);
+    assert_eq!(
+        diagnostic.message,
+        "numeric escape must have exactly three digits"
+    );
+}
+
+#[test]
+fn test_comments() {
+    let program = "/* outer /* inner */ still outer */\n\
+                   function main () -> int { // line comment\n\
+                   \x20   \"/* not a comment */\" = \"// nor this\"\n\
+                   }\n";
+    let tokens: Vec<Token> = Lexer::new(program).map(|t| t.unwrap().1).collect();
+    assert_eq!(tokens[0], Token::Function);
+    assert!(tokens.contains(&Token::StringLit("\"/* not a comment */\"")));
+    assert!(tokens.contains(&Token::StringLit("\"// nor this\"")));
+    ProgramParser::new().parse(Lexer::new(program)).unwrap();
+}
+
+#[test]
+fn test_unterminated_comment() {
+    let diagnostic = parse_diagnostic("function /* main /* () */");
+    assert_eq!(diagnostic.message, "unterminated block comment");
+    assert_eq!(diagnostic.span, Span::new(9, 11));
+}
+
+#[test]
+fn test_lexer_tokens_and_spans() {
+    let tokens: Vec<_> = Lexer::new("if x1 <> end_2 then matrix3[0] := \"s\"")
+        .map(|t| t.unwrap())
+        .collect();
+    assert_eq!(
+        tokens,
+        vec![
+            (0, Token::If, 2),
+            (3, Token::Id("x1"), 5),
+            (6, Token::NotEqual, 8),
+            (9, Token::Id("end_2"), 14),
+            (15, Token::Then, 19),
+            (20, Token::Id("matrix3"), 27),
+            (27, Token::LeftBracket, 28),
+            (28, Token::IntLit("0"), 29),
+            (29, Token::RightBracket, 30),
+            (31, Token::Assign, 33),
+            (34, Token::StringLit("\"s\""), 37),
+        ]
+    );
+}
+
+#[test]
+fn test_identifiers_with_digits() {
+    let program = r#"
+    function add2 (x1 : int, x_2 : int) -> int { x1 + x_2 }
+    function main () -> int {
+        let var matrix3 : int := add2(1, 2)
+            var nil0 : int := 0
+        in matrix3 + nil0
+        end
+    }
+    "#;
+    ProgramParser::new().parse(Lexer::new(program)).unwrap();
+}
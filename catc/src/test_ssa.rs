@@ -0,0 +1,152 @@
+use crate::common::{Symbol, SymbolGenerator};
+use crate::eval_lir::{eval, Value};
+use crate::lir::LIRInstruction;
+use crate::lowering::LoweringOptions;
+use crate::ssa::{assigned, construct_ssa, optimize, sequentialize};
+use crate::test_common::lower_source;
+
+use std::collections::{HashMap, HashSet};
+
+/*
+ * Take every function of the program into SSA form and back out, checking
+ * that each symbol is assigned once in between and that the program computes
+ * the same value afterwards.
+ */
+fn assert_round_trip(program: &str, expected: Value) {
+    let (lir_program, mut label_gen, mut symbol_gen) =
+        lower_source(program, LoweringOptions::default());
+    assert_eq!(eval(&lir_program), expected);
+
+    let functions =
+        std::iter::once(&lir_program.main_function).chain(lir_program.other_functions.values());
+    for function in functions {
+        let ssa_function = construct_ssa(function, &mut label_gen, &mut symbol_gen);
+        let mut seen: HashSet<Symbol> = ssa_function.arguments.iter().copied().collect();
+        for block in ssa_function.blocks.iter() {
+            let phis = block.phis.iter().map(|phi| phi.assign_to);
+            let instructions = block.instructions.iter().filter_map(assigned);
+            for symbol in phis.chain(instructions) {
+                assert!(
+                    seen.insert(symbol),
+                    "{} assigned twice in\n{}",
+                    symbol,
+                    ssa_function
+                );
+            }
+        }
+    }
+
+    let optimized_program = optimize(lir_program, &mut label_gen, &mut symbol_gen, &[]);
+    assert_eq!(eval(&optimized_program), expected);
+}
+
+#[test]
+fn test_round_trip_loops() {
+    let program = "function fib_while(n : int) -> int {
+        let var i : int := 0
+            var a : int := 0
+            var b : int := 1
+            var temp : int := 0
+        in (while i < n do
+                (temp := a; a := b; b := temp + b; i := i + 1);
+            b)
+        end
+    }
+    function first_square_over(n : int) -> int {
+        let var found : int := 0 in
+            (while found * found <= n do found := found + 1;
+             for i := 0 to n do break;
+             found)
+        end
+    }
+    function main () -> int {
+        fib_while(20) * 100 + first_square_over(50)
+    }";
+    assert_round_trip(program, Value::Int(10946 * 100 + 8));
+}
+
+#[test]
+fn test_round_trip_branches_and_recursion() {
+    let program = "function collatz(n : int, steps : int) -> int {
+        if n = 1 then
+            steps
+        else if n % 2 = 0 then
+            collatz(n / 2, steps + 1)
+        else
+            collatz(3 * n + 1, steps + 1)
+    }
+    function main () -> int {
+        let var x : int := 5
+            var y : int := 9
+        in (if x < y and (y - x > 3 or 1 / 0) then
+                (x := x + y; y := x - y; x := x - y)
+            else
+                (x := 0);
+            collatz(27, 0) * 100 + x * 10 + y)
+        end
+    }";
+    assert_round_trip(program, Value::Int(111 * 100 + 9 * 10 + 5));
+}
+
+#[test]
+fn test_round_trip_memory() {
+    let program = "type ints = array of int
+    type pair = { first : int, second : int }
+    function main () -> int {
+        let var a : ints := ints [6] of 7
+            var p : pair := pair { first = 1, second = 2 }
+            var total : int := 0
+        in (for i := 1 to 5 do
+                (a[i] := a[i - 1] + i; p.first := p.second; p.second := a[i]);
+            for i := 0 to 5 do total := total + a[i];
+            total * 1000 + p.first * 10 + p.second)
+        end
+    }";
+    assert_round_trip(program, Value::Int(77 * 1000 + 17 * 10 + 22));
+}
+
+#[test]
+fn test_round_trip_void() {
+    let program = "function count(n : int) -> void {
+        let var i : int := 0 in
+            (while i < n do (print_line_int(i); i := i + 1);
+             while 1 do break)
+        end
+    }
+    function main () -> void {
+        (count(3); for i := 13 to 15 do break)
+    }";
+    assert_round_trip(program, Value::Void);
+}
+
+#[test]
+fn test_sequentialize_cycles() {
+    let mut symbol_gen = SymbolGenerator::new();
+    let symbols: Vec<Symbol> = (0..5).map(|_| symbol_gen.new_symbol()).collect();
+    let (a, b, c, d, e) = (symbols[0], symbols[1], symbols[2], symbols[3], symbols[4]);
+    let mut pointers: HashSet<Symbol> = vec![c].into_iter().collect();
+
+    // Rotate a, b and c, copy the old a to d and leave e alone
+    let copies = vec![(a, b), (b, c), (c, a), (d, a), (e, e)];
+    let sequence = sequentialize(copies, &mut symbol_gen, &mut pointers);
+
+    let mut values: HashMap<Symbol, i64> = symbols
+        .iter()
+        .enumerate()
+        .map(|(index, symbol)| (*symbol, index as i64))
+        .collect();
+    for instruction in sequence.iter() {
+        match instruction {
+            LIRInstruction::Assign { assign_to, id } => {
+                let value = values[id];
+                values.insert(*assign_to, value);
+            }
+            _ => panic!("{} is not a copy", instruction),
+        }
+    }
+    assert_eq!(
+        (values[&a], values[&b], values[&c], values[&d], values[&e]),
+        (1, 2, 0, 0, 4)
+    );
+    assert!(sequence.len() <= 5);
+}
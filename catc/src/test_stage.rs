@@ -0,0 +1,120 @@
+use crate::common::{Label, Symbol};
+use crate::eval_lir;
+use crate::lexer::Lexer;
+use crate::parser::ProgramParser;
+use crate::stage::{Format, PipelineOptions, Stage, StagedProgram};
+
+fn advance_to(program: &str, stage: Stage) -> StagedProgram {
+    let mut program = StagedProgram::Ast(ProgramParser::new().parse(Lexer::new(program)).unwrap());
+    while program.stage() != stage {
+        program = program.advance(PipelineOptions::default()).unwrap();
+    }
+    program
+}
+
+fn sorted_lines(text: &str) -> Vec<&str> {
+    let mut lines: Vec<_> = text
+        .lines()
+        .map(|line| line.trim_end_matches(','))
+        .collect();
+    lines.sort_unstable();
+    lines
+}
+
+#[test]
+fn test_names_round_trip() {
+    for label in [
+        Label::Uid(12),
+        Label::Main,
+        Label::PrintlnInt,
+        Label::GcFrames,
+    ]
+    .iter()
+    {
+        assert_eq!(label.to_string().parse::<Label>(), Ok(*label));
+    }
+    assert_eq!("S7".parse::<Symbol>().unwrap().to_string(), "S7");
+    assert!("_main".parse::<Label>().is_err());
+    assert!("L".parse::<Label>().is_err());
+}
+
+#[test]
+fn test_stages_round_trip() {
+    let program = "type ints = array of int
+    function sum(a : ints, n : int) -> int {
+        if n = 0 then 0 else a[n - 1] + sum(a, n - 1)
+    }
+    function main () -> int {
+        let var a : ints := ints [5] of 3 in (a[2] := 10; sum(a, 5) % 7) end
+    }";
+
+    // Reading a written program back gives one that runs the same
+    for format in [Format::Ron, Format::Json].iter() {
+        let lir = advance_to(program, Stage::Lir).write(*format).unwrap();
+        match StagedProgram::read(Stage::Lir, *format, &lir).unwrap() {
+            StagedProgram::Lir(lir_program, label_gen, symbol_gen) => {
+                assert_eq!(eval_lir::eval(&lir_program), eval_lir::Value::Int(1));
+
+                // New labels and symbols don't collide with the program's
+                let (mut label_gen, mut symbol_gen) = (label_gen, symbol_gen);
+                let new_label = label_gen.new_label();
+                let new_symbol = symbol_gen.new_symbol();
+                let lir = StagedProgram::Lir(lir_program, label_gen, symbol_gen)
+                    .write(*format)
+                    .unwrap();
+                assert!(!lir.contains(&format!("\"{}\"", new_label)));
+                assert!(!lir.contains(&format!("\"{}\"", new_symbol)));
+            }
+            _ => panic!("read LIR as another stage"),
+        }
+
+        // Maps are written in no particular order, so compare the lines but
+        // not where JSON puts commas
+        for stage in [
+            Stage::Ast,
+            Stage::Checked,
+            Stage::X64s,
+            Stage::FixedX64s,
+            Stage::Asm,
+        ]
+        .iter()
+        {
+            let written = advance_to(program, *stage).write(*format).unwrap();
+            let read = StagedProgram::read(*stage, *format, &written).unwrap();
+            assert!(sorted_lines(&read.write(*format).unwrap()) == sorted_lines(&written));
+        }
+    }
+}
+
+#[test]
+fn test_optimize_read_program() {
+    let program = "function main () -> int {
+        let var a : int := 3 in if a < 10 then a * 8 else a + 100 end
+    }";
+    let instructions = |program: &StagedProgram| match program {
+        StagedProgram::Lir(lir_program, ..) => lir_program.main_function.instruction_listing.len(),
+        _ => panic!("expected LIR"),
+    };
+    let options = PipelineOptions {
+        optimize: true,
+        ..PipelineOptions::default()
+    };
+
+    // A program read as LIR is optimized as advancing would have
+    let lir = advance_to(program, Stage::Lir).write(Format::Ron).unwrap();
+    let read = StagedProgram::read(Stage::Lir, Format::Ron, &lir).unwrap();
+    let before = instructions(&read);
+    let optimized = read.optimize(options).unwrap();
+    assert!(instructions(&optimized) < before);
+    match &optimized {
+        StagedProgram::Lir(lir_program, ..) => {
+            assert_eq!(eval_lir::eval(lir_program), eval_lir::Value::Int(24))
+        }
+        _ => panic!("optimizing changed the stage"),
+    }
+
+    // -O can't do anything once the program is past LIR
+    let x64s = advance_to(program, Stage::X64s).write(Format::Ron).unwrap();
+    let read = StagedProgram::read(Stage::X64s, Format::Ron, &x64s).unwrap();
+    assert!(read.optimize(options).is_err());
+}
@@ -1,11 +1,16 @@
 use crate::check_type::type_check;
+use crate::lexer::Lexer;
+use crate::parser::ProgramParser;
 use crate::source_grammar::*;
 use std::collections::VecDeque;
 // In the future we will have a parser (woot, woot)
 //use crate::tiger::{ProgramParser};
 #[test]
 fn test_int_lit() {
-    let body = Box::new(Exp::IntLit { value: 2 });
+    let body = Box::new(Exp::IntLit {
+        value: 2,
+        span: Span::default(),
+    });
     let args = VecDeque::new();
     let name = "main".to_string();
     let return_type = "int".to_string();
@@ -15,6 +20,7 @@ fn test_int_lit() {
         args,
         name,
         return_type,
+        span: Span::default(),
     }]
     .into_iter()
     .collect();
@@ -28,6 +34,7 @@ fn test_int_lit() {
 fn test_str_lit() {
     let body = Box::new(Exp::Sequence {
         sequence: VecDeque::new(),
+        span: Span::default(),
     });
     let args = VecDeque::new();
     let name = "main".to_string();
@@ -35,6 +42,7 @@ fn test_str_lit() {
 
     let body_ = Box::new(Exp::StringLit {
         value: "Hello".to_string(),
+        span: Span::default(),
     });
     let args_ = VecDeque::new();
     let name_ = "s".to_string();
@@ -46,12 +54,14 @@ fn test_str_lit() {
             args: args_,
             name: name_,
             return_type: return_type_,
+            span: Span::default(),
         },
         TopLevelDec::FunDec {
             body,
             args,
             name,
             return_type,
+            span: Span::default(),
         },
     ]
     .into_iter()
@@ -61,28 +71,208 @@ fn test_str_lit() {
 
     type_check(program).unwrap();
 }
+
+#[test]
+fn test_recursive_record() {
+    let program = r#"
+    type list = {head: int, tail: list}
+    function main () -> int {
+        let var l : list := list {head = 1, tail = list {head = 2, tail = nil}} in
+            if l.tail.tail = nil then l.tail.head else 0
+        end
+    }
+    "#;
+    type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap();
+}
+
+#[test]
+fn test_mutually_recursive_types() {
+    let program = r#"
+    type tree = {value: int, children: forest}
+    type forest = {first: tree, rest: forest}
+    type trees = array of tree
+    function main () -> int {
+        let var leaf : tree := tree {value = 1, children = nil}
+            var f : forest := forest {first = leaf, rest = nil}
+            var ts : trees := trees [2] of leaf
+        in (ts[1] := tree {value = 2, children = f}; ts[1].children.first.value)
+        end
+    }
+    "#;
+    type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap();
+}
+
+#[test]
+fn test_type_group_split_by_function() {
+    let program = r#"
+    type a = {b: b}
+    function main () -> int { 0 }
+    type b = {a: a}
+    "#;
+    type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap_err();
+}
+
+#[test]
+fn test_nil_not_int() {
+    let program = r#"
+    function main () -> int {
+        let var i : int := nil in i end
+    }
+    "#;
+    type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap_err();
+}
+
+#[test]
+fn test_string_operators() {
+    let program = r#"
+    function main () -> int {
+        let var s : string := "a" ^ "b" in
+            (s < "b") + (s = "ab") + (s <> "c") + (s >= "a")
+        end
+    }
+    "#;
+    type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap();
+}
+
+#[test]
+fn test_string_operator_mismatches() {
+    let program = r#"
+    function main () -> int {
+        ("a" < 1) + ("a" * "b") + (1 ^ "b"; 0)
+    }
+    "#;
+    let diagnostics =
+        type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap_err();
+    let found: Vec<_> = diagnostics
+        .iter()
+        .map(|d| (d.expected.clone().unwrap(), d.found.clone().unwrap()))
+        .collect();
+    let expected = vec![
+        ("string".to_string(), "int".to_string()),
+        ("int".to_string(), "string".to_string()),
+        ("string".to_string(), "int".to_string()),
+    ];
+    assert_eq!(found, expected);
+}
+
+#[test]
+fn test_array_length_arguments() {
+    let program = r#"
+    type ints = array of int
+    function main () -> int {
+        length(ints [1] of 0) + length(1) + length()
+    }
+    "#;
+    let diagnostics =
+        type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap_err();
+    let messages: Vec<_> = diagnostics.iter().map(|d| d.to_string()).collect();
+    assert_eq!(messages.len(), 2);
+    assert!(messages[0].starts_with("mismatched types: expected `array`, found `int`"));
+    assert!(messages[1].starts_with("`length` takes 1 argument but 0 arguments were supplied"));
+}
+
+#[test]
+fn test_diagnostic_mismatched_types() {
+    let program = r#"
+    type list = {head: int, tail: list}
+    function main () -> int {
+        let var l : list := list {head = 1, tail = 2} in 0 end
+    }
+    "#;
+    let diagnostics =
+        type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap_err();
+    assert_eq!(diagnostics.len(), 1);
+    let diagnostic = &diagnostics[0];
+    assert_eq!(diagnostic.message, "mismatched types");
+    assert_eq!(diagnostic.expected, Some("list".to_string()));
+    assert_eq!(diagnostic.found, Some("int".to_string()));
+    assert_eq!(&program[diagnostic.span.start..diagnostic.span.end], "2");
+}
+
+#[test]
+fn test_diagnostic_unknown_identifier() {
+    let program = "function main () -> int {\n    1 + missing\n}\n";
+    let diagnostics =
+        type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap_err();
+    let diagnostic = &diagnostics[0];
+    assert_eq!(diagnostic.identifier, Some("missing".to_string()));
+    assert_eq!(
+        diagnostic.render("test.cat", program),
+        "error: cannot find variable `missing` in this scope\n \
+         --> test.cat:2:9\n  \
+         |\n\
+         2 |     1 + missing\n  \
+         |         ^^^^^^^\n"
+    );
+}
+
+#[test]
+fn test_reports_every_error() {
+    let program = r#"
+    type point = {x: int, y: missing}
+    function f (p : point) -> int {
+        p.x + undefined + "s"
+    }
+    function main () -> int {
+        let var s : string := f(nil) + 1
+            var i : int := s.field
+        in (g(); i + s)
+        end
+    }
+    "#;
+    let diagnostics =
+        type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap_err();
+    let messages: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "cannot find type `missing` in this scope",
+            "cannot find variable `undefined` in this scope",
+            "mismatched types",
+            "mismatched types",
+            "no field `field` on type `string`",
+            "cannot find function `g` in this scope",
+            "mismatched types",
+        ]
+    );
+}
+
+#[test]
+fn test_error_type_does_not_cascade() {
+    let program = r#"
+    function main () -> int {
+        let var a : int := unknown in
+            if a = 1 then a + 2 else -a
+        end
+    }
+    "#;
+    let diagnostics =
+        type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap_err();
+    assert_eq!(diagnostics.len(), 1);
+}
+
 /***** Tests from SRC code -- Requires Program Parser ************
 #[test]
 fn test_int_lit_src() {
     let program = "function main () : int { 9 }";
 
-    //print!("{:#?}", ProgramParser::new().parse(program));
-    assert!(ProgramParser::new().parse(program).is_ok());
+    //print!("{:#?}", ProgramParser::new().parse(Lexer::new(program)));
+    assert!(ProgramParser::new().parse(Lexer::new(program)).is_ok());
 }
 
 #[test]
 fn test_string_lit_src() {
     let program = "function main () : int { (\"String\"; 9) }";
 
-    //print!("{:#?}", ProgramParser::new().parse(program));
-    assert!(ProgramParser::new().parse(program).is_ok());
+    //print!("{:#?}", ProgramParser::new().parse(Lexer::new(program)));
+    assert!(ProgramParser::new().parse(Lexer::new(program)).is_ok());
 }
 
 
 #[test]
 fn test_int_lit_exp_src() {
     let program = "function main () : int { -(9 + 10 * 10 - (9/10)) }";
-    type_check(ProgramParser::new().parse(program).unwrap()).unwrap();
+    type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap();
 }
 
 #[test]
@@ -95,8 +285,8 @@ fn test_array_exp_src() {
         end
     }
     ";
-//    print!("{:#?}", ProgramParser::new().parse(program).unwrap());
-    type_check(ProgramParser::new().parse(program).unwrap()).unwrap();
+//    print!("{:#?}", ProgramParser::new().parse(Lexer::new(program)).unwrap());
+    type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap();
 }
 
 #[test]
@@ -109,8 +299,8 @@ fn test_rec_exp_src() {
         end
     }
     ";
-//    print!("{:#?}", ProgramParser::new().parse(program).unwrap());
-    type_check(ProgramParser::new().parse(program).unwrap()).unwrap();
+//    print!("{:#?}", ProgramParser::new().parse(Lexer::new(program)).unwrap());
+    type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap();
 }
 
 #[test]
@@ -123,8 +313,8 @@ fn test_if_then_else_exp_src() {
         end
     }
     ";
-//    print!("{:#?}", ProgramParser::new().parse(program).unwrap());
-    type_check(ProgramParser::new().parse(program).unwrap()).unwrap();
+//    print!("{:#?}", ProgramParser::new().parse(Lexer::new(program)).unwrap());
+    type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap();
 }
 
 
@@ -138,8 +328,8 @@ fn test_if_then_exp_src() {
         end
     }
     ";
-//    print!("{:#?}", ProgramParser::new().parse(program).unwrap());
-    type_check(ProgramParser::new().parse(program).unwrap()).unwrap();
+//    print!("{:#?}", ProgramParser::new().parse(Lexer::new(program)).unwrap());
+    type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap();
 }
 
 #[test]
@@ -152,8 +342,8 @@ fn test_while() {
         end
     }
     ";
-//    print!("{:#?}", ProgramParser::new().parse(program).unwrap());
-    type_check(ProgramParser::new().parse(program).unwrap()).unwrap();
+//    print!("{:#?}", ProgramParser::new().parse(Lexer::new(program)).unwrap());
+    type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap();
 }
 
 #[test]
@@ -170,8 +360,8 @@ fn test_while_break() {
         end
     }
     ";
-//    print!("{:#?}", ProgramParser::new().parse(program).unwrap());
-    type_check(ProgramParser::new().parse(program).unwrap()).unwrap();
+//    print!("{:#?}", ProgramParser::new().parse(Lexer::new(program)).unwrap());
+    type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap();
 }
 
 #[test]
@@ -184,8 +374,8 @@ fn test_for() {
         end
     }
     ";
-//    print!("{:#?}", ProgramParser::new().parse(program).unwrap());
-    type_check(ProgramParser::new().parse(program).unwrap()).unwrap();
+//    print!("{:#?}", ProgramParser::new().parse(Lexer::new(program)).unwrap());
+    type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap();
 }
 
 #[test]
@@ -198,8 +388,8 @@ fn test_for_break() {
         end
     }
     ";
-//    print!("{:#?}", ProgramParser::new().parse(program).unwrap());
-    type_check(ProgramParser::new().parse(program).unwrap()).unwrap();
+//    print!("{:#?}", ProgramParser::new().parse(Lexer::new(program)).unwrap());
+    type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap();
 }
 
 #[test]
@@ -210,8 +400,8 @@ fn test_call_rec() {
         main()
     }
     ";
-//    print!("{:#?}", ProgramParser::new().parse(program).unwrap());
-    type_check(ProgramParser::new().parse(program).unwrap()).unwrap();
+//    print!("{:#?}", ProgramParser::new().parse(Lexer::new(program)).unwrap());
+    type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap();
 }
 
 #[test]
@@ -221,8 +411,8 @@ fn test_call_missing_name() {
         missingName ()
     }
     ";
-//    print!("{:#?}", ProgramParser::new().parse(program).unwrap());
-    type_check(ProgramParser::new().parse(program).unwrap()).unwrap_err();
+//    print!("{:#?}", ProgramParser::new().parse(Lexer::new(program)).unwrap());
+    type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap_err();
 }
 
 #[test]
@@ -232,8 +422,8 @@ fn test_missing_symbol() {
         missingName
     }
     ";
-//    print!("{:#?}", ProgramParser::new().parse(program).unwrap());
-    type_check(ProgramParser::new().parse(program).unwrap()).unwrap_err();
+//    print!("{:#?}", ProgramParser::new().parse(Lexer::new(program)).unwrap());
+    type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap_err();
 }
 
 #[test]
@@ -246,8 +436,8 @@ fn test_break_for_cond() {
         end
     }
     ";
-//    print!("{:#?}", ProgramParser::new().parse(program).unwrap());
-    type_check(ProgramParser::new().parse(program).unwrap()).unwrap_err();
+//    print!("{:#?}", ProgramParser::new().parse(Lexer::new(program)).unwrap());
+    type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap_err();
 }
 
 #[test]
@@ -260,8 +450,8 @@ fn test_break_for_cond_1() {
         end
     }
     ";
-//    print!("{:#?}", ProgramParser::new().parse(program).unwrap());
-    type_check(ProgramParser::new().parse(program).unwrap()).unwrap_err();
+//    print!("{:#?}", ProgramParser::new().parse(Lexer::new(program)).unwrap());
+    type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap_err();
 }
 
 #[test]
@@ -278,8 +468,8 @@ fn test_break_while_cond_1() {
         end
     }
     ";
-//    print!("{:#?}", ProgramParser::new().parse(program).unwrap());
-    type_check(ProgramParser::new().parse(program).unwrap()).unwrap_err();
+//    print!("{:#?}", ProgramParser::new().parse(Lexer::new(program)).unwrap());
+    type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap_err();
 }
 
 #[test]
@@ -292,8 +482,8 @@ fn test_bad_assign() {
         end
     }
     "#;
-//    print!("{:#?}", ProgramParser::new().parse(program).unwrap());
-    type_check(ProgramParser::new().parse(program).unwrap()).unwrap_err();
+//    print!("{:#?}", ProgramParser::new().parse(Lexer::new(program)).unwrap());
+    type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap_err();
 }
 
 #[test]
@@ -306,8 +496,8 @@ fn test_bad_if_then_else_exp_src() {
         end
     }
     "#;
-//    print!("{:#?}", ProgramParser::new().parse(program).unwrap());
-    type_check(ProgramParser::new().parse(program).unwrap()).unwrap_err();
+//    print!("{:#?}", ProgramParser::new().parse(Lexer::new(program)).unwrap());
+    type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap_err();
 }
 
 #[test]
@@ -320,8 +510,8 @@ fn test_bad_call_src() {
         i + 1
     }
     "#;
-//    print!("{:#?}", ProgramParser::new().parse(program).unwrap());
-    type_check(ProgramParser::new().parse(program).unwrap()).unwrap_err();
+//    print!("{:#?}", ProgramParser::new().parse(Lexer::new(program)).unwrap());
+    type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap_err();
 }
 
 #[test]
@@ -334,8 +524,8 @@ fn test_bad_return_src() {
         "i + 1"
     }
     "#;
-//    print!("{:#?}", ProgramParser::new().parse(program).unwrap());
-    type_check(ProgramParser::new().parse(program).unwrap()).unwrap_err();
+//    print!("{:#?}", ProgramParser::new().parse(Lexer::new(program)).unwrap());
+    type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap_err();
 }
 
 #[test]
@@ -348,8 +538,8 @@ fn test_bad_return_context_src() {
         "i + 1"
     }
     "#;
-//    print!("{:#?}", ProgramParser::new().parse(program).unwrap());
-    type_check(ProgramParser::new().parse(program).unwrap()).unwrap_err();
+//    print!("{:#?}", ProgramParser::new().parse(Lexer::new(program)).unwrap());
+    type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap_err();
 }
 
 #[test]
@@ -362,8 +552,8 @@ fn test_bad_return_context_src_1() {
         i + 1
     }
     "#;
-//    print!("{:#?}", ProgramParser::new().parse(program).unwrap());
-    type_check(ProgramParser::new().parse(program).unwrap()).unwrap_err();
+//    print!("{:#?}", ProgramParser::new().parse(Lexer::new(program)).unwrap());
+    type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap_err();
 }
 
 #[test]
@@ -376,8 +566,8 @@ fn test_bad_array_len() {
         end
     }
     "#;
-//    print!("{:#?}", ProgramParser::new().parse(program).unwrap());
-    type_check(ProgramParser::new().parse(program).unwrap()).unwrap_err();
+//    print!("{:#?}", ProgramParser::new().parse(Lexer::new(program)).unwrap());
+    type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap_err();
 }
 
 #[test]
@@ -390,8 +580,8 @@ fn test_bad_array_bad_init() {
         end
     }
     "#;
-//    print!("{:#?}", ProgramParser::new().parse(program).unwrap());
-    type_check(ProgramParser::new().parse(program).unwrap()).unwrap_err();
+//    print!("{:#?}", ProgramParser::new().parse(Lexer::new(program)).unwrap());
+    type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap_err();
 }
 
 #[test]
@@ -405,8 +595,8 @@ fn test_array_array_int() {
         end
     }
     "#;
-//    print!("{:#?}", ProgramParser::new().parse(program).unwrap());
-    type_check(ProgramParser::new().parse(program).unwrap()).unwrap();
+//    print!("{:#?}", ProgramParser::new().parse(Lexer::new(program)).unwrap());
+    type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap();
 }
 
 #[test]
@@ -420,7 +610,7 @@ fn test_bad_array_array_int_init() {
         end
     }
     "#;
-//    print!("{:#?}", ProgramParser::new().parse(program).unwrap());
-    type_check(ProgramParser::new().parse(program).unwrap()).unwrap_err();
+//    print!("{:#?}", ProgramParser::new().parse(Lexer::new(program)).unwrap());
+    type_check(ProgramParser::new().parse(Lexer::new(program)).unwrap()).unwrap_err();
 }
 */
@@ -0,0 +1,142 @@
+use crate::common::InfixOp;
+use crate::eval_lir::{eval, Value};
+use crate::lir::{LIRInstruction, LIRProgram};
+use crate::lowering::LoweringOptions;
+use crate::ssa::Pass;
+use crate::test_common::{instructions, optimized};
+use crate::value_numbering::{number_values, number_values_locally};
+
+// How many instructions of the one function besides main match
+fn count(program: &LIRProgram, matches: impl Fn(&LIRInstruction) -> bool) -> usize {
+    let function = program.other_functions.values().next().unwrap();
+    instructions(function)
+        .into_iter()
+        .filter(|instruction| matches(instruction))
+        .count()
+}
+
+fn multiplies(program: &LIRProgram) -> usize {
+    count(program, |instruction| {
+        matches!(
+            instruction,
+            LIRInstruction::BinaryOp {
+                op: InfixOp::Multiply,
+                ..
+            }
+        )
+    })
+}
+
+fn loads(program: &LIRProgram) -> usize {
+    count(program, |instruction| {
+        matches!(instruction, LIRInstruction::LoadFromMemoryAtOffset { .. })
+    })
+}
+
+#[test]
+fn test_local_numbering() {
+    let program = "function f(a : int, b : int) -> int {
+            (a * b + b * a) - a * b
+        }
+        function main () -> int { f(6, 7) }";
+    let options = LoweringOptions::default();
+    let runs: [(&[Pass], usize); 3] = [
+        (&[], 3),
+        (&[number_values_locally], 1),
+        (&[number_values], 1),
+    ];
+    for &(passes, expected) in runs.iter() {
+        let optimized_program = optimized(program, options, passes);
+        assert_eq!(eval(&optimized_program), Value::Int(42));
+        assert_eq!(multiplies(&optimized_program), expected);
+    }
+}
+
+#[test]
+fn test_global_numbering() {
+    // The multiplication in each branch is dominated by the one before the if
+    let program = "function f(a : int, b : int) -> int {
+            let var x : int := a * b in
+                if a < b then b * a else x + a * b
+            end
+        }
+        function main () -> int { f(6, 7) * 100 + f(7, 6) }";
+    let options = LoweringOptions::default();
+    let program_locally = optimized(program, options, &[number_values_locally]);
+    let program_globally = optimized(program, options, &[number_values]);
+    assert_eq!(multiplies(&program_locally), 3);
+    assert_eq!(multiplies(&program_globally), 1);
+    assert_eq!(eval(&program_locally), Value::Int(4284));
+    assert_eq!(eval(&program_globally), Value::Int(4284));
+}
+
+#[test]
+fn test_repeated_loads() {
+    // Reading memo[n] again reads the array's length and the element again,
+    // and nothing in between changes either
+    let program = "type intArray = array of int
+        function lookup(n : int, memo : intArray) -> int {
+            if memo[n] = -1 then 0 else memo[n]
+        }
+        function main () -> int {
+            let var memo : intArray := intArray [3] of -1 in
+                (memo[2] := 5; lookup(2, memo) * 10 + lookup(1, memo) + 1)
+            end
+        }";
+    let options = LoweringOptions::default();
+    let runs: [(&[Pass], usize); 3] = [
+        (&[], 4),
+        (&[number_values_locally], 4),
+        (&[number_values], 2),
+    ];
+    for &(passes, expected) in runs.iter() {
+        let optimized_program = optimized(program, options, passes);
+        assert_eq!(eval(&optimized_program), Value::Int(51));
+        assert_eq!(loads(&optimized_program), expected);
+    }
+}
+
+#[test]
+fn test_loads_respect_stores_and_calls() {
+    // Bounds checks are left out so that only the loads of a[0] are counted
+    let loads_after = |body: &str| {
+        let program = format!(
+            "type intArray = array of int
+            function f(a : intArray) -> int {{
+                let var x : int := a[0] in {} end
+            }}
+            function main () -> int {{
+                let var a : intArray := intArray [1] of 4 in f(a) * 10 + a[0] end
+            }}",
+            body
+        );
+        let options = LoweringOptions {
+            bounds_checks: false,
+        };
+        let optimized_program = optimized(&program, options, &[number_values]);
+        (loads(&optimized_program), eval(&optimized_program))
+    };
+
+    assert_eq!(loads_after("x + a[0]"), (1, Value::Int(84)));
+    assert_eq!(
+        loads_after("(a[0] := x + 1; x + a[0])"),
+        (2, Value::Int(95))
+    );
+    assert_eq!(
+        loads_after("(print_line_int(x); x + a[0])"),
+        (2, Value::Int(84))
+    );
+    assert_eq!(
+        loads_after("(if x > 0 then (a[0] := 1) else (); x + a[0])"),
+        (2, Value::Int(51))
+    );
+    assert_eq!(
+        loads_after("(if x > 0 then (x := 1) else (); x + a[0])"),
+        (1, Value::Int(54))
+    );
+    // The body and the code after the loop read what the loop test read
+    assert_eq!(
+        loads_after("(while a[0] < 9 do (a[0] := a[0] + 1); x + a[0])"),
+        (2, Value::Int(139))
+    );
+}
@@ -0,0 +1,347 @@
+/*
+ * Value numbering
+ *
+ * Finds instructions that compute a value an instruction before them already
+ * has, and reads the symbol holding it instead. In SSA form a symbol holds
+ * the same value wherever it is read, so the same operation on the same
+ * symbols computes the same value.
+ *
+ * Local value numbering only looks for the earlier instruction in the same
+ * block. Global value numbering, after Briggs, Cooper and Simpson, walks the
+ * dominator tree and also looks in the blocks dominating the block, which
+ * control always passes through first.
+ *
+ * A load also depends on memory, which stores and calls change. Memory is
+ * numbered like a symbol: each store or call makes a new version, and a
+ * block where different versions meet starts with one of its own. Two loads
+ * only have the same value when they read the same version.
+ */
+use crate::common::{InfixOp, Label, Symbol, SymbolGenerator};
+use crate::lir::LIRInstruction;
+use crate::ssa::{assigned, uses_mut, Block, SSAFunction};
+
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum Memory {
+    // As the function found it
+    Entry,
+    // As the store or call at this position in a block left it
+    After(Label, usize),
+    // As different versions met at the start of a block
+    Join(Label),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Expression {
+    IntLit(i64),
+    StringLit(String),
+    Negate(Symbol),
+    BinaryOp(InfixOp, Symbol, Symbol),
+    Load(Symbol, Symbol, Memory),
+    // Phis in the same block with the same sources
+    Phi(Label, Vec<(Label, Symbol)>),
+}
+
+/*
+ * The value an instruction computes, with the operands of commutative
+ * operations in order so that either order finds it. Copies and calls have
+ * none.
+ */
+fn expression(instruction: &LIRInstruction, memory: Memory) -> Option<Expression> {
+    match instruction {
+        LIRInstruction::IntLit { value, .. } => Some(Expression::IntLit(*value)),
+        LIRInstruction::StringLit { value, .. } => Some(Expression::StringLit(value.clone())),
+        LIRInstruction::Negate { value, .. } => Some(Expression::Negate(*value)),
+        LIRInstruction::BinaryOp {
+            left, op, right, ..
+        } => match op {
+            InfixOp::Add | InfixOp::Multiply | InfixOp::And | InfixOp::Or => Some(
+                Expression::BinaryOp(*op, *left.min(right), *left.max(right)),
+            ),
+            InfixOp::Subtract | InfixOp::Divide | InfixOp::Modulo => {
+                Some(Expression::BinaryOp(*op, *left, *right))
+            }
+        },
+        LIRInstruction::LoadFromMemoryAtOffset {
+            location, offset, ..
+        } => Some(Expression::Load(*location, *offset, memory)),
+        _ => None,
+    }
+}
+
+/*
+ * The runtime functions which exit the program, after which what memory
+ * holds doesn't matter
+ */
+fn never_returns(function_name: Label) -> bool {
+    matches!(
+        function_name,
+        Label::IndexOutOfBounds | Label::DivisionByZero | Label::IntegerOverflow | Label::Exit
+    )
+}
+
+/*
+ * The version of memory at the end of a block given the version at its
+ * start, or None if control never leaves it.
+ */
+fn memory_at_end(block: &Block, at_start: Memory) -> Option<Memory> {
+    let mut memory = at_start;
+    for (index, instruction) in block.instructions.iter().enumerate() {
+        match instruction {
+            LIRInstruction::Call { function_name, .. } if never_returns(*function_name) => {
+                return None
+            }
+            LIRInstruction::Call { .. } | LIRInstruction::StoreToMemoryAtOffset { .. } => {
+                memory = Memory::After(block.label, index)
+            }
+            _ => (),
+        }
+    }
+    Some(memory)
+}
+
+/*
+ * The version of memory at the start of each block, found by following
+ * control from the entry until nothing changes. A block takes the version
+ * every predecessor found so far leaves it, and once two differ it has its
+ * own for good, so only the blocks with their own versions can keep
+ * changing the others.
+ */
+fn memory_at_blocks(function: &SSAFunction) -> HashMap<Label, Memory> {
+    let predecessors = function.predecessors();
+    let indices = function.block_indices();
+    let mut at_start = HashMap::new();
+    at_start.insert(function.blocks[0].label, Memory::Entry);
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for block in function.blocks.iter().skip(1) {
+            let mut incoming = predecessors[&block.label].iter().filter_map(|predecessor| {
+                let predecessor = &function.blocks[indices[predecessor]];
+                at_start
+                    .get(&predecessor.label)
+                    .and_then(|memory| memory_at_end(predecessor, *memory))
+            });
+            let first = match incoming.next() {
+                Some(first) => first,
+                None => continue,
+            };
+            let memory = match at_start.get(&block.label) {
+                Some(Memory::Join(label)) if *label == block.label => continue,
+                _ if incoming.all(|memory| memory == first) => first,
+                _ => Memory::Join(block.label),
+            };
+            if at_start.insert(block.label, memory) != Some(memory) {
+                changed = true;
+            }
+        }
+    }
+
+    at_start
+}
+
+/*
+ * Number values locally
+ *
+ * A Pass which removes the instructions computing a value already computed
+ * in their block. It exists only for the tests to compare global numbering
+ * against, so it isn't built into the compiler: -O numbers values globally,
+ * which finds all of these too.
+ */
+#[cfg(test)]
+pub fn number_values_locally(function: &mut SSAFunction, _symbol_gen: &mut SymbolGenerator) {
+    let mut numbering = ValueNumbering::new(function, false);
+    let labels: Vec<Label> = function.blocks.iter().map(|block| block.label).collect();
+    for label in labels {
+        numbering.number_block(function, label);
+    }
+    numbering.replace(function);
+}
+
+/*
+ * Number values
+ *
+ * A Pass which removes the instructions computing a value already computed
+ * in their block or in a block dominating it.
+ */
+pub fn number_values(function: &mut SSAFunction, _symbol_gen: &mut SymbolGenerator) {
+    let mut numbering = ValueNumbering::new(function, true);
+    let entry = function.blocks[0].label;
+    numbering.number_block(function, entry);
+    numbering.replace(function);
+}
+
+struct ValueNumbering {
+    global: bool,
+    tree: HashMap<Label, Vec<Label>>,
+    indices: HashMap<Label, usize>,
+    memory: HashMap<Label, Memory>,
+    // The symbol holding each value computed in the blocks on the way down
+    values: HashMap<Expression, Symbol>,
+    // The symbol to read in place of each symbol whose instruction was removed
+    replacements: HashMap<Symbol, Symbol>,
+}
+
+impl ValueNumbering {
+    fn new(function: &SSAFunction, global: bool) -> Self {
+        ValueNumbering {
+            global,
+            tree: if global {
+                function.dominator_tree()
+            } else {
+                HashMap::new()
+            },
+            indices: function.block_indices(),
+            memory: memory_at_blocks(function),
+            values: HashMap::new(),
+            replacements: HashMap::new(),
+        }
+    }
+
+    fn replacement(&self, symbol: Symbol) -> Symbol {
+        self.replacements.get(&symbol).copied().unwrap_or(symbol)
+    }
+
+    /*
+     * Have symbol be replaced by the symbol already holding its value, if
+     * there is one, returning whether it was. The collector has to find a
+     * pointer wherever it is, so a pointer is never replaced by a symbol
+     * which isn't one, or the other way around.
+     */
+    fn replace_with(
+        &mut self,
+        symbol: Symbol,
+        existing: Option<Symbol>,
+        pointers: &HashSet<Symbol>,
+    ) -> bool {
+        match existing {
+            Some(existing) if pointers.contains(&existing) == pointers.contains(&symbol) => {
+                self.replacements.insert(symbol, existing);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /*
+     * Whether symbol, holding the value of key, can be replaced by the symbol
+     * which already does. Otherwise symbol holds the value from now on, and
+     * the key goes in added.
+     */
+    fn number(
+        &mut self,
+        symbol: Symbol,
+        key: Expression,
+        pointers: &HashSet<Symbol>,
+        added: &mut Vec<Expression>,
+    ) -> bool {
+        let existing = self.values.get(&key).copied();
+        if self.replace_with(symbol, existing, pointers) {
+            return true;
+        }
+        if existing.is_none() {
+            self.values.insert(key.clone(), symbol);
+            added.push(key);
+        }
+        false
+    }
+
+    /*
+     * Number the phis and instructions of a block, and then the blocks it
+     * dominates if numbering globally.
+     */
+    fn number_block(&mut self, function: &mut SSAFunction, label: Label) {
+        let SSAFunction {
+            blocks, pointers, ..
+        } = function;
+        let block = &mut blocks[self.indices[&label]];
+        let mut added = vec![];
+
+        let mut phis = vec![];
+        for mut phi in block.phis.drain(..) {
+            for (_, source) in phi.sources.iter_mut() {
+                *source = self.replacement(*source);
+            }
+            let mut sources = phi.sources.clone();
+            sources.sort();
+            if !self.number(
+                phi.assign_to,
+                Expression::Phi(label, sources),
+                pointers,
+                &mut added,
+            ) {
+                phis.push(phi);
+            }
+        }
+        block.phis = phis;
+
+        // Control never reaches a block without a version, as each way in
+        // calls a function that never returns
+        let mut memory = self
+            .memory
+            .get(&label)
+            .copied()
+            .unwrap_or(Memory::Join(label));
+        let mut instructions = vec![];
+        for (index, mut instruction) in block.instructions.drain(..).enumerate() {
+            for used in uses_mut(&mut instruction) {
+                *used = self.replacement(*used);
+            }
+            match &instruction {
+                // A copy has the value it copies
+                LIRInstruction::Assign { assign_to, id } => {
+                    if self.replace_with(*assign_to, Some(*id), pointers) {
+                        continue;
+                    }
+                }
+                LIRInstruction::StoreToMemoryAtOffset { .. } | LIRInstruction::Call { .. } => {
+                    memory = Memory::After(label, index)
+                }
+                _ => {
+                    if let (Some(key), Some(assign_to)) =
+                        (expression(&instruction, memory), assigned(&instruction))
+                    {
+                        if self.number(assign_to, key, pointers, &mut added) {
+                            continue;
+                        }
+                    }
+                }
+            }
+            instructions.push(instruction);
+        }
+        block.instructions = instructions;
+
+        if self.global {
+            for child in self.tree[&label].clone() {
+                self.number_block(function, child);
+            }
+        }
+
+        for key in added {
+            self.values.remove(&key);
+        }
+    }
+
+    /*
+     * Replace the reads numbering came to before the symbols they read were
+     * found redundant: phi sources along edges back up the dominator tree,
+     * and, numbering locally, reads in other blocks.
+     */
+    fn replace(&self, function: &mut SSAFunction) {
+        for block in function.blocks.iter_mut() {
+            for phi in block.phis.iter_mut() {
+                for (_, source) in phi.sources.iter_mut() {
+                    *source = self.replacement(*source);
+                }
+            }
+            for instruction in block.instructions.iter_mut() {
+                for used in uses_mut(instruction) {
+                    *used = self.replacement(*used);
+                }
+            }
+        }
+        function.return_symbol = self.replacement(function.return_symbol);
+    }
+}
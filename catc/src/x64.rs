@@ -6,6 +6,7 @@
 #![allow(unused_macros)]
 
 use crate::common::Label;
+use crate::target::Target;
 
 use std::collections::HashMap;
 
@@ -15,7 +16,7 @@ use serde::{Deserialize, Serialize};
  * Note that these registers are only those which are 64-bit
  * Cat only contains 64 bit numbers (and pointers)
  */
-#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum X64Register {
     Rax, // Not Saved - Return
     Rbx, // Saved
@@ -48,6 +49,8 @@ pub enum X64opCode {
 
     IMulq,
     IDivq,
+    // Sign extend %rax into %rdx:%rax, for idivq
+    Cqto,
     Neg,
     Push,
     Pop,
@@ -60,6 +63,7 @@ pub enum X64opCode {
     Jge,
     Jl,
     Jle,
+    Jo,
 
     Ret,
     Nop,
@@ -114,6 +118,7 @@ pub struct X64Program {
     pub main_function: X64Function,
     pub other_functions: HashMap<Label, X64Function>,
     pub string_literals: HashMap<Label, String>,
+    pub target: Target,
 }
 
 /* Helpful Macros */
@@ -138,23 +143,84 @@ macro_rules! ret {
 /* Converting to assembly text file */
 use std::fmt;
 
+/*
+ * Escape a string for the body of a .string directive. Quotes and
+ * backslashes are escaped, and anything that isn't printable ASCII is written
+ * as an octal escape, one per byte.
+ */
+pub fn escape_string(value: &str) -> String {
+    let mut escaped = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'"' => escaped.push_str("\\\""),
+            b'\\' => escaped.push_str("\\\\"),
+            b'\n' => escaped.push_str("\\n"),
+            b'\t' => escaped.push_str("\\t"),
+            b' '..=b'~' => escaped.push(byte as char),
+            _ => escaped.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    escaped
+}
+
 impl fmt::Display for X64Program {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        // Basic main only assembly generation:
-        let mut program: String = format!(".globl _main\n_main:\n{}\n", self.main_function);
+        let target = self.target;
+        let mut program = String::from("\t.text\n");
+
+        // Only main is visible outside the file
+        let main = target.symbol(Label::Main);
+        program.push_str(&format!("\t.globl\t{}\n", main));
+        program.push_str(&function_assembly(target, main, &self.main_function));
 
         for (k, v) in self.other_functions.iter() {
-            program.push_str(format!("{}:\n{}", k, v).as_str());
+            program.push_str(&function_assembly(target, target.symbol(*k), v));
         }
 
+        // String literals get the same header as strings on the heap (next
+        // object, mark, pointer map, length), marked pinned so the garbage
+        // collector leaves them alone, and never written to.
+        if !self.string_literals.is_empty() {
+            program.push_str(&format!("{}\n", target.string_section()));
+        }
         for (k, v) in self.string_literals.iter() {
-            program.push_str(format!("{}:\t.string \"{}\"\n", k, v).as_str());
+            program.push_str("\t.p2align 3\n\t.quad 0, 2, 0, 0\n");
+            program.push_str(
+                format!("{}:\t.string \"{}\"\n", target.symbol(*k), escape_string(v)).as_str(),
+            );
+        }
+
+        // Nothing needs an executable stack
+        if target.is_elf() {
+            program.push_str("\t.section\t.note.GNU-stack,\"\",@progbits\n");
         }
 
         write!(f, "{}", program)
     }
 }
 
+/*
+ * A function's label and body, with the type and size of its symbol on ELF
+ * targets.
+ */
+fn function_assembly(target: Target, name: String, function: &X64Function) -> String {
+    let mut assembly = String::new();
+    if target.is_elf() {
+        assembly.push_str(&format!("\t.type\t{}, @function\n", name));
+    }
+    assembly.push_str(&format!("{}:\n{}", name, ForTarget(function, target)));
+    if target.is_elf() {
+        assembly.push_str(&format!("\t.size\t{}, .-{}\n", name, name));
+    }
+    assembly
+}
+
+/*
+ * A piece of assembly written for a target, which decides how its labels are
+ * spelled. Display on the piece itself writes it for the default target.
+ */
+pub struct ForTarget<'a, T>(pub &'a T, pub Target);
+
 impl fmt::Display for X64Register {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let name = match self {
@@ -187,6 +253,7 @@ impl fmt::Display for X64opCode {
             X64opCode::Sub => "sub",
             X64opCode::IMulq => "imulq",
             X64opCode::IDivq => "idivq",
+            X64opCode::Cqto => "cqto",
             X64opCode::Or => "or",
             X64opCode::And => "and",
             X64opCode::Movq => "movq",
@@ -202,6 +269,7 @@ impl fmt::Display for X64opCode {
             X64opCode::Jge => "jge",
             X64opCode::Jl => "jl",
             X64opCode::Jle => "jle",
+            X64opCode::Jo => "jo",
             X64opCode::Lea => "lea",
             X64opCode::Nop => "nop",
             X64opCode::Cmp => "cmp",
@@ -211,64 +279,81 @@ impl fmt::Display for X64opCode {
     }
 }
 
-impl fmt::Display for Operand {
+impl fmt::Display for ForTarget<'_, Operand> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Operand::Immediate(i) => write!(f, "${}", i),
+        let ForTarget(operand, target) = *self;
+        match operand {
+            Operand::Immediate(i) => write!(f, "${}", ForTarget(i, target)),
             Operand::Register(r) => write!(f, "{}", r),
-            Operand::MemoryImm(v) => write!(f, "{}", v),
+            Operand::MemoryImm(v) => write!(f, "{}", ForTarget(v, target)),
             Operand::MemoryReg(r) => write!(f, "({})", r),
-            Operand::MemoryOffset(offset, r) => write!(f, "{}({})", offset, r),
-            Operand::MemoryScaledIndexed(offset, r, scale, index) => {
-                write!(f, "{}({}, {}, {})", offset, r, index, scale)
-            }
+            Operand::MemoryOffset(offset, r) => write!(f, "{}({})", ForTarget(offset, target), r),
+            Operand::MemoryScaledIndexed(offset, r, scale, index) => write!(
+                f,
+                "{}({}, {}, {})",
+                ForTarget(offset, target),
+                r,
+                index,
+                scale
+            ),
         }
     }
 }
 
-impl fmt::Display for X64Value {
+impl fmt::Display for ForTarget<'_, X64Value> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            X64Value::LabelRef(l) => write!(f, "{}", l),
-            X64Value::Absolute(v) => write!(f, "{}", v),
+        match *self {
+            ForTarget(X64Value::LabelRef(l), target) => write!(f, "{}", target.symbol(*l)),
+            ForTarget(X64Value::Absolute(v), _) => write!(f, "{}", v),
         }
     }
 }
 
-impl fmt::Display for Operands {
+impl fmt::Display for ForTarget<'_, Operands> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
+        let ForTarget(operands, target) = *self;
+        match operands {
             Operands::Zero => write!(f, ""),
-            Operands::One(o) => write!(f, "{}", o),
-            Operands::Two(a, b) => write!(f, "{}, {}", a, b),
+            Operands::One(o) => write!(f, "{}", ForTarget(o, target)),
+            Operands::Two(a, b) => write!(f, "{}, {}", ForTarget(a, target), ForTarget(b, target)),
         }
     }
 }
 
-impl fmt::Display for X64Instruction {
+impl fmt::Display for ForTarget<'_, X64Instruction> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}\t{}", self.op_code, self.args)
+        let ForTarget(instruction, target) = *self;
+        write!(
+            f,
+            "{}\t{}",
+            instruction.op_code,
+            ForTarget(&instruction.args, target)
+        )
     }
 }
 
-impl fmt::Display for X64Assembly {
+impl fmt::Display for ForTarget<'_, X64Assembly> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            X64Assembly::Label(l) => write!(f, "{}:", l),
-            X64Assembly::Instruction(i) => write!(f, "\t{}", i),
+        match *self {
+            ForTarget(X64Assembly::Label(l), target) => write!(f, "{}:", target.symbol(*l)),
+            ForTarget(X64Assembly::Instruction(i), target) => {
+                write!(f, "\t{}", ForTarget(i, target))
+            }
         }
     }
 }
 
-impl fmt::Display for X64Function {
+impl fmt::Display for ForTarget<'_, X64Function> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ForTarget(function, target) = *self;
         write!(
             f,
             "{}",
-            self.instruction_listing
+            function
+                .instruction_listing
                 .iter()
                 .map(|assem| {
-                    let mut assem = assem.to_string();
+                    let mut assem = ForTarget(assem, target).to_string();
                     assem.push_str("\n");
                     assem
                 })
@@ -276,3 +361,22 @@ impl fmt::Display for X64Function {
         )
     }
 }
+
+macro_rules! display_for_default_target {
+    ($($t:ty),*) => {
+        $(impl fmt::Display for $t {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                ForTarget(self, Target::default()).fmt(f)
+            }
+        })*
+    };
+}
+
+display_for_default_target!(
+    X64Value,
+    Operand,
+    Operands,
+    X64Instruction,
+    X64Assembly,
+    X64Function
+);
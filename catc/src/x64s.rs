@@ -1,10 +1,10 @@
-use crate::x64::{Operand, X64Register, X64Value, X64opCode};
+use crate::x64::{escape_string, Operand, X64Register, X64Value, X64opCode};
 
 use serde::{Deserialize, Serialize};
 
 use crate::common::{Label, Symbol};
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use std::convert::TryInto;
 
@@ -42,6 +42,7 @@ pub enum X64SAssembly {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct X64SFunction {
     pub body: Vec<X64SAssembly>,
+    pub pointers: HashSet<Symbol>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -82,7 +83,7 @@ impl fmt::Display for X64SProgram {
         }
 
         for (k, v) in self.string_literals.iter() {
-            program.push_str(format!("{}:\t.string \"{}\"\n", k, v).as_str());
+            program.push_str(format!("{}:\t.string \"{}\"\n", k, escape_string(v)).as_str());
         }
 
         write!(f, "{}", program)